@@ -0,0 +1,423 @@
+//! Authentication of the MCP clients of the HTTP transports.
+//!
+//! A client sends `Authorization: Bearer <token>`, where the token is either one of the static API
+//! keys or a JWT signed by one of the keys of the JWKS file. The API keys are only known by their
+//! SHA-256 hashes, so the configuration holds no secrets. The identity of the client is added to
+//! the extensions of the request, where the tools pick it up for their logs.
+
+use anyhow::{Context, anyhow, bail};
+use axum::{
+    Router,
+    extract::{Request, State},
+    http::{HeaderValue, StatusCode, header},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, jwk::JwkSet};
+use rand::{Rng, distr::Alphanumeric};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, fmt, path::Path, sync::Arc};
+use tracing::{debug, info, warn};
+
+/// Prefix of the generated API keys, which makes them easy to spot in leaked files
+const API_KEY_PREFIX: &str = "cas_";
+/// Number of random characters of a generated API key
+const API_KEY_LENGTH: usize = 40;
+
+/// How a client authenticated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthMethod {
+    ApiKey,
+    Jwt,
+}
+impl fmt::Display for AuthMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthMethod::ApiKey => write!(f, "api_key"),
+            AuthMethod::Jwt => write!(f, "jwt"),
+        }
+    }
+}
+
+/// The authenticated identity of a client: the name of its API key, or the subject of its JWT
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    pub subject: String,
+    pub method: AuthMethod,
+}
+impl fmt::Display for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.method, self.subject)
+    }
+}
+
+/// The accepted JWTs
+#[derive(Debug, Clone, Default)]
+pub struct JwtConfig {
+    /// Accepted `iss` claims. Not checked if empty
+    pub issuers: Vec<String>,
+    /// Accepted `aud` claims. Not checked if empty
+    pub audiences: Vec<String>,
+}
+
+/// A key of the JWKS file, ready to verify signatures
+struct VerificationKey {
+    key_id: Option<String>,
+    /// The algorithm the key is restricted to, if the JWK sets one
+    algorithm: Option<Algorithm>,
+    key: DecodingKey,
+}
+
+#[derive(Debug, Deserialize)]
+struct Claims {
+    sub: Option<String>,
+}
+
+/// Authenticates the bearer tokens of the clients
+#[derive(Clone)]
+pub struct Authenticator {
+    /// Names of the API keys by the SHA-256 hash of the key
+    api_keys: Arc<HashMap<[u8; 32], String>>,
+    jwks: Arc<Vec<VerificationKey>>,
+    jwt_config: JwtConfig,
+}
+
+impl Authenticator {
+    /// Build the authenticator from API key entries and an optional JWKS file
+    ///
+    /// Each API key entry is `<name>:<hex-encoded SHA-256 hash of the key>`.
+    pub fn new(
+        api_key_entries: &[String],
+        jwks_file: Option<&Path>,
+        jwt_config: JwtConfig,
+    ) -> anyhow::Result<Self> {
+        let mut api_keys = HashMap::new();
+        for entry in api_key_entries {
+            let (name, hash) = parse_api_key_entry(entry)?;
+            if let Some(other) = api_keys.insert(hash, name.clone()) {
+                bail!("The API keys `{other}` and `{name}` have the same hash");
+            }
+        }
+
+        let jwks = match jwks_file {
+            Some(jwks_file) => load_jwks(jwks_file)?,
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            api_keys: Arc::new(api_keys),
+            jwks: Arc::new(jwks),
+            jwt_config,
+        })
+    }
+
+    pub fn api_key_count(&self) -> usize {
+        self.api_keys.len()
+    }
+
+    pub fn jwk_count(&self) -> usize {
+        self.jwks.len()
+    }
+
+    /// Authenticate a bearer token, returning why it was rejected otherwise
+    pub fn authenticate(&self, token: &str) -> Result<Identity, String> {
+        if let Some(name) = self.api_keys.get(&hash_api_key(token)) {
+            return Ok(Identity {
+                subject: name.clone(),
+                method: AuthMethod::ApiKey,
+            });
+        }
+
+        // a JWT has three dot-separated parts, anything else is an unknown API key
+        if self.jwks.is_empty() || token.split('.').count() != 3 {
+            return Err("Invalid API key".to_string());
+        }
+        self.validate_jwt(token)
+    }
+
+    fn validate_jwt(&self, token: &str) -> Result<Identity, String> {
+        let header =
+            jsonwebtoken::decode_header(token).map_err(|e| format!("Invalid JWT header: {e}"))?;
+
+        let verification_key = match &header.kid {
+            Some(kid) => self
+                .jwks
+                .iter()
+                .find(|key| key.key_id.as_deref() == Some(kid.as_str()))
+                .ok_or_else(|| format!("Unknown JWT key id `{kid}`"))?,
+            None => match self.jwks.as_slice() {
+                [key] => key,
+                _ => return Err("The JWT has no key id".to_string()),
+            },
+        };
+
+        // never let the token pick an algorithm the key is not meant for
+        if verification_key
+            .algorithm
+            .is_some_and(|algorithm| algorithm != header.alg)
+            || verification_key.key.family() != header.alg.family()
+        {
+            return Err(format!(
+                "The JWT algorithm {:?} does not match its key",
+                header.alg
+            ));
+        }
+
+        let mut validation = Validation::new(header.alg);
+        if !self.jwt_config.issuers.is_empty() {
+            validation.set_issuer(&self.jwt_config.issuers);
+        }
+        if self.jwt_config.audiences.is_empty() {
+            validation.validate_aud = false;
+        } else {
+            validation.set_audience(&self.jwt_config.audiences);
+        }
+
+        let claims = jsonwebtoken::decode::<Claims>(token, &verification_key.key, &validation)
+            .map_err(|e| format!("Invalid JWT: {e}"))?
+            .claims;
+        let subject = claims
+            .sub
+            .ok_or_else(|| "The JWT has no `sub` claim".to_string())?;
+
+        Ok(Identity {
+            subject,
+            method: AuthMethod::Jwt,
+        })
+    }
+}
+
+impl fmt::Debug for Authenticator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Authenticator")
+            .field("api_keys", &self.api_keys.values().collect::<Vec<_>>())
+            .field("jwks", &self.jwks.len())
+            .field("jwt_config", &self.jwt_config)
+            .finish()
+    }
+}
+
+/// Require authentication on all routes of the router, if an authenticator is configured
+///
+/// Only applies to the routes already in the router, so merge the public ones afterwards.
+pub fn protect(router: Router, authenticator: Option<Authenticator>) -> Router {
+    match authenticator {
+        Some(authenticator) => {
+            router.route_layer(middleware::from_fn_with_state(authenticator, authenticate))
+        }
+        None => router,
+    }
+}
+
+/// Middleware rejecting the requests without a valid bearer token with `401 Unauthorized`, and
+/// adding the identity of the client to the extensions of the others
+async fn authenticate(
+    State(authenticator): State<Authenticator>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim);
+
+    let result = match token {
+        Some(token) => authenticator.authenticate(token),
+        None => Err("Missing bearer token".to_string()),
+    };
+    match result {
+        Ok(identity) => {
+            debug!(
+                "Authenticated {} for {} {}",
+                identity,
+                request.method(),
+                request.uri().path()
+            );
+            request.extensions_mut().insert(identity);
+            next.run(request).await
+        }
+        Err(error_message) => {
+            warn!(
+                "Rejected {} {}: {}",
+                request.method(),
+                request.uri().path(),
+                error_message
+            );
+            let mut response = (StatusCode::UNAUTHORIZED, error_message).into_response();
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+            response
+        }
+    }
+}
+
+/// Generate a random API key, returning it with its entry for the API key configuration
+pub fn generate_api_key(name: &str) -> anyhow::Result<(String, String)> {
+    if name.is_empty() || name.contains([':', ',']) || name.trim() != name {
+        bail!("The name of an API key must be non-empty, without `:`, `,` or surrounding spaces");
+    }
+
+    let random: String = rand::rng()
+        .sample_iter(&Alphanumeric)
+        .take(API_KEY_LENGTH)
+        .map(char::from)
+        .collect();
+    let api_key = format!("{API_KEY_PREFIX}{random}");
+    let entry = format!("{name}:{}", to_hex(&hash_api_key(&api_key)));
+
+    Ok((api_key, entry))
+}
+
+/// Read the API key entries of a file, one per line, skipping blank lines and `#` comments
+pub fn read_api_key_file(path: &Path) -> anyhow::Result<Vec<String>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read the API key file {}", path.display()))?;
+
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+fn hash_api_key(api_key: &str) -> [u8; 32] {
+    Sha256::digest(api_key.as_bytes()).into()
+}
+
+fn parse_api_key_entry(entry: &str) -> anyhow::Result<(String, [u8; 32])> {
+    let (name, hash) = entry
+        .trim()
+        .split_once(':')
+        .ok_or_else(|| anyhow!("Invalid API key entry `{entry}`, expected `<name>:<sha256>`"))?;
+    if name.is_empty() {
+        bail!("Invalid API key entry `{entry}`, the name is empty");
+    }
+
+    let hash = from_hex(hash).ok_or_else(|| {
+        anyhow!("Invalid API key entry for `{name}`, expected a hex-encoded SHA-256 hash")
+    })?;
+
+    Ok((name.to_string(), hash))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn from_hex(hex: &str) -> Option<[u8; 32]> {
+    // `from_str_radix` accepts a sign
+    if hex.len() != 64 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let mut bytes = [0u8; 32];
+    for (index, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * index..2 * index + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
+fn load_jwks(path: &Path) -> anyhow::Result<Vec<VerificationKey>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read the JWKS file {}", path.display()))?;
+    let jwk_set: JwkSet = serde_json::from_str(&content)
+        .with_context(|| format!("Invalid JWKS file {}", path.display()))?;
+    if jwk_set.keys.is_empty() {
+        bail!("The JWKS file {} has no keys", path.display());
+    }
+
+    let mut keys = Vec::new();
+    for jwk in &jwk_set.keys {
+        let key_id = jwk.common.key_id.clone();
+        let key = DecodingKey::from_jwk(jwk)
+            .with_context(|| format!("Unsupported JWK {:?} in {}", key_id, path.display()))?;
+        let algorithm = jwk
+            .common
+            .key_algorithm
+            .map(Algorithm::try_from)
+            .transpose()
+            .with_context(|| format!("Unsupported JWK {:?} in {}", key_id, path.display()))?;
+        keys.push(VerificationKey {
+            key_id,
+            algorithm,
+            key,
+        });
+    }
+    info!("Loaded {} keys from {}", keys.len(), path.display());
+
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The SHA-256 hash of `secret`
+    const SECRET_HASH: &str = "2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b";
+
+    #[test]
+    fn entries_are_parsed() {
+        let (name, hash) = parse_api_key_entry(&format!("ci:{SECRET_HASH}")).unwrap();
+        assert_eq!(name, "ci");
+        assert_eq!(hash, hash_api_key("secret"));
+
+        // the entries of files and lists may have surrounding spaces, and the hash any case
+        let entry = format!("  ci:{}\n", SECRET_HASH.to_uppercase());
+        assert_eq!(parse_api_key_entry(&entry).unwrap(), (name, hash));
+    }
+
+    #[test]
+    fn generated_entries_are_valid() {
+        let (api_key, entry) = generate_api_key("ci").unwrap();
+        assert!(api_key.starts_with(API_KEY_PREFIX));
+        assert_eq!(
+            parse_api_key_entry(&entry).unwrap(),
+            ("ci".to_string(), hash_api_key(&api_key))
+        );
+    }
+
+    #[test]
+    fn invalid_entries_are_rejected() {
+        let short_hash = &SECRET_HASH[..62];
+        let signed_hash = format!("+f{}", &SECRET_HASH[2..]);
+        let non_hex_hash = format!("zz{}", &SECRET_HASH[2..]);
+        for entry in [
+            SECRET_HASH.to_string(),
+            format!(":{SECRET_HASH}"),
+            "ci:".to_string(),
+            format!("ci:{short_hash}"),
+            format!("ci:{SECRET_HASH}00"),
+            format!("ci:{signed_hash}"),
+            format!("ci:{non_hex_hash}"),
+            format!("ci:{}é", &SECRET_HASH[..62]),
+        ] {
+            assert!(parse_api_key_entry(&entry).is_err(), "{entry}");
+        }
+    }
+
+    #[test]
+    fn api_keys_are_authenticated_by_their_hash() {
+        let authenticator =
+            Authenticator::new(&[format!("ci:{SECRET_HASH}")], None, JwtConfig::default()).unwrap();
+        assert_eq!(
+            authenticator.authenticate("secret"),
+            Ok(Identity {
+                subject: "ci".to_string(),
+                method: AuthMethod::ApiKey,
+            })
+        );
+        assert!(authenticator.authenticate("Secret").is_err());
+        assert!(authenticator.authenticate(SECRET_HASH).is_err());
+    }
+
+    #[test]
+    fn api_keys_with_the_same_hash_are_rejected() {
+        let entries = [format!("ci:{SECRET_HASH}"), format!("cd:{SECRET_HASH}")];
+        assert!(Authenticator::new(&entries, None, JwtConfig::default()).is_err());
+    }
+}
@@ -0,0 +1,193 @@
+//! Search backends.
+//!
+//! A backend is a single searchable store, e.g. a Qdrant collection or a TiDB table. Each index
+//! of the server holds a list of named backends and fuses the results of all of them in hybrid
+//! mode, so adding a new store only requires a new [`SearchBackend`] implementation.
+
+pub mod postgres;
+pub mod qdrant;
+pub mod tantivy;
+pub mod tidb;
+
+use crate::types::{SearchFilter, SearchHit, SearchParams};
+use async_trait::async_trait;
+use rmcp::{ErrorData as McpError, schemars};
+use serde::{
+    Deserialize, Deserializer, Serialize,
+    de::{MapAccess, Visitor},
+};
+use serde_json::{Map, Value};
+
+/// The kind of retrieval a backend performs, which decides the search modes it serves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// Semantic search over embeddings, served in `vector` and `hybrid` mode
+    Vector,
+    /// Full-text search, served in `keyword` and `hybrid` mode
+    Keyword,
+    /// Both, fused by the backend itself and served in every mode. In `vector` and `keyword`
+    /// mode only the matching half of the search runs
+    Hybrid,
+}
+impl std::fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackendKind::Vector => write!(f, "vector"),
+            BackendKind::Keyword => write!(f, "keyword"),
+            BackendKind::Hybrid => write!(f, "hybrid"),
+        }
+    }
+}
+
+/// A description of a configured backend, safe to show to clients
+#[derive(Debug, Clone, Serialize, schemars::JsonSchema)]
+pub struct BackendDescription {
+    #[schemars(description = "The name of the backend, as reported in the search results")]
+    pub name: String,
+    #[schemars(description = "The kind of retrieval the backend performs")]
+    pub kind: BackendKind,
+    #[schemars(description = "What the backend searches, e.g. the collection or table")]
+    pub description: String,
+}
+
+#[async_trait]
+pub trait SearchBackend: std::fmt::Debug + Send + Sync {
+    /// The unique name of the backend, used in the search results and as the fusion weight key
+    fn name(&self) -> &str;
+
+    /// The kind of retrieval the backend performs
+    fn kind(&self) -> BackendKind;
+
+    /// Describe the backend without exposing credentials
+    fn describe(&self) -> BackendDescription;
+
+    /// Check whether a payload filter can be applied by this backend
+    ///
+    /// Returns a message describing the problem if it cannot. Backends do not support payload
+    /// filters unless they override this method.
+    fn validate_filter(&self, _filter: &SearchFilter) -> Result<(), String> {
+        Err(format!(
+            "The `{}` backend does not support payload filters",
+            self.name()
+        ))
+    }
+
+    /// Search the backend for the query
+    ///
+    /// `params.mode` tells a hybrid backend which half of its search to run. The returned hits are ranked best first, with 1-based ranks counting from `params.offset`.
+    async fn search(&self, query: &str, params: &SearchParams) -> Result<Vec<SearchHit>, McpError>;
+
+    /// Check that the backend is reachable, its collection or table exists, and the embedding
+    /// and chat services it uses respond
+    async fn health_check(&self) -> Result<(), McpError>;
+}
+
+/// Select the metadata fields of a document: only the `selected` fields if configured, otherwise all of them
+pub(crate) fn select_metadata(
+    fields: impl IntoIterator<Item = (String, Value)>,
+    selected: Option<&[String]>,
+) -> Map<String, Value> {
+    fields
+        .into_iter()
+        .filter(|(name, _)| selected.is_none_or(|selected| selected.contains(name)))
+        .collect()
+}
+
+/// Render a field value as a plain string, e.g. for ids and titles
+pub(crate) fn field_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        value => Some(value.to_string()),
+    }
+}
+
+/// Render the fields of a document as a human-readable text suitable for LLM processing
+///
+/// Fields with empty values are skipped. Field names are turned into title case, and long
+/// values are put on their own line.
+pub(crate) fn format_document(
+    document_number: usize,
+    fields: impl IntoIterator<Item = (String, String)>,
+) -> String {
+    let mut field_parts = Vec::new();
+
+    // 文档头部
+    field_parts.push(format!("=== Document {} ===", document_number));
+
+    for (column_name, text_value) in fields {
+        if !text_value.trim().is_empty() {
+            // 将字段名转换为更友好的格式
+            let friendly_name = column_name
+                .replace("_", " ")
+                .split_whitespace()
+                .map(|word| {
+                    let mut chars = word.chars();
+                    match chars.next() {
+                        None => String::new(),
+                        Some(first) => first.to_uppercase().collect::<String>() + chars.as_str(),
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+
+            // 根据内容长度决定格式
+            let formatted_field = if text_value.len() > 100 {
+                // 长文本用换行格式
+                format!("{}:\n{}", friendly_name, text_value)
+            } else {
+                // 短文本用同行格式
+                format!("{}: {}", friendly_name, text_value)
+            };
+
+            field_parts.push(formatted_field);
+        }
+    }
+
+    field_parts.join("\n\n")
+}
+
+/// The text of a document rendered by [`format_document`], without the `=== Document N ===`
+/// header numbering it in its page. Other texts are returned as is
+pub(crate) fn document_text(source: &str) -> &str {
+    let Some((number, text)) = source
+        .strip_prefix("=== Document ")
+        .and_then(|rest| rest.split_once(" ==="))
+    else {
+        return source;
+    };
+    if number.is_empty() || !number.bytes().all(|byte| byte.is_ascii_digit()) {
+        return source;
+    }
+    text.strip_prefix("\n\n").unwrap_or(text)
+}
+
+/// The fields of a JSON object in their original order
+///
+/// `serde_json::Map` sorts its keys, which would lose the column order of a row.
+pub(crate) struct OrderedFields(pub Vec<(String, Value)>);
+
+impl<'de> Deserialize<'de> for OrderedFields {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct OrderedFieldsVisitor;
+
+        impl<'de> Visitor<'de> for OrderedFieldsVisitor {
+            type Value = OrderedFields;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a JSON object")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut fields = Vec::with_capacity(map.size_hint().unwrap_or(0));
+                while let Some(field) = map.next_entry()? {
+                    fields.push(field);
+                }
+                Ok(OrderedFields(fields))
+            }
+        }
+
+        deserializer.deserialize_map(OrderedFieldsVisitor)
+    }
+}
@@ -0,0 +1,436 @@
+use super::{BackendDescription, BackendKind, SearchBackend, select_metadata};
+use crate::{
+    QdrantConfig, ServiceConfig, filter, hyde,
+    metrics::{self, Stage},
+    services, telemetry,
+    types::*,
+};
+use async_trait::async_trait;
+use rmcp::{ErrorData as McpError, model::ErrorCode};
+use serde_json::{Value, json};
+use tracing::{debug, error, info, instrument, warn};
+
+/// Name of the Qdrant backend configured on the command line
+pub const DEFAULT_BACKEND_NAME: &str = "qdrant";
+
+/// Vector search in a Qdrant collection, embedding the query, or the hypothetical document
+/// drafted for it, with the embedding service
+#[derive(Debug, Clone)]
+pub struct QdrantBackend {
+    name: String,
+    config: QdrantConfig,
+    embedding_service: ServiceConfig,
+}
+impl QdrantBackend {
+    pub fn new(
+        name: impl Into<String>,
+        config: QdrantConfig,
+        embedding_service: ServiceConfig,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            config,
+            embedding_service,
+        }
+    }
+
+    /// Build a request to the Qdrant server, authenticated with the API key if one is configured
+    fn request(&self, method: reqwest::Method, url: &str) -> reqwest::RequestBuilder {
+        let request = reqwest::Client::new()
+            .request(method, url)
+            .header("Content-Type", "application/json")
+            .headers(telemetry::trace_headers());
+
+        match &self.config.api_key {
+            Some(api_key) => {
+                let auth_info = if api_key.starts_with("Bearer ") {
+                    api_key.clone()
+                } else {
+                    format!("Bearer {api_key}")
+                };
+                request.header("api-key", auth_info)
+            }
+            None => request,
+        }
+    }
+
+    fn collection_url(&self) -> String {
+        format!(
+            "{}/collections/{}",
+            self.config.base_url.trim_end_matches('/'),
+            self.config.collection
+        )
+    }
+
+    #[instrument(skip_all, fields(collection = %self.config.collection))]
+    async fn search_in_qdrant(
+        &self,
+        vector: impl AsRef<[f64]>,
+        params: &SearchParams,
+    ) -> Result<Vec<QdrantSearchHit>, McpError> {
+        let url = format!("{}/points/search", self.collection_url());
+
+        // build params
+        let mut search_params = json!({
+            "vector": vector.as_ref().to_vec(),
+            "limit": params.limit,
+            "offset": params.offset,
+            "with_payload": true,
+            "with_vector": true,
+            "score_threshold": params
+                .score_threshold
+                .unwrap_or(self.config.score_threshold),
+        });
+        if let Some(filter) = &params.filter {
+            search_params["filter"] =
+                filter::to_qdrant_filter(filter, &self.config.filterable_fields).map_err(
+                    |error_message| {
+                        warn!("Invalid filter: {}", error_message);
+                        McpError::invalid_params(error_message, None)
+                    },
+                )?;
+        }
+
+        let response = self
+            .request(reqwest::Method::POST, &url)
+            .json(&search_params)
+            .send()
+            .await
+            .map_err(|e| {
+                let err_msg = format!("Failed to search points: {e}");
+                error!("{}", err_msg);
+                McpError::new(ErrorCode::INTERNAL_ERROR, err_msg, None)
+            })?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let error_message =
+                format!("Failed to send search request to Qdrant server. Status: {status}");
+            error!("{}", error_message);
+            return Err(McpError::new(
+                ErrorCode::INTERNAL_ERROR,
+                error_message,
+                None,
+            ));
+        }
+
+        let json = response.json::<Value>().await.map_err(|e| {
+            let error_message = format!("Failed to search points: {e}");
+            error!("{}", error_message);
+            McpError::new(ErrorCode::INTERNAL_ERROR, error_message, None)
+        })?;
+
+        match json.get("result") {
+            Some(result) => result
+                .as_array()
+                .ok_or_else(|| invalid_response("`result` is not an array"))?
+                .iter()
+                .map(parse_hit)
+                .collect(),
+            None => {
+                debug!(
+                    "Qdrant search response:\n{}",
+                    serde_json::to_string_pretty(&json).unwrap_or_default()
+                );
+
+                let error_message = match json
+                    .get("status")
+                    .and_then(|status| status.get("error"))
+                    .and_then(Value::as_str)
+                {
+                    Some(status_error) => format!("Failed to search points. {status_error}"),
+                    None => "Failed to search points. The response has no `result`".to_string(),
+                };
+                error!("{}", error_message);
+                Err(McpError::new(
+                    ErrorCode::INTERNAL_ERROR,
+                    error_message,
+                    None,
+                ))
+            }
+        }
+    }
+}
+
+/// Parse a point of the search response
+fn parse_hit(point: &Value) -> Result<QdrantSearchHit, McpError> {
+    let id = match point.get("id") {
+        Some(Value::String(id)) => id.clone(),
+        Some(id @ Value::Number(_)) => id.to_string(),
+        _ => return Err(invalid_response("a point has no string or integer `id`")),
+    };
+    let score = point
+        .get("score")
+        .and_then(Value::as_f64)
+        .ok_or_else(|| invalid_response(format!("the point {id} has no numeric `score`")))?;
+    let payload = point
+        .get("payload")
+        .and_then(Value::as_object)
+        .ok_or_else(|| invalid_response(format!("the point {id} has no `payload` object")))?
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.clone()))
+        .collect();
+    // the points of a collection without vectors have none
+    let vector = match point.get("vector") {
+        None | Some(Value::Null) => Vec::new(),
+        Some(vector) => vector
+            .as_array()
+            .and_then(|vector| {
+                vector
+                    .iter()
+                    .map(Value::as_f64)
+                    .collect::<Option<Vec<f64>>>()
+            })
+            .ok_or_else(|| {
+                invalid_response(format!(
+                    "the `vector` of the point {id} is not a list of numbers"
+                ))
+            })?,
+    };
+
+    Ok(QdrantSearchHit {
+        id,
+        score,
+        payload,
+        vector,
+    })
+}
+
+/// The error of a search response that does not have the expected shape
+fn invalid_response(problem: impl std::fmt::Display) -> McpError {
+    let error_message = format!("Invalid search response from the Qdrant server: {problem}");
+    error!("{}", error_message);
+    McpError::internal_error(error_message, None)
+}
+
+#[async_trait]
+impl SearchBackend for QdrantBackend {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn kind(&self) -> BackendKind {
+        BackendKind::Vector
+    }
+
+    fn describe(&self) -> BackendDescription {
+        BackendDescription {
+            name: self.name.clone(),
+            kind: self.kind(),
+            description: format!("Qdrant collection `{}`", self.config.collection),
+        }
+    }
+
+    fn validate_filter(&self, filter: &SearchFilter) -> Result<(), String> {
+        filter::to_qdrant_filter(filter, &self.config.filterable_fields).map(|_| ())
+    }
+
+    async fn search(&self, query: &str, params: &SearchParams) -> Result<Vec<SearchHit>, McpError> {
+        info!("Starting vector search in {} ...", self.name);
+
+        // compute the embedding of the query
+        let embedding = hyde::embed_query(
+            &self.embedding_service,
+            params.hypothetical_document.as_ref(),
+            query,
+            params.mode,
+            &self.name,
+        )
+        .await?;
+
+        // search in qdrant
+        info!("Searching in Qdrant...");
+        let hits = metrics::time_stage(
+            params.mode,
+            &self.name,
+            Stage::Qdrant,
+            self.search_in_qdrant(embedding, params),
+        )
+        .await?;
+
+        if !hits.is_empty() {
+            let payload_source = &self.config.payload_source;
+            info!(
+                "Extracting the payload ({}) of the vector search results...",
+                payload_source
+            );
+            let mut output = Vec::new();
+            for mut hit in hits {
+                // a point without a source is skipped rather than failing the whole search
+                let source = match hit.payload.remove(payload_source) {
+                    Some(Value::String(source)) => source,
+                    Some(_) => {
+                        warn!(
+                            "Skipping the point {}, its payload field `{}` is not a string",
+                            hit.id, payload_source
+                        );
+                        continue;
+                    }
+                    None => {
+                        warn!(
+                            "Skipping the point {}, it has no payload field `{}`",
+                            hit.id, payload_source
+                        );
+                        continue;
+                    }
+                };
+                let title = self
+                    .config
+                    .title_field
+                    .as_ref()
+                    .and_then(|field| hit.payload.remove(field))
+                    .and_then(|title| title.as_str().map(|title| title.to_string()));
+                output.push(SearchHit {
+                    backend: self.name.clone(),
+                    rank: params.offset as usize + output.len() + 1,
+                    score: Some(hit.score),
+                    id: Some(hit.id),
+                    title,
+                    source,
+                    metadata: select_metadata(hit.payload, self.config.metadata_fields.as_deref()),
+                    vector: (!hit.vector.is_empty()).then_some(hit.vector),
+                });
+            }
+
+            info!("Vector search done! 🎉");
+
+            debug!("vector search results:\n{:#?}", &output);
+
+            Ok(output)
+        } else {
+            let error_message = "No vector search results found in Qdrant";
+            warn!("{}", error_message);
+            Ok(vec![])
+        }
+    }
+
+    async fn health_check(&self) -> Result<(), McpError> {
+        let response = self
+            .request(reqwest::Method::GET, &self.collection_url())
+            .send()
+            .await
+            .map_err(|e| {
+                let error_message = format!("Failed to reach the Qdrant server: {e}");
+                error!("{}", error_message);
+                McpError::new(ErrorCode::INTERNAL_ERROR, error_message, None)
+            })?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let error_message = format!(
+                "Failed to get collection `{}` from the Qdrant server. Status: {status}",
+                self.config.collection
+            );
+            error!("{}", error_message);
+            return Err(McpError::new(
+                ErrorCode::INTERNAL_ERROR,
+                error_message,
+                None,
+            ));
+        }
+
+        services::health_check(&self.embedding_service).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, StubServices};
+    use axum::{Json, Router, routing::post};
+
+    #[test]
+    fn points_are_parsed() {
+        let hit = parse_hit(&json!({
+            "id": 7,
+            "score": 0.8,
+            "payload": { "text": "doc" },
+            "vector": [1.0, 0.5],
+        }))
+        .unwrap();
+
+        assert_eq!(hit.id, "7");
+        assert_eq!(hit.score, 0.8);
+        assert_eq!(hit.payload["text"], "doc");
+        assert_eq!(hit.vector, [1.0, 0.5]);
+
+        let hit = parse_hit(&json!({
+            "id": "5c56c793-69f3-4fbf-87e6-c4bf54c28c26",
+            "score": 0.8,
+            "payload": {},
+            "vector": null,
+        }))
+        .unwrap();
+        assert_eq!(hit.id, "5c56c793-69f3-4fbf-87e6-c4bf54c28c26");
+        assert!(hit.vector.is_empty());
+    }
+
+    #[test]
+    fn malformed_points_are_errors() {
+        for (point, field) in [
+            (json!({ "score": 0.8, "payload": {} }), "`id`"),
+            (
+                json!({ "id": 1, "score": "high", "payload": {} }),
+                "`score`",
+            ),
+            (json!({ "id": 1, "score": 0.8 }), "`payload`"),
+            (
+                json!({ "id": 1, "score": 0.8, "payload": {}, "vector": { "text": [1.0] } }),
+                "`vector`",
+            ),
+        ] {
+            let error = parse_hit(&point).unwrap_err();
+            assert!(error.message.contains(field), "{}", error.message);
+        }
+    }
+
+    #[tokio::test]
+    async fn points_without_a_source_are_skipped() {
+        async fn search() -> Json<Value> {
+            Json(json!({
+                "result": [
+                    { "id": 1, "score": 0.9, "payload": { "title": "No text" } },
+                    { "id": 2, "score": 0.8, "payload": { "text": "Rust", "title": "Rust" } },
+                    { "id": 3, "score": 0.7, "payload": { "text": ["not", "a", "string"] } },
+                    { "id": 4, "score": 0.6, "payload": { "text": "Tokio", "lang": "en" } },
+                ],
+                "status": "ok",
+            }))
+        }
+        let address = test_support::serve(
+            Router::new().route("/collections/docs/points/search", post(search)),
+        )
+        .await;
+        let services = StubServices::spawn(|_| vec![1.0, 0.0], |_| String::new()).await;
+        let backend = QdrantBackend::new(
+            "qdrant",
+            QdrantConfig {
+                api_key: None,
+                base_url: format!("http://{address}"),
+                collection: "docs".to_string(),
+                payload_source: "text".to_string(),
+                title_field: Some("title".to_string()),
+                metadata_fields: None,
+                filterable_fields: Vec::new(),
+                score_threshold: 0.5,
+            },
+            services.config(),
+        );
+        let params = SearchParams {
+            mode: RetrievalMode::Vector,
+            limit: 10,
+            offset: 5,
+            score_threshold: None,
+            filter: None,
+            mmr_lambda: None,
+            hypothetical_document: None,
+        };
+
+        let hits = backend.search("rust", &params).await.unwrap();
+        let hits: Vec<(Option<&str>, usize, &str)> = hits
+            .iter()
+            .map(|hit| (hit.id.as_deref(), hit.rank, hit.source.as_str()))
+            .collect();
+        assert_eq!(hits, [(Some("2"), 6, "Rust"), (Some("4"), 7, "Tokio")]);
+    }
+}
@@ -0,0 +1,501 @@
+use super::{
+    BackendDescription, BackendKind, OrderedFields, SearchBackend, field_to_string,
+    format_document, select_metadata,
+};
+use crate::{
+    ServiceConfig, TantivyConfig,
+    metrics::{self, Stage},
+    services,
+    types::*,
+};
+use async_trait::async_trait;
+use rmcp::{ErrorData as McpError, model::ErrorCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tantivy::{
+    IndexReader, ReloadPolicy, TantivyDocument,
+    collector::TopDocs,
+    query::QueryParser,
+    schema::{Field, STORED, STRING, Schema, TEXT, Value as _},
+};
+use tracing::{debug, error, info, instrument, warn};
+
+/// Name of the Tantivy backend configured on the command line
+pub const DEFAULT_BACKEND_NAME: &str = "tantivy";
+/// Index field holding the id of a document
+const ID_FIELD: &str = "id";
+/// Index field holding the full-text indexed text of a document
+const TEXT_FIELD: &str = "text";
+/// Index field holding the original JSON object of a document
+const DOCUMENT_FIELD: &str = "document";
+
+/// The fields of a Tantivy index built by the `index` command
+#[derive(Debug, Clone, Copy)]
+pub struct IndexFields {
+    pub id: Field,
+    pub text: Field,
+    pub document: Field,
+}
+impl IndexFields {
+    /// The schema of the index and its fields
+    pub fn schema() -> (Schema, Self) {
+        let mut builder = Schema::builder();
+        let fields = Self {
+            id: builder.add_text_field(ID_FIELD, STRING | STORED),
+            text: builder.add_text_field(TEXT_FIELD, TEXT),
+            document: builder.add_text_field(DOCUMENT_FIELD, STORED),
+        };
+        (builder.build(), fields)
+    }
+
+    /// Look up the fields in the schema of an existing index
+    pub fn from_schema(schema: &Schema) -> tantivy::Result<Self> {
+        Ok(Self {
+            id: schema.get_field(ID_FIELD)?,
+            text: schema.get_field(TEXT_FIELD)?,
+            document: schema.get_field(DOCUMENT_FIELD)?,
+        })
+    }
+}
+
+/// Information about the indexed documents, stored as the payload of the index commit
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IndexPayload {
+    /// The document field holding the id of a document
+    pub id_field: String,
+    /// The document fields whose text was indexed
+    pub search_fields: Vec<String>,
+}
+
+/// BM25 full-text search in a local Tantivy index
+///
+/// The keywords of the query are extracted with the chat service if one is configured, otherwise
+/// the query is searched as is.
+#[derive(Clone)]
+pub struct TantivyBackend {
+    name: String,
+    config: TantivyConfig,
+    chat_service: Option<ServiceConfig>,
+    fields: IndexFields,
+    reader: IndexReader,
+}
+
+/// A document returned by the search
+struct TantivySearchHit {
+    id: Option<String>,
+    /// The fields of the document, in their original order
+    fields: Vec<(String, Value)>,
+    /// The BM25 relevance score of the document
+    score: f64,
+}
+
+impl TantivyBackend {
+    pub fn new(
+        name: impl Into<String>,
+        config: TantivyConfig,
+        chat_service: Option<ServiceConfig>,
+    ) -> tantivy::Result<Self> {
+        let fields = IndexFields::from_schema(&config.index.schema())?;
+        // pick up the commits of a rerun of the `index` command without a restart
+        let reader = config
+            .index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::OnCommitWithDelay)
+            .try_into()?;
+
+        Ok(Self {
+            name: name.into(),
+            config,
+            chat_service,
+            fields,
+            reader,
+        })
+    }
+
+    #[instrument(skip_all, fields(index_dir = %self.config.index_dir.display()))]
+    async fn search_in_tantivy(
+        &self,
+        query: String,
+        params: SearchParams,
+    ) -> Result<Vec<TantivySearchHit>, McpError> {
+        let backend = self.clone();
+
+        // tantivy searches are blocking, so keep them off the async worker threads
+        tokio::task::spawn_blocking(move || backend.search_in_tantivy_blocking(&query, &params))
+            .await
+            .map_err(|e| {
+                let error_message = format!("Failed to join the Tantivy search task: {e}");
+                error!(error_message);
+                McpError::new(ErrorCode::INTERNAL_ERROR, error_message, None)
+            })?
+    }
+
+    fn search_in_tantivy_blocking(
+        &self,
+        query: &str,
+        params: &SearchParams,
+    ) -> Result<Vec<TantivySearchHit>, McpError> {
+        // Parse leniently, as the query is free text rather than query syntax. The terms are
+        // combined with OR, so that a document matching some of the keywords is still returned.
+        let query_parser = QueryParser::for_index(&self.config.index, vec![self.fields.text]);
+        let (query, errors) = query_parser.parse_query_lenient(query);
+        if !errors.is_empty() {
+            debug!("Ignored invalid parts of the query: {:?}", errors);
+        }
+
+        let searcher = self.reader.searcher();
+        let top_docs = searcher
+            .search(
+                &query,
+                &TopDocs::with_limit(params.limit as usize)
+                    .and_offset(params.offset as usize)
+                    .order_by_score(),
+            )
+            .map_err(|e| {
+                let error_message = format!("Failed to execute search: {e}");
+                error!(error_message);
+                McpError::new(ErrorCode::INTERNAL_ERROR, error_message, None)
+            })?;
+
+        info!("Query returned {} documents", top_docs.len());
+
+        // The documents are sorted by descending score, so dropping the ones below the threshold
+        // after the limit yields the same page as filtering before it.
+        let score_threshold = params
+            .score_threshold
+            .unwrap_or(self.config.score_threshold) as f64;
+
+        let mut hits = Vec::new();
+        for (score, address) in top_docs {
+            let score = score as f64;
+            if score < score_threshold {
+                break;
+            }
+
+            let document: TantivyDocument = searcher.doc(address).map_err(|e| {
+                let error_message = format!("Failed to read the search results: {e}");
+                error!(error_message);
+                McpError::new(ErrorCode::INTERNAL_ERROR, error_message, None)
+            })?;
+            let id = document
+                .get_first(self.fields.id)
+                .and_then(|id| id.as_str())
+                .map(|id| id.to_string());
+            let OrderedFields(fields) = document
+                .get_first(self.fields.document)
+                .and_then(|document| document.as_str())
+                .map(serde_json::from_str)
+                .transpose()
+                .map_err(|e| {
+                    let error_message = format!("Failed to parse the search results: {e}");
+                    error!(error_message);
+                    McpError::new(ErrorCode::INTERNAL_ERROR, error_message, None)
+                })?
+                .unwrap_or(OrderedFields(Vec::new()));
+
+            hits.push(TantivySearchHit { id, fields, score });
+        }
+        debug!(
+            "{} documents left after applying the score threshold {}",
+            hits.len(),
+            score_threshold
+        );
+
+        Ok(hits)
+    }
+
+    fn is_returned(&self, field: &str) -> bool {
+        self.config
+            .return_field
+            .iter()
+            .any(|returned| returned == "*" || returned == field)
+    }
+}
+
+impl std::fmt::Debug for TantivyBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TantivyBackend")
+            .field("name", &self.name)
+            .field("config", &self.config)
+            .field("chat_service", &self.chat_service)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl SearchBackend for TantivyBackend {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn kind(&self) -> BackendKind {
+        BackendKind::Keyword
+    }
+
+    fn describe(&self) -> BackendDescription {
+        BackendDescription {
+            name: self.name.clone(),
+            kind: self.kind(),
+            description: format!("Tantivy index `{}`", self.config.index_dir.display()),
+        }
+    }
+
+    async fn search(&self, query: &str, params: &SearchParams) -> Result<Vec<SearchHit>, McpError> {
+        info!("Starting keyword search in {} ...", self.name);
+
+        // extract keywords from the query
+        let query = match &self.chat_service {
+            Some(chat_service) => {
+                info!("Extracting keywords from the query...");
+                let keywords = metrics::time_stage(
+                    params.mode,
+                    &self.name,
+                    Stage::KeywordExtraction,
+                    services::extract_keywords(chat_service, query),
+                )
+                .await?;
+                debug!("Extracted keywords: {:#?}", keywords);
+                keywords
+            }
+            None => query.to_string(),
+        };
+
+        // search in tantivy
+        info!("Searching in Tantivy...");
+        let hits = metrics::time_stage(
+            params.mode,
+            &self.name,
+            Stage::Tantivy,
+            self.search_in_tantivy(query, params.clone()),
+        )
+        .await?;
+
+        if !hits.is_empty() {
+            info!("Extracting the source of the keyword search results...");
+            let mut output = Vec::new();
+            for (index, hit) in hits.into_iter().enumerate() {
+                let fields: Vec<(String, Value)> = hit
+                    .fields
+                    .into_iter()
+                    .filter(|(name, _)| self.is_returned(name))
+                    .collect();
+                let source = format_document(
+                    index + 1,
+                    fields
+                        .iter()
+                        .filter_map(|(name, value)| Some((name.clone(), field_to_string(value)?))),
+                );
+
+                let mut title = None;
+                let mut metadata = Vec::new();
+                for (name, value) in fields {
+                    if name == self.config.title_field {
+                        title = field_to_string(&value);
+                    } else if name != self.config.payload.id_field
+                        && !self.config.payload.search_fields.contains(&name)
+                    {
+                        metadata.push((name, value));
+                    }
+                }
+
+                output.push(SearchHit {
+                    backend: self.name.clone(),
+                    rank: params.offset as usize + index + 1,
+                    score: Some(hit.score),
+                    id: hit.id,
+                    title,
+                    source,
+                    metadata: select_metadata(metadata, self.config.metadata_fields.as_deref()),
+                    vector: None,
+                });
+            }
+
+            info!("Keyword search done! 🎉");
+
+            debug!("keyword search results:\n{:#?}", &output);
+
+            Ok(output)
+        } else {
+            let error_message = "No keyword search results found in Tantivy";
+            warn!("{}", error_message);
+            Ok(vec![])
+        }
+    }
+
+    async fn health_check(&self) -> Result<(), McpError> {
+        if self.reader.searcher().num_docs() == 0 {
+            let error_message = format!(
+                "The Tantivy index `{}` is empty, build it with the `index` command",
+                self.config.index_dir.display()
+            );
+            error!(error_message);
+            return Err(McpError::new(
+                ErrorCode::INTERNAL_ERROR,
+                error_message,
+                None,
+            ));
+        }
+
+        match &self.chat_service {
+            Some(chat_service) => services::health_check(chat_service).await,
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::{IndexOptions, build_index};
+    use std::path::PathBuf;
+
+    const DOCUMENTS: &str = r#"
+{"id": "1", "title": "Rust ownership", "body": "The borrow checker of Rust enforces memory safety", "lang": "en"}
+{"id": "2", "title": "Async Rust", "body": "Rust futures run on the tokio runtime, the async Rust runtime", "lang": "en"}
+{"id": "3", "title": "Bread", "body": "Baking sourdough bread at home", "lang": "fr"}
+"#;
+
+    const GUIDE: &str = "# Tokio guide\n\nThe tokio runtime schedules the tasks.\n";
+
+    /// An index built by the `index` command in a temporary directory, removed at the end of the
+    /// test
+    struct TestIndex {
+        dir: PathBuf,
+        index: tantivy::Index,
+    }
+    impl TestIndex {
+        fn build(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("cardea-tantivy-{}-{name}", std::process::id()));
+            let docs = dir.join("docs");
+            std::fs::create_dir_all(&docs).unwrap();
+            std::fs::write(docs.join("docs.jsonl"), DOCUMENTS).unwrap();
+            std::fs::write(docs.join("guide.md"), GUIDE).unwrap();
+
+            let count = build_index(&IndexOptions {
+                index_dir: dir.join("index"),
+                paths: vec![docs],
+                id_field: "id".to_string(),
+                search_fields: vec!["title".to_string(), "body".to_string()],
+            })
+            .unwrap();
+            assert_eq!(count, 4);
+
+            let index = tantivy::Index::open_in_dir(dir.join("index")).unwrap();
+            Self { dir, index }
+        }
+
+        fn backend(&self, return_field: &[&str]) -> TantivyBackend {
+            let payload = self.index.load_metas().unwrap().payload.unwrap();
+            TantivyBackend::new(
+                "tantivy",
+                TantivyConfig {
+                    index_dir: self.dir.join("index"),
+                    index: self.index.clone(),
+                    payload: serde_json::from_str(&payload).unwrap(),
+                    return_field: return_field.iter().map(|field| field.to_string()).collect(),
+                    title_field: "title".to_string(),
+                    metadata_fields: None,
+                    score_threshold: 0.0,
+                },
+                None,
+            )
+            .unwrap()
+        }
+    }
+    impl Drop for TestIndex {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn params(limit: u64, offset: u64) -> SearchParams {
+        SearchParams {
+            mode: RetrievalMode::Keyword,
+            limit,
+            offset,
+            score_threshold: None,
+            filter: None,
+            mmr_lambda: None,
+            hypothetical_document: None,
+        }
+    }
+
+    fn ids(hits: &[SearchHit]) -> Vec<&str> {
+        hits.iter().filter_map(|hit| hit.id.as_deref()).collect()
+    }
+
+    #[tokio::test]
+    async fn the_documents_are_ranked_by_bm25() {
+        let index = TestIndex::build("bm25");
+        let backend = index.backend(&["*"]);
+        assert!(backend.health_check().await.is_ok());
+
+        let hits = backend.search("rust", &params(10, 0)).await.unwrap();
+        assert_eq!(ids(&hits), ["2", "1"]);
+        assert!(hits[0].score > hits[1].score);
+        assert_eq!(hits.iter().map(|hit| hit.rank).collect::<Vec<_>>(), [1, 2]);
+
+        let hits = backend.search("rust", &params(1, 1)).await.unwrap();
+        assert_eq!(ids(&hits), ["1"]);
+        assert_eq!(hits[0].rank, 2);
+
+        assert!(
+            backend
+                .search("kubernetes", &params(10, 0))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn the_query_is_parsed_leniently() {
+        let index = TestIndex::build("lenient");
+        let backend = index.backend(&["*"]);
+
+        // the unbalanced parenthesis and quote are not errors, the parts that parse are searched
+        let hits = backend
+            .search("(tokio \"sourdough lang:fr", &params(10, 0))
+            .await
+            .unwrap();
+        let mut titles: Vec<&str> = hits.iter().filter_map(|hit| hit.title.as_deref()).collect();
+        titles.sort();
+        assert_eq!(titles, ["Async Rust", "Tokio guide"]);
+    }
+
+    #[tokio::test]
+    async fn the_returned_fields_make_up_the_results() {
+        let index = TestIndex::build("fields");
+
+        let hits = index
+            .backend(&["*"])
+            .search("borrow", &params(10, 0))
+            .await
+            .unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].title.as_deref(), Some("Rust ownership"));
+        // the id, title and indexed fields are not repeated in the metadata
+        assert_eq!(hits[0].metadata.keys().collect::<Vec<_>>(), ["lang"]);
+        assert_eq!(
+            hits[0].source,
+            "=== Document 1 ===\n\nId: 1\n\nTitle: Rust ownership\n\nBody: The borrow checker of Rust enforces memory safety\n\nLang: en"
+        );
+
+        let hits = index
+            .backend(&["title", "path"])
+            .search("schedules", &params(10, 0))
+            .await
+            .unwrap();
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].id.as_deref().unwrap().ends_with("guide.md"));
+        assert_eq!(hits[0].title.as_deref(), Some("Tokio guide"));
+        assert!(
+            hits[0].metadata["path"]
+                .as_str()
+                .unwrap()
+                .ends_with("guide.md")
+        );
+        assert!(!hits[0].source.contains("schedules"));
+    }
+}
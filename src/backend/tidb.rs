@@ -0,0 +1,411 @@
+use super::{
+    BackendDescription, BackendKind, SearchBackend, field_to_string, format_document,
+    select_metadata,
+};
+use crate::{
+    ServiceConfig, TiDBConfig,
+    metrics::{self, Stage},
+    services,
+    sql::quote_mysql_identifier,
+    types::*,
+};
+use async_trait::async_trait;
+use mysql::{PooledConn, params, prelude::*};
+use rmcp::{ErrorData as McpError, model::ErrorCode};
+use serde_json::{Map, Value, json};
+use tracing::{debug, error, info, instrument, warn};
+
+/// Name of the TiDB backend configured on the command line
+pub const DEFAULT_BACKEND_NAME: &str = "tidb";
+/// Alias of the `fts_match_word` relevance score column added to the TiDB search results
+const TIDB_SCORE_COLUMN: &str = "_fts_score";
+
+/// Full-text search in a TiDB table, extracting the keywords of the query with the chat service
+#[derive(Debug, Clone)]
+pub struct TidbBackend {
+    name: String,
+    config: TiDBConfig,
+    chat_service: ServiceConfig,
+}
+impl TidbBackend {
+    pub fn new(name: impl Into<String>, config: TiDBConfig, chat_service: ServiceConfig) -> Self {
+        Self {
+            name: name.into(),
+            config,
+            chat_service,
+        }
+    }
+
+    /// Search in TiDB using the keywords
+    ///
+    /// # Arguments
+    ///
+    /// * `keywords` - The keywords to search for. The keywords should be separated by spaces.
+    ///
+    /// # Returns
+    ///
+    /// A string containing the search results
+    #[instrument(skip_all, fields(table = %self.config.table_name))]
+    async fn search_in_tidb(
+        &self,
+        keywords: impl AsRef<str>,
+        params: SearchParams,
+    ) -> Result<Vec<TidbSearchHit>, McpError> {
+        let tidb_config = self.config.clone();
+        let keywords = keywords.as_ref().to_string();
+
+        // the mysql client is blocking, so keep it off the async worker threads
+        tokio::task::spawn_blocking(move || {
+            Self::search_in_tidb_blocking(&tidb_config, &keywords, params)
+        })
+        .await
+        .map_err(|e| {
+            let error_message = format!("Failed to join the TiDB search task: {e}");
+            error!(error_message);
+            McpError::new(ErrorCode::INTERNAL_ERROR, error_message, None)
+        })?
+    }
+
+    /// Get a connection from the pool, and check that the server answers and the table exists
+    fn connect(tidb_config: &TiDBConfig) -> Result<PooledConn, McpError> {
+        // get connection
+        debug!("Getting connection to TiDB Cloud...");
+        let mut conn = tidb_config.pool.get_conn().map_err(|e| {
+            let error_message = format!("Failed to get connection: {e}");
+
+            error!(error_message);
+
+            McpError::new(ErrorCode::INTERNAL_ERROR, error_message, None)
+        })?;
+
+        // test connection
+        debug!("Testing connection...");
+        let version: String = match conn.query_first("SELECT VERSION()").map_err(|e| {
+            let error_message = format!("Failed to query version: {e}");
+
+            error!(error_message);
+
+            McpError::new(ErrorCode::INTERNAL_ERROR, error_message, None)
+        })? {
+            Some(version) => version,
+            None => {
+                let error_message = "Failed to query version";
+
+                error!(error_message);
+
+                return Err(McpError::new(
+                    ErrorCode::INTERNAL_ERROR,
+                    error_message,
+                    None,
+                ));
+            }
+        };
+        debug!("Connected to TiDB Cloud! Version: {}", version);
+
+        // check if table exists
+        debug!("Checking if table exists...");
+        let table_exists: i32 = conn
+            .exec_first(
+                "SELECT COUNT(*) FROM information_schema.tables
+                WHERE table_schema = ? AND table_name = ?",
+                (&tidb_config.database, &tidb_config.table_name),
+            )
+            .map_err(|e| {
+                let error_message = format!("Failed to check table: {e}");
+
+                error!(error_message);
+
+                McpError::new(ErrorCode::INTERNAL_ERROR, error_message, None)
+            })?
+            .unwrap_or(0);
+
+        if table_exists == 0 {
+            let error_message = format!(
+                "Not found table `{}` in database `{}`",
+                tidb_config.table_name, tidb_config.database
+            );
+
+            error!(error_message);
+
+            return Err(McpError::new(
+                ErrorCode::INTERNAL_ERROR,
+                error_message,
+                None,
+            ));
+        }
+
+        Ok(conn)
+    }
+
+    fn search_in_tidb_blocking(
+        tidb_config: &TiDBConfig,
+        keywords: &str,
+        params: SearchParams,
+    ) -> Result<Vec<TidbSearchHit>, McpError> {
+        let mut conn = Self::connect(tidb_config)?;
+
+        // execute full-text search
+        debug!(
+            "\nExecuting full-text search in table {} for '{}'...",
+            tidb_config.table_name, keywords
+        );
+        debug!(
+            "Search field: {}, return fields: {:?}",
+            tidb_config.search_field, tidb_config.return_field
+        );
+
+        // identifiers were validated at startup and are quoted here; values are bound below
+        let table = quote_mysql_identifier(&tidb_config.table_name);
+        let select_clause = if tidb_config.return_field.contains(&"*".to_string()) {
+            "*".to_string()
+        } else {
+            tidb_config
+                .return_field
+                .iter()
+                .map(|field| format!("{table}.{}", quote_mysql_identifier(field)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        let search_sql = format!(
+            r"SELECT {select_clause}, fts_match_word(:query, {table}.{search_field}) AS {score_column}
+            FROM {table}
+            WHERE fts_match_word(:query, {table}.{search_field})
+            ORDER BY fts_match_word(:query, {table}.{search_field}) DESC
+            LIMIT :limit OFFSET :offset",
+            select_clause = select_clause,
+            table = table,
+            search_field = quote_mysql_identifier(&tidb_config.search_field),
+            score_column = quote_mysql_identifier(TIDB_SCORE_COLUMN),
+        );
+
+        // execute the prepared statement and get the Row results
+        let rows: Vec<mysql::Row> = conn
+            .exec(
+                &search_sql,
+                params! {
+                    "query" => keywords,
+                    "limit" => params.limit,
+                    "offset" => params.offset,
+                },
+            )
+            .map_err(|e| {
+                let error_message = format!("Failed to execute search: {e}");
+                error!(error_message);
+                McpError::new(ErrorCode::INTERNAL_ERROR, error_message, None)
+            })?;
+
+        info!("Query returned {} rows", rows.len());
+
+        // Take the score column out of the rows so that it is not rendered as a field. The rows
+        // are sorted by descending score, so dropping the ones below the threshold after `LIMIT`
+        // yields the same page as filtering before it.
+        let score_threshold = params
+            .score_threshold
+            .unwrap_or(tidb_config.score_threshold) as f64;
+        let (scores, rows): (Vec<f64>, Vec<mysql::Row>) = rows
+            .into_iter()
+            .map(|mut row| {
+                let score = row
+                    .take::<Option<f64>, _>(TIDB_SCORE_COLUMN)
+                    .flatten()
+                    .unwrap_or(0.0);
+                (score, row)
+            })
+            .filter(|(score, _)| *score >= score_threshold)
+            .unzip();
+        debug!(
+            "{} rows left after applying the score threshold {}",
+            scores.len(),
+            score_threshold
+        );
+
+        // keep the raw field values for the structured output
+        let row_fields: Vec<Map<String, Value>> =
+            rows.iter().map(Self::extract_row_fields).collect();
+
+        // convert the Row results to formatted strings
+        let formatted_results = Self::extract_rows_generic_natural_language(rows);
+
+        // convert formatted strings to TidbSearchHit instances
+        let mut tidb_hits = Vec::new();
+        for ((formatted_text, fields), score) in
+            formatted_results.into_iter().zip(row_fields).zip(scores)
+        {
+            let hit = TidbSearchHit {
+                score,
+                id: fields.get(&tidb_config.id_field).and_then(field_to_string),
+                title: tidb_config
+                    .title_field
+                    .as_ref()
+                    .and_then(|field| fields.get(field))
+                    .and_then(field_to_string),
+                content: formatted_text,
+                fields,
+            };
+            tidb_hits.push(hit);
+        }
+
+        Ok(tidb_hits)
+    }
+
+    /// Extract rows from TiDB query results using generic natural language format
+    ///
+    /// This method converts MySQL rows to human-readable strings suitable for LLM processing
+    fn extract_rows_generic_natural_language(rows: Vec<mysql::Row>) -> Vec<String> {
+        let mut results = Vec::new();
+
+        for (row_index, row) in rows.iter().enumerate() {
+            let mut fields = Vec::new();
+
+            for (index, column) in row.columns_ref().iter().enumerate() {
+                if let Some(value) = row.get::<mysql::Value, _>(index) {
+                    let text_value = match value {
+                        mysql::Value::Bytes(bytes) => String::from_utf8_lossy(&bytes).to_string(),
+                        mysql::Value::NULL => continue,
+                        mysql::Value::Int(i) => i.to_string(),
+                        mysql::Value::UInt(u) => u.to_string(),
+                        mysql::Value::Float(f) => f.to_string(),
+                        mysql::Value::Double(d) => d.to_string(),
+                        mysql::Value::Date(year, month, day, hour, minute, second, microsecond) => {
+                            format!(
+                                "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:06}",
+                                year, month, day, hour, minute, second, microsecond
+                            )
+                        }
+                        _ => format!("{:?}", value),
+                    };
+                    fields.push((column.name_str().to_string(), text_value));
+                }
+            }
+
+            results.push(format_document(row_index + 1, fields));
+        }
+
+        results
+    }
+
+    /// Extract the fields of a TiDB row as JSON values, keyed by column name
+    fn extract_row_fields(row: &mysql::Row) -> Map<String, Value> {
+        let mut fields = Map::new();
+
+        for (index, column) in row.columns_ref().iter().enumerate() {
+            if let Some(value) = row.get::<mysql::Value, _>(index) {
+                let json_value = match value {
+                    mysql::Value::NULL => Value::Null,
+                    mysql::Value::Bytes(bytes) => {
+                        Value::String(String::from_utf8_lossy(&bytes).to_string())
+                    }
+                    mysql::Value::Int(i) => json!(i),
+                    mysql::Value::UInt(u) => json!(u),
+                    mysql::Value::Float(f) => json!(f),
+                    mysql::Value::Double(d) => json!(d),
+                    mysql::Value::Date(year, month, day, hour, minute, second, microsecond) => {
+                        Value::String(format!(
+                            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:06}",
+                            year, month, day, hour, minute, second, microsecond
+                        ))
+                    }
+                    _ => Value::String(format!("{:?}", value)),
+                };
+                fields.insert(column.name_str().to_string(), json_value);
+            }
+        }
+
+        fields
+    }
+}
+
+#[async_trait]
+impl SearchBackend for TidbBackend {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn kind(&self) -> BackendKind {
+        BackendKind::Keyword
+    }
+
+    fn describe(&self) -> BackendDescription {
+        BackendDescription {
+            name: self.name.clone(),
+            kind: self.kind(),
+            description: format!(
+                "TiDB table `{}` in database `{}`",
+                self.config.table_name, self.config.database
+            ),
+        }
+    }
+
+    async fn search(&self, query: &str, params: &SearchParams) -> Result<Vec<SearchHit>, McpError> {
+        info!("Starting keyword search in {} ...", self.name);
+
+        // extract keywords from the query
+        info!("Extracting keywords from the query...");
+        let keywords = metrics::time_stage(
+            params.mode,
+            &self.name,
+            Stage::KeywordExtraction,
+            services::extract_keywords(&self.chat_service, query),
+        )
+        .await?;
+        debug!("Extracted keywords: {:#?}", keywords);
+
+        // search in tidb
+        info!("Searching in TiDB...");
+        let hits = metrics::time_stage(
+            params.mode,
+            &self.name,
+            Stage::Tidb,
+            self.search_in_tidb(keywords, params.clone()),
+        )
+        .await?;
+
+        if !hits.is_empty() {
+            // format the search results
+            info!("Extracting the source of the keyword search results...");
+            let mut output = Vec::new();
+            for (index, mut hit) in hits.into_iter().enumerate() {
+                hit.fields.remove(&self.config.search_field);
+                hit.fields.remove(&self.config.id_field);
+                if let Some(title_field) = &self.config.title_field {
+                    hit.fields.remove(title_field);
+                }
+                output.push(SearchHit {
+                    backend: self.name.clone(),
+                    rank: params.offset as usize + index + 1,
+                    score: Some(hit.score),
+                    id: hit.id,
+                    title: hit.title,
+                    source: hit.content,
+                    metadata: select_metadata(hit.fields, self.config.metadata_fields.as_deref()),
+                    vector: None,
+                });
+            }
+
+            info!("Keyword search done! 🎉");
+
+            debug!("keyword search results:\n{:#?}", &output);
+
+            Ok(output)
+        } else {
+            let error_message = "No keyword search results found in TiDB";
+            warn!("{}", error_message);
+            Ok(vec![])
+        }
+    }
+
+    async fn health_check(&self) -> Result<(), McpError> {
+        let tidb_config = self.config.clone();
+
+        tokio::task::spawn_blocking(move || Self::connect(&tidb_config).map(|_| ()))
+            .await
+            .map_err(|e| {
+                let error_message = format!("Failed to join the TiDB health check task: {e}");
+                error!(error_message);
+                McpError::new(ErrorCode::INTERNAL_ERROR, error_message, None)
+            })??;
+
+        services::health_check(&self.chat_service).await
+    }
+}
@@ -0,0 +1,218 @@
+//! Tracking of the requests in progress, so that shutdown can drain them.
+//!
+//! A request is in progress from the moment its session receives it until its response is handed
+//! to the transport, which delivers it before closing. Closing the sessions any earlier loses the
+//! responses still on their way from the handlers to the transports.
+
+use futures::Stream;
+use rmcp::{
+    RoleServer,
+    model::{ClientJsonRpcMessage, JsonRpcMessage, RequestId, ServerJsonRpcMessage},
+    service::{RxJsonRpcMessage, TxJsonRpcMessage},
+    transport::{
+        IntoTransport, Transport,
+        common::server_side_http::ServerSseMessage,
+        streamable_http_server::{SessionId, SessionManager},
+    },
+};
+use std::collections::HashMap;
+use tokio_util::task::{TaskTracker, task_tracker::TaskTrackerToken};
+
+/// A transport counting the requests it received and has not responded to yet in the tracker
+pub struct TrackedTransport<T> {
+    inner: T,
+    in_flight: TaskTracker,
+    pending: HashMap<RequestId, TaskTrackerToken>,
+}
+impl<T> TrackedTransport<T>
+where
+    T: Transport<RoleServer>,
+{
+    pub fn new(inner: T, in_flight: TaskTracker) -> Self {
+        Self {
+            inner,
+            in_flight,
+            pending: HashMap::new(),
+        }
+    }
+}
+
+/// Track the requests received over the transport
+pub fn track<E, A>(
+    transport: impl IntoTransport<RoleServer, E, A>,
+    in_flight: TaskTracker,
+) -> TrackedTransport<impl Transport<RoleServer, Error = E>>
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    TrackedTransport::new(transport.into_transport(), in_flight)
+}
+
+impl<T> Transport<RoleServer> for TrackedTransport<T>
+where
+    T: Transport<RoleServer>,
+{
+    type Error = T::Error;
+
+    fn send(
+        &mut self,
+        item: TxJsonRpcMessage<RoleServer>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'static {
+        let token = match &item {
+            JsonRpcMessage::Response(response) => self.pending.remove(&response.id),
+            JsonRpcMessage::Error(error) => self.pending.remove(&error.id),
+            _ => None,
+        };
+        let send = self.inner.send(item);
+        async move {
+            let result = send.await;
+            drop(token);
+            result
+        }
+    }
+
+    async fn receive(&mut self) -> Option<RxJsonRpcMessage<RoleServer>> {
+        let message = self.inner.receive().await?;
+        if let JsonRpcMessage::Request(request) = &message {
+            self.pending
+                .insert(request.id.clone(), self.in_flight.token());
+        }
+        Some(message)
+    }
+
+    async fn close(&mut self) -> Result<(), Self::Error> {
+        // the requests left are never responded to
+        self.pending.clear();
+        self.inner.close().await
+    }
+}
+
+/// A session manager of the Streamable HTTP transport tracking the requests of its sessions
+pub struct TrackedSessionManager<M> {
+    inner: M,
+    in_flight: TaskTracker,
+}
+impl<M> TrackedSessionManager<M> {
+    pub fn new(inner: M, in_flight: TaskTracker) -> Self {
+        Self { inner, in_flight }
+    }
+}
+
+impl<M> SessionManager for TrackedSessionManager<M>
+where
+    M: SessionManager,
+{
+    type Error = M::Error;
+    type Transport = TrackedTransport<M::Transport>;
+
+    async fn create_session(&self) -> Result<(SessionId, Self::Transport), Self::Error> {
+        let (id, transport) = self.inner.create_session().await?;
+        Ok((id, TrackedTransport::new(transport, self.in_flight.clone())))
+    }
+
+    fn initialize_session(
+        &self,
+        id: &SessionId,
+        message: ClientJsonRpcMessage,
+    ) -> impl Future<Output = Result<ServerJsonRpcMessage, Self::Error>> + Send {
+        self.inner.initialize_session(id, message)
+    }
+
+    fn has_session(
+        &self,
+        id: &SessionId,
+    ) -> impl Future<Output = Result<bool, Self::Error>> + Send {
+        self.inner.has_session(id)
+    }
+
+    fn close_session(
+        &self,
+        id: &SessionId,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        self.inner.close_session(id)
+    }
+
+    fn create_stream(
+        &self,
+        id: &SessionId,
+        message: ClientJsonRpcMessage,
+    ) -> impl Future<
+        Output = Result<impl Stream<Item = ServerSseMessage> + Send + Sync + 'static, Self::Error>,
+    > + Send {
+        self.inner.create_stream(id, message)
+    }
+
+    fn accept_message(
+        &self,
+        id: &SessionId,
+        message: ClientJsonRpcMessage,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        self.inner.accept_message(id, message)
+    }
+
+    fn create_standalone_stream(
+        &self,
+        id: &SessionId,
+    ) -> impl Future<
+        Output = Result<impl Stream<Item = ServerSseMessage> + Send + Sync + 'static, Self::Error>,
+    > + Send {
+        self.inner.create_standalone_stream(id)
+    }
+
+    fn resume(
+        &self,
+        id: &SessionId,
+        last_event_id: String,
+    ) -> impl Future<
+        Output = Result<impl Stream<Item = ServerSseMessage> + Send + Sync + 'static, Self::Error>,
+    > + Send {
+        self.inner.resume(id, last_event_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tokio::sync::mpsc;
+    use tokio_stream::wrappers::ReceiverStream;
+    use tokio_util::sync::PollSender;
+
+    #[tokio::test]
+    async fn requests_are_tracked_until_responded_to() {
+        let (from_client_tx, from_client_rx) = mpsc::channel(4);
+        let (to_client_tx, mut to_client_rx) = mpsc::channel(4);
+        let in_flight = TaskTracker::new();
+        let mut transport = track(
+            (
+                PollSender::<ServerJsonRpcMessage>::new(to_client_tx),
+                ReceiverStream::new(from_client_rx),
+            ),
+            in_flight.clone(),
+        );
+
+        for message in [
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "ping" }),
+            json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }),
+            json!({ "jsonrpc": "2.0", "id": "two", "method": "ping" }),
+        ] {
+            let message: ClientJsonRpcMessage = serde_json::from_value(message).unwrap();
+            from_client_tx.send(message).await.unwrap();
+            transport.receive().await.unwrap();
+        }
+        assert_eq!(in_flight.len(), 2);
+
+        let response = |id| {
+            serde_json::from_value(json!({ "jsonrpc": "2.0", "id": id, "result": {} })).unwrap()
+        };
+        transport.send(response(json!(3))).await.unwrap();
+        assert_eq!(in_flight.len(), 2);
+        transport.send(response(json!(1))).await.unwrap();
+        assert_eq!(in_flight.len(), 1);
+        assert!(to_client_rx.recv().await.is_some());
+
+        // the requests left when the session closes are not waited for
+        transport.close().await.unwrap();
+        assert!(in_flight.is_empty());
+    }
+}
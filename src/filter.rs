@@ -0,0 +1,229 @@
+//! Translation of the `search` tool's payload filters into Qdrant's filter JSON.
+//!
+//! Only a safe subset of Qdrant's filter syntax is exposed, and only the payload keys that
+//! are allow-listed at startup may be filtered on.
+
+use crate::types::{FilterCondition, RangeBound, RangeCondition, SearchFilter};
+use serde_json::{Map, Value, json};
+
+/// Maximum number of conditions across `must`, `should` and `must_not`
+pub const MAX_FILTER_CONDITIONS: usize = 32;
+
+/// Validate a filter against the allow-listed keys and convert it into Qdrant's filter JSON
+///
+/// Returns a message describing the first problem if the filter is invalid.
+pub fn to_qdrant_filter(
+    filter: &SearchFilter,
+    filterable_fields: &[String],
+) -> Result<Value, String> {
+    let count = filter.must.len() + filter.should.len() + filter.must_not.len();
+    if count == 0 {
+        return Err("`filter` must contain at least one condition".to_string());
+    }
+    if count > MAX_FILTER_CONDITIONS {
+        return Err(format!(
+            "`filter` must not contain more than {MAX_FILTER_CONDITIONS} conditions, got {count}"
+        ));
+    }
+
+    let mut qdrant_filter = Map::new();
+    for (clause, conditions) in [
+        ("must", &filter.must),
+        ("should", &filter.should),
+        ("must_not", &filter.must_not),
+    ] {
+        if conditions.is_empty() {
+            continue;
+        }
+
+        let conditions = conditions
+            .iter()
+            .map(|condition| to_qdrant_condition(condition, filterable_fields))
+            .collect::<Result<Vec<_>, _>>()?;
+        qdrant_filter.insert(clause.to_string(), Value::Array(conditions));
+    }
+
+    Ok(Value::Object(qdrant_filter))
+}
+
+fn to_qdrant_condition(
+    condition: &FilterCondition,
+    filterable_fields: &[String],
+) -> Result<Value, String> {
+    let key = &condition.key;
+    if !filterable_fields.contains(key) {
+        return Err(format!(
+            "Filtering on `{key}` is not allowed. Filterable fields: {}",
+            if filterable_fields.is_empty() {
+                "none".to_string()
+            } else {
+                filterable_fields.join(", ")
+            }
+        ));
+    }
+
+    match (&condition.match_, &condition.range, condition.is_empty) {
+        (Some(match_), None, None) => match (&match_.value, &match_.any) {
+            (Some(value), None) => Ok(json!({ "key": key, "match": { "value": value } })),
+            (None, Some(any)) if !any.is_empty() => {
+                Ok(json!({ "key": key, "match": { "any": any } }))
+            }
+            (None, Some(_)) => Err(format!("`match.any` on `{key}` must not be empty")),
+            _ => Err(format!(
+                "`match` on `{key}` must set exactly one of `value` and `any`"
+            )),
+        },
+        (None, Some(range), None) => {
+            Ok(json!({ "key": key, "range": to_qdrant_range(key, range)? }))
+        }
+        (None, None, Some(true)) => Ok(json!({ "is_empty": { "key": key } })),
+        // Qdrant has no `is_not_empty` condition, so negate `is_empty` with a nested filter
+        (None, None, Some(false)) => Ok(json!({ "must_not": [{ "is_empty": { "key": key } }] })),
+        _ => Err(format!(
+            "The condition on `{key}` must set exactly one of `match`, `range` and `is_empty`"
+        )),
+    }
+}
+
+fn to_qdrant_range(key: &str, range: &RangeCondition) -> Result<Value, String> {
+    let bounds: Vec<(&str, &RangeBound)> = [
+        ("gt", &range.gt),
+        ("gte", &range.gte),
+        ("lt", &range.lt),
+        ("lte", &range.lte),
+    ]
+    .into_iter()
+    .filter_map(|(name, bound)| bound.as_ref().map(|bound| (name, bound)))
+    .collect();
+
+    if bounds.is_empty() {
+        return Err(format!("`range` on `{key}` must set at least one bound"));
+    }
+
+    let numeric = bounds
+        .iter()
+        .all(|(_, bound)| matches!(bound, RangeBound::Number(_)));
+    let datetime = bounds
+        .iter()
+        .all(|(_, bound)| matches!(bound, RangeBound::Datetime(_)));
+    if !numeric && !datetime {
+        return Err(format!(
+            "The bounds of `range` on `{key}` must be either all numbers or all datetimes"
+        ));
+    }
+
+    let mut qdrant_range = Map::new();
+    for (name, bound) in bounds {
+        let value = match bound {
+            RangeBound::Number(n) if n.is_finite() => json!(n),
+            RangeBound::Number(n) => {
+                return Err(format!("`range.{name}` on `{key}` must be finite, got {n}"));
+            }
+            RangeBound::Datetime(dt) => json!(dt),
+        };
+        qdrant_range.insert(name.to_string(), value);
+    }
+
+    Ok(Value::Object(qdrant_range))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filterable_fields() -> Vec<String> {
+        ["lang", "year", "published", "tags"]
+            .map(str::to_string)
+            .to_vec()
+    }
+
+    fn convert(filter: Value) -> Result<Value, String> {
+        let filter: SearchFilter = serde_json::from_value(filter).unwrap();
+        to_qdrant_filter(&filter, &filterable_fields())
+    }
+
+    #[test]
+    fn conditions_are_converted() {
+        let qdrant_filter = convert(json!({
+            "must": [
+                { "key": "lang", "match": { "value": "en" } },
+                { "key": "year", "range": { "gte": 2020, "lt": 2025 } },
+            ],
+            "should": [{ "key": "tags", "match": { "any": ["rust", 7, true] } }],
+            "must_not": [
+                { "key": "published", "range": { "lt": "2020-01-01T00:00:00Z" } },
+                { "key": "tags", "is_empty": true },
+            ],
+        }))
+        .unwrap();
+
+        assert_eq!(
+            qdrant_filter,
+            json!({
+                "must": [
+                    { "key": "lang", "match": { "value": "en" } },
+                    { "key": "year", "range": { "gte": 2020.0, "lt": 2025.0 } },
+                ],
+                "should": [{ "key": "tags", "match": { "any": ["rust", 7, true] } }],
+                "must_not": [
+                    { "key": "published", "range": { "lt": "2020-01-01T00:00:00Z" } },
+                    { "is_empty": { "key": "tags" } },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn is_not_empty_is_a_nested_negation() {
+        let qdrant_filter =
+            convert(json!({ "must": [{ "key": "tags", "is_empty": false }] })).unwrap();
+
+        assert_eq!(
+            qdrant_filter,
+            json!({ "must": [{ "must_not": [{ "is_empty": { "key": "tags" } }] }] })
+        );
+    }
+
+    #[test]
+    fn only_the_filterable_fields_are_allowed() {
+        let error =
+            convert(json!({ "must": [{ "key": "secret", "match": { "value": 1 } }] })).unwrap_err();
+        assert!(error.contains("`secret` is not allowed"), "{error}");
+
+        let filter: SearchFilter =
+            serde_json::from_value(json!({ "must": [{ "key": "lang", "is_empty": true }] }))
+                .unwrap();
+        let error = to_qdrant_filter(&filter, &[]).unwrap_err();
+        assert!(error.ends_with("Filterable fields: none"), "{error}");
+    }
+
+    #[test]
+    fn the_number_of_conditions_is_bounded() {
+        assert!(convert(json!({})).is_err());
+
+        let conditions = vec![json!({ "key": "lang", "is_empty": true }); MAX_FILTER_CONDITIONS];
+        assert!(convert(json!({ "must": conditions })).is_ok());
+
+        let conditions =
+            vec![json!({ "key": "lang", "is_empty": true }); MAX_FILTER_CONDITIONS + 1];
+        assert!(convert(json!({ "must": conditions })).is_err());
+    }
+
+    #[test]
+    fn invalid_conditions_are_rejected() {
+        for condition in [
+            json!({ "key": "lang" }),
+            json!({ "key": "lang", "match": { "value": "en" }, "is_empty": true }),
+            json!({ "key": "lang", "match": {} }),
+            json!({ "key": "lang", "match": { "value": "en", "any": ["fr"] } }),
+            json!({ "key": "tags", "match": { "any": [] } }),
+            json!({ "key": "year", "range": {} }),
+            json!({ "key": "year", "range": { "gte": 2020, "lt": "2025-01-01T00:00:00Z" } }),
+        ] {
+            assert!(
+                convert(json!({ "must": [condition] })).is_err(),
+                "{condition}"
+            );
+        }
+    }
+}
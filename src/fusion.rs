@@ -0,0 +1,391 @@
+use crate::{
+    backend::document_text,
+    types::{SearchHit, SearchResult},
+};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Default `k` constant of Reciprocal Rank Fusion, as proposed by Cormack et al.
+pub const DEFAULT_RRF_K: f64 = 60.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FusionMethod {
+    /// Reciprocal Rank Fusion: `sum(weight / (k + rank))`
+    Rrf,
+    /// Weighted sum of the per-backend min-max normalized scores
+    Weighted,
+}
+
+#[derive(Debug, Clone)]
+pub struct FusionConfig {
+    pub method: FusionMethod,
+    pub rrf_k: f64,
+    /// Per-backend weights keyed by backend name. Backends without an entry weigh 1.0
+    pub weights: HashMap<String, f64>,
+}
+impl FusionConfig {
+    pub fn weight(&self, backend: &str) -> f64 {
+        self.weights.get(backend).copied().unwrap_or(1.0)
+    }
+}
+impl Default for FusionConfig {
+    fn default() -> Self {
+        Self {
+            method: FusionMethod::Rrf,
+            rrf_k: DEFAULT_RRF_K,
+            weights: HashMap::new(),
+        }
+    }
+}
+
+/// A document produced by fusing the hits of one or more backends
+#[derive(Debug, Clone)]
+pub struct FusedHit {
+    /// The fused score. Only meaningful relative to the other hits of the same fusion
+    pub score: f64,
+    /// The hits that were merged into this document, in the order they were first seen
+    pub hits: Vec<SearchHit>,
+}
+impl FusedHit {
+    /// Convert the fused document into a search result, taking its id, title, source and
+    /// metadata from the first backend that returned it, and its embedding from the first one
+    /// that returned that
+    pub fn into_result(self, rank: usize) -> SearchResult {
+        let matches = self.hits.iter().map(SearchHit::to_match).collect();
+        let vector = self.hits.iter().find_map(|hit| hit.vector.clone());
+        let first = self.hits.into_iter().next().unwrap();
+
+        SearchResult {
+            id: first.id,
+            backend: first.backend,
+            rank,
+            score: Some(self.score),
+            title: first.title,
+            source: first.source,
+            metadata: first.metadata,
+            matches,
+            vector,
+        }
+    }
+}
+
+/// Fuse the ranked result lists of several backends into a single ranked list.
+///
+/// Hits of the same document are merged into one document: the hits with the same id in the
+/// same backend, e.g. when several queries return it, and the hits with the same text once the
+/// header numbering the documents in their page is removed, e.g. when several backends return it.
+/// The output is sorted by descending fused score; ties keep the order in which the documents
+/// were first seen, so the same inputs always produce the same ranking.
+pub fn fuse(config: &FusionConfig, result_lists: Vec<Vec<SearchHit>>) -> Vec<FusedHit> {
+    let mut fused: Vec<FusedHit> = Vec::new();
+    let mut by_id: HashMap<(String, String), usize> = HashMap::new();
+    let mut by_text: HashMap<String, usize> = HashMap::new();
+
+    for hits in result_lists {
+        let normalized = match config.method {
+            FusionMethod::Rrf => Vec::new(),
+            FusionMethod::Weighted => {
+                normalize_scores(hits.iter().map(|hit| (hit.score, hit.rank)))
+            }
+        };
+
+        for (i, hit) in hits.into_iter().enumerate() {
+            let weight = config.weight(&hit.backend);
+            let contribution = match config.method {
+                FusionMethod::Rrf => weight / (config.rrf_k + hit.rank as f64),
+                FusionMethod::Weighted => weight * normalized[i],
+            };
+
+            let id_key = hit.id.clone().map(|id| (hit.backend.clone(), id));
+            let text_key = document_text(&hit.source).to_string();
+            let pos = match id_key
+                .as_ref()
+                .and_then(|key| by_id.get(key))
+                .or_else(|| by_text.get(&text_key))
+            {
+                Some(&pos) => {
+                    fused[pos].score += contribution;
+                    fused[pos].hits.push(hit);
+                    pos
+                }
+                None => {
+                    fused.push(FusedHit {
+                        score: contribution,
+                        hits: vec![hit],
+                    });
+                    fused.len() - 1
+                }
+            };
+            if let Some(id_key) = id_key {
+                by_id.entry(id_key).or_insert(pos);
+            }
+            by_text.entry(text_key).or_insert(pos);
+        }
+    }
+
+    // `sort_by` is stable, so documents with equal scores keep their first-seen order
+    fused.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    fused
+}
+
+/// Min-max normalize the scores of a ranked list, e.g. a single backend's hits, into `[0, 1]`.
+///
+/// Takes the score and the rank of each item. Items without a score fall back to a rank-based
+/// score of `1 / rank`. If all the scores are equal, they normalize to 1.
+pub(crate) fn normalize_scores(scores: impl IntoIterator<Item = (Option<f64>, usize)>) -> Vec<f64> {
+    let raw: Vec<f64> = scores
+        .into_iter()
+        .map(|(score, rank)| score.unwrap_or(1.0 / rank as f64))
+        .collect();
+
+    let min = raw.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = raw.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let range = max - min;
+
+    raw.into_iter()
+        .map(|score| {
+            if range > f64::EPSILON {
+                (score - min) / range
+            } else {
+                1.0
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::format_document;
+    use serde_json::Map;
+
+    fn hit(backend: &str, rank: usize, score: Option<f64>, source: &str) -> SearchHit {
+        SearchHit {
+            backend: backend.to_string(),
+            rank,
+            score,
+            id: None,
+            title: None,
+            source: source.to_string(),
+            metadata: Map::new(),
+            vector: None,
+        }
+    }
+
+    /// A hit of a table row, rendered as the TiDB, Postgres and Tantivy backends render it, with
+    /// the header numbering it in its page
+    fn row(backend: &str, rank: usize, id: Option<&str>, content: &str) -> SearchHit {
+        SearchHit {
+            id: id.map(str::to_string),
+            source: format_document(rank, [("content".to_string(), content.to_string())]),
+            ..hit(backend, rank, None, "")
+        }
+    }
+
+    fn normalize(hits: &[SearchHit]) -> Vec<f64> {
+        normalize_scores(hits.iter().map(|hit| (hit.score, hit.rank)))
+    }
+
+    fn sources(fused: &[FusedHit]) -> Vec<&str> {
+        fused
+            .iter()
+            .map(|hit| hit.hits[0].source.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn rrf_of_a_single_list_keeps_its_order() {
+        let config = FusionConfig::default();
+        let fused = fuse(
+            &config,
+            vec![vec![
+                hit("qdrant", 1, Some(0.9), "a"),
+                hit("qdrant", 2, Some(0.8), "b"),
+                hit("qdrant", 3, Some(0.1), "c"),
+            ]],
+        );
+
+        assert_eq!(sources(&fused), ["a", "b", "c"]);
+        assert_eq!(fused[0].score, 1.0 / (DEFAULT_RRF_K + 1.0));
+        assert_eq!(fused[2].score, 1.0 / (DEFAULT_RRF_K + 3.0));
+    }
+
+    #[test]
+    fn rrf_merges_the_hits_of_the_same_source() {
+        let config = FusionConfig::default();
+        let fused = fuse(
+            &config,
+            vec![
+                vec![hit("qdrant", 1, None, "a"), hit("qdrant", 2, None, "b")],
+                vec![hit("tidb", 1, None, "b"), hit("tidb", 2, None, "c")],
+            ],
+        );
+
+        assert_eq!(sources(&fused), ["b", "a", "c"]);
+        assert_eq!(fused[0].hits.len(), 2);
+        assert_eq!(
+            fused[0].score,
+            1.0 / (DEFAULT_RRF_K + 2.0) + 1.0 / (DEFAULT_RRF_K + 1.0)
+        );
+    }
+
+    #[test]
+    fn rrf_merges_the_rows_numbered_differently_in_their_pages() {
+        let config = FusionConfig::default();
+        let fused = fuse(
+            &config,
+            vec![
+                vec![row("tidb", 1, None, "a"), row("tidb", 2, None, "b")],
+                vec![row("postgres", 1, None, "b"), row("postgres", 2, None, "a")],
+            ],
+        );
+
+        assert_eq!(fused.len(), 2);
+        assert_eq!(fused[0].hits.len(), 2);
+        assert_eq!(fused[1].hits.len(), 2);
+        assert_eq!(
+            fused[0].score,
+            1.0 / (DEFAULT_RRF_K + 1.0) + 1.0 / (DEFAULT_RRF_K + 2.0)
+        );
+        assert_eq!(fused[0].score, fused[1].score);
+    }
+
+    #[test]
+    fn rrf_merges_the_hits_of_the_same_id_in_a_backend() {
+        let config = FusionConfig::default();
+        let fused = fuse(
+            &config,
+            vec![
+                // the same document, whose text changed between the searches of two queries
+                vec![
+                    row("postgres", 1, Some("7"), "old"),
+                    row("postgres", 2, Some("8"), "b"),
+                ],
+                vec![
+                    row("postgres", 1, Some("8"), "b"),
+                    row("postgres", 2, Some("7"), "new"),
+                ],
+                // another backend using the same id for another document
+                vec![SearchHit {
+                    id: Some("7".to_string()),
+                    ..hit("qdrant", 1, None, "c")
+                }],
+            ],
+        );
+
+        let documents: Vec<(&str, Option<&str>, usize)> = fused
+            .iter()
+            .map(|hit| {
+                (
+                    hit.hits[0].backend.as_str(),
+                    hit.hits[0].id.as_deref(),
+                    hit.hits.len(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            documents,
+            [
+                ("postgres", Some("7"), 2),
+                ("postgres", Some("8"), 2),
+                ("qdrant", Some("7"), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn the_page_header_is_not_part_of_the_text() {
+        let source = format_document(12, [("title".to_string(), "Rust".to_string())]);
+        assert_eq!(document_text(&source), "Title: Rust");
+        assert_eq!(document_text("=== Document 3 ==="), "");
+        assert_eq!(
+            document_text("=== Document x ===\n\nRust"),
+            "=== Document x ===\n\nRust"
+        );
+        assert_eq!(document_text("Rust"), "Rust");
+    }
+
+    #[test]
+    fn rank_ties_keep_the_first_seen_order() {
+        let config = FusionConfig::default();
+        let fused = fuse(
+            &config,
+            vec![
+                vec![hit("qdrant", 1, None, "a")],
+                vec![hit("tidb", 1, None, "b")],
+            ],
+        );
+
+        assert_eq!(fused[0].score, fused[1].score);
+        assert_eq!(sources(&fused), ["a", "b"]);
+    }
+
+    #[test]
+    fn weights_change_the_order() {
+        let lists = || {
+            vec![
+                vec![
+                    hit("qdrant", 1, Some(0.9), "a"),
+                    hit("qdrant", 2, Some(0.5), "b"),
+                ],
+                vec![
+                    hit("tidb", 1, Some(7.0), "b"),
+                    hit("tidb", 2, Some(3.0), "a"),
+                ],
+            ]
+        };
+        let mut config = FusionConfig {
+            method: FusionMethod::Weighted,
+            ..Default::default()
+        };
+
+        // equal weights tie, and the first seen document comes first
+        assert_eq!(sources(&fuse(&config, lists())), ["a", "b"]);
+
+        config.weights.insert("tidb".to_string(), 2.0);
+        assert_eq!(sources(&fuse(&config, lists())), ["b", "a"]);
+
+        config.method = FusionMethod::Rrf;
+        config.weights = HashMap::from([("qdrant".to_string(), 3.0)]);
+        assert_eq!(sources(&fuse(&config, lists())), ["a", "b"]);
+    }
+
+    #[test]
+    fn normalize_scores_maps_to_the_unit_interval() {
+        let hits = [
+            hit("tidb", 1, Some(7.0), "a"),
+            hit("tidb", 2, Some(4.0), "b"),
+            hit("tidb", 3, Some(3.0), "c"),
+        ];
+
+        assert_eq!(normalize(&hits), [1.0, 0.25, 0.0]);
+    }
+
+    #[test]
+    fn normalize_scores_of_equal_scores_does_not_divide_by_zero() {
+        let hits = [
+            hit("tidb", 1, Some(2.0), "a"),
+            hit("tidb", 2, Some(2.0), "b"),
+        ];
+        assert_eq!(normalize(&hits), [1.0, 1.0]);
+
+        let single = [hit("tidb", 1, Some(0.3), "a")];
+        assert_eq!(normalize(&single), [1.0]);
+
+        assert!(normalize(&[]).is_empty());
+    }
+
+    #[test]
+    fn normalize_scores_falls_back_to_the_rank() {
+        let hits = [
+            hit("tantivy", 1, None, "a"),
+            hit("tantivy", 2, None, "b"),
+            hit("tantivy", 4, None, "c"),
+        ];
+
+        assert_eq!(normalize(&hits), [1.0, 1.0 / 3.0, 0.0]);
+    }
+}
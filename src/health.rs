@@ -0,0 +1,464 @@
+//! Health, readiness and status endpoints for orchestrators and on-call.
+//!
+//! - `GET /healthz` answers as long as the process is alive
+//! - `GET /readyz` checks every backend and the services it uses, and fails during shutdown
+//! - `GET /status` reports the outcome and latency of the last searches in each backend
+
+use crate::{
+    AgenticSearchConfig,
+    backend::{BackendDescription, BackendKind, SearchBackend},
+    metrics,
+    types::{SearchFilter, SearchHit, SearchParams},
+};
+use async_trait::async_trait;
+use axum::{
+    Json, Router,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use chrono::{DateTime, Utc};
+use rmcp::ErrorData as McpError;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+/// The search outcomes of a backend
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BackendStatus {
+    pub successes: u64,
+    pub failures: u64,
+    pub last_success_at: Option<DateTime<Utc>>,
+    pub last_failure_at: Option<DateTime<Utc>>,
+    /// The error of the last failed search
+    pub last_error: Option<String>,
+    /// The latency of the last search, successful or not
+    pub last_latency_ms: Option<f64>,
+}
+
+/// The search outcomes of all backends, shared by the sessions and the status endpoint
+#[derive(Debug, Clone, Default)]
+pub struct StatusRegistry(Arc<Mutex<HashMap<String, BackendStatus>>>);
+impl StatusRegistry {
+    fn record(&self, backend: &str, latency: Duration, result: Result<(), String>) {
+        let mut statuses = self.0.lock().unwrap();
+        let status = statuses.entry(backend.to_string()).or_default();
+        status.last_latency_ms = Some(latency.as_secs_f64() * 1000.0);
+        match result {
+            Ok(()) => {
+                status.successes += 1;
+                status.last_success_at = Some(Utc::now());
+            }
+            Err(error_message) => {
+                status.failures += 1;
+                status.last_failure_at = Some(Utc::now());
+                status.last_error = Some(error_message);
+            }
+        }
+    }
+
+    fn get(&self, backend: &str) -> BackendStatus {
+        self.0
+            .lock()
+            .unwrap()
+            .get(backend)
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+/// A backend that records the outcome and latency of its searches in the status registry
+///
+/// A search that is dropped before it completes, e.g. because it timed out, is recorded as a
+/// failure.
+#[derive(Debug)]
+pub struct MonitoredBackend {
+    inner: Arc<dyn SearchBackend>,
+    status: StatusRegistry,
+}
+impl MonitoredBackend {
+    pub fn new(inner: Arc<dyn SearchBackend>, status: StatusRegistry) -> Self {
+        Self { inner, status }
+    }
+}
+
+/// Records a search as failed unless it is marked as done
+struct PendingSearch<'a> {
+    backend: &'a MonitoredBackend,
+    start: Instant,
+    result: Option<Result<(), String>>,
+}
+impl Drop for PendingSearch<'_> {
+    fn drop(&mut self) {
+        let result = self
+            .result
+            .take()
+            .unwrap_or_else(|| Err("The search was cancelled or timed out".to_string()));
+        self.backend
+            .status
+            .record(self.backend.name(), self.start.elapsed(), result);
+    }
+}
+
+#[async_trait]
+impl SearchBackend for MonitoredBackend {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn kind(&self) -> BackendKind {
+        self.inner.kind()
+    }
+
+    fn describe(&self) -> BackendDescription {
+        self.inner.describe()
+    }
+
+    fn validate_filter(&self, filter: &SearchFilter) -> Result<(), String> {
+        self.inner.validate_filter(filter)
+    }
+
+    async fn search(&self, query: &str, params: &SearchParams) -> Result<Vec<SearchHit>, McpError> {
+        let mut pending = PendingSearch {
+            backend: self,
+            start: Instant::now(),
+            result: None,
+        };
+        let result = self.inner.search(query, params).await;
+        pending.result = Some(match &result {
+            Ok(hits) => {
+                metrics::record_backend_results(params.mode, self.name(), hits.len());
+                Ok(())
+            }
+            Err(e) => Err(e.message.to_string()),
+        });
+
+        result
+    }
+
+    async fn health_check(&self) -> Result<(), McpError> {
+        self.inner.health_check().await
+    }
+}
+
+#[derive(Clone)]
+struct HealthState {
+    /// The search config, whose backends are checked with its backend timeout
+    config: watch::Receiver<Arc<AgenticSearchConfig>>,
+    status: StatusRegistry,
+    shutdown: CancellationToken,
+}
+
+#[derive(Debug, Serialize)]
+struct ReadinessCheck {
+    #[serde(flatten)]
+    backend: BackendDescription,
+    ready: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    latency_ms: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct Readiness {
+    ready: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    checks: Vec<ReadinessCheck>,
+}
+
+#[derive(Debug, Serialize)]
+struct BackendStatusReport {
+    #[serde(flatten)]
+    backend: BackendDescription,
+    #[serde(flatten)]
+    status: BackendStatus,
+}
+
+#[derive(Debug, Serialize)]
+struct StatusReport {
+    shutting_down: bool,
+    backends: Vec<BackendStatusReport>,
+}
+
+/// Build the router of the health endpoints
+///
+/// Readiness fails once `shutdown` is cancelled, so that no new traffic is routed to the server.
+pub fn router(
+    config: watch::Receiver<Arc<AgenticSearchConfig>>,
+    status: StatusRegistry,
+    shutdown: CancellationToken,
+) -> Router {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/status", get(status_report))
+        .with_state(HealthState {
+            config,
+            status,
+            shutdown,
+        })
+}
+
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+async fn readyz(State(state): State<HealthState>) -> Response {
+    let config = state.config.borrow().clone();
+    let check_timeout = config.backend_timeout;
+    // check the backends concurrently, as each may take up to the timeout
+    let checks = futures::future::join_all(config.backends().map(|backend| async {
+        let start = Instant::now();
+        let error = match tokio::time::timeout(check_timeout, backend.health_check()).await {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some(e.message.to_string()),
+            Err(_) => Some(format!(
+                "The health check timed out after {check_timeout:?}"
+            )),
+        };
+        ReadinessCheck {
+            backend: backend.describe(),
+            ready: error.is_none(),
+            error,
+            latency_ms: start.elapsed().as_secs_f64() * 1000.0,
+        }
+    }))
+    .await;
+
+    let reason = if state.shutdown.is_cancelled() {
+        Some("The server is shutting down".to_string())
+    } else {
+        checks
+            .iter()
+            .find(|check| !check.ready)
+            .map(|check| format!("The search backend `{}` is not ready", check.backend.name))
+    };
+    if let Some(reason) = &reason {
+        warn!("Not ready: {}", reason);
+    }
+
+    let status_code = match reason {
+        Some(_) => StatusCode::SERVICE_UNAVAILABLE,
+        None => StatusCode::OK,
+    };
+    let readiness = Readiness {
+        ready: reason.is_none(),
+        reason,
+        checks,
+    };
+    (status_code, Json(readiness)).into_response()
+}
+
+async fn status_report(State(state): State<HealthState>) -> Json<StatusReport> {
+    let config = state.config.borrow().clone();
+    Json(StatusReport {
+        shutting_down: state.shutdown.is_cancelled(),
+        backends: config
+            .backends()
+            .map(|backend| BackendStatusReport {
+                backend: backend.describe(),
+                status: state.status.get(backend.name()),
+            })
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_support, types::RetrievalMode};
+    use rmcp::model::ErrorCode;
+    use serde_json::Value;
+
+    /// A keyword backend whose health check and searches fail with the given error, if any
+    #[derive(Debug)]
+    struct StubBackend {
+        name: &'static str,
+        error: Option<&'static str>,
+        latency: Duration,
+    }
+    impl StubBackend {
+        fn new(name: &'static str, error: Option<&'static str>) -> Self {
+            Self {
+                name,
+                error,
+                latency: Duration::ZERO,
+            }
+        }
+
+        fn result(&self) -> Result<(), McpError> {
+            match self.error {
+                Some(error) => Err(McpError::new(ErrorCode::INTERNAL_ERROR, error, None)),
+                None => Ok(()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl SearchBackend for StubBackend {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn kind(&self) -> BackendKind {
+            BackendKind::Keyword
+        }
+
+        fn describe(&self) -> BackendDescription {
+            BackendDescription {
+                name: self.name.to_string(),
+                kind: BackendKind::Keyword,
+                description: "stub".to_string(),
+            }
+        }
+
+        async fn search(
+            &self,
+            _query: &str,
+            _params: &SearchParams,
+        ) -> Result<Vec<SearchHit>, McpError> {
+            tokio::time::sleep(self.latency).await;
+            self.result().map(|()| Vec::new())
+        }
+
+        async fn health_check(&self) -> Result<(), McpError> {
+            self.result()
+        }
+    }
+
+    /// Serve the health endpoints of the backends, monitored with the status registry
+    async fn serve(
+        backends: Vec<Arc<dyn SearchBackend>>,
+        status: StatusRegistry,
+        shutdown: CancellationToken,
+    ) -> String {
+        let (_, config) = watch::channel(Arc::new(test_support::search_config(backends)));
+        let address = test_support::serve(router(config, status, shutdown)).await;
+        format!("http://{address}")
+    }
+
+    async fn get(url: String) -> (StatusCode, Value) {
+        let response = reqwest::get(url).await.unwrap();
+        let status = StatusCode::from_u16(response.status().as_u16()).unwrap();
+        (status, response.json().await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn readiness_fails_with_a_failing_backend() {
+        let base_url = serve(
+            vec![
+                Arc::new(StubBackend::new("qdrant", None)),
+                Arc::new(StubBackend::new("tidb", Some("Connection refused"))),
+            ],
+            StatusRegistry::default(),
+            CancellationToken::new(),
+        )
+        .await;
+
+        let (status, readiness) = get(format!("{base_url}/readyz")).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(readiness["ready"], false);
+        assert_eq!(
+            readiness["reason"],
+            "The search backend `tidb` is not ready"
+        );
+        assert_eq!(readiness["checks"][0]["ready"], true);
+        assert_eq!(readiness["checks"][1]["error"], "Connection refused");
+
+        let healthz = reqwest::get(format!("{base_url}/healthz")).await.unwrap();
+        assert!(healthz.status().is_success());
+    }
+
+    #[tokio::test]
+    async fn readiness_fails_once_shutdown_starts() {
+        let shutdown = CancellationToken::new();
+        let base_url = serve(
+            vec![Arc::new(StubBackend::new("qdrant", None))],
+            StatusRegistry::default(),
+            shutdown.clone(),
+        )
+        .await;
+
+        let (status, readiness) = get(format!("{base_url}/readyz")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(readiness["ready"], true);
+
+        shutdown.cancel();
+        let (status, readiness) = get(format!("{base_url}/readyz")).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(readiness["reason"], "The server is shutting down");
+        let (_, report) = get(format!("{base_url}/status")).await;
+        assert_eq!(report["shutting_down"], true);
+    }
+
+    #[tokio::test]
+    async fn the_status_reports_the_monitored_searches() {
+        let status = StatusRegistry::default();
+        let monitored = |backend: StubBackend| -> Arc<dyn SearchBackend> {
+            Arc::new(MonitoredBackend::new(Arc::new(backend), status.clone()))
+        };
+        let slow = monitored(StubBackend {
+            latency: Duration::from_millis(20),
+            ..StubBackend::new("qdrant", None)
+        });
+        let failing = monitored(StubBackend::new("tidb", Some("Connection refused")));
+        let hanging = monitored(StubBackend {
+            latency: Duration::from_secs(60),
+            ..StubBackend::new("tantivy", None)
+        });
+        let base_url = serve(
+            vec![slow.clone(), failing.clone(), hanging.clone()],
+            status.clone(),
+            CancellationToken::new(),
+        )
+        .await;
+
+        let params = SearchParams {
+            mode: RetrievalMode::Keyword,
+            limit: 10,
+            offset: 0,
+            score_threshold: None,
+            filter: None,
+            mmr_lambda: None,
+            hypothetical_document: None,
+        };
+        slow.search("rust", &params).await.unwrap();
+        failing.search("rust", &params).await.unwrap_err();
+        failing.search("rust", &params).await.unwrap_err();
+        let timeout =
+            tokio::time::timeout(Duration::from_millis(10), hanging.search("rust", &params));
+        assert!(timeout.await.is_err());
+
+        let (_, report) = get(format!("{base_url}/status")).await;
+        assert_eq!(report["shutting_down"], false);
+        let [slow, failing, hanging] = [0, 1, 2].map(|i| &report["backends"][i]);
+
+        assert_eq!(slow["name"], "qdrant");
+        assert_eq!(
+            (slow["successes"].as_u64(), slow["failures"].as_u64()),
+            (Some(1), Some(0))
+        );
+        assert!(slow["last_latency_ms"].as_f64().unwrap() >= 20.0);
+        assert!(slow["last_success_at"].is_string());
+        assert!(slow["last_error"].is_null());
+
+        assert_eq!(failing["failures"], 2);
+        assert_eq!(failing["last_error"], "Connection refused");
+        assert!(failing["last_failure_at"].is_string());
+
+        assert_eq!(hanging["failures"], 1);
+        assert_eq!(
+            hanging["last_error"],
+            "The search was cancelled or timed out"
+        );
+        assert_eq!(status.get("unknown").successes, 0);
+    }
+}
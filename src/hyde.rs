@@ -0,0 +1,220 @@
+//! Hypothetical document embeddings (HyDE) for the vector search.
+//!
+//! A question and the passages answering it are worded differently, so their embeddings can be
+//! far apart. With HyDE, the chat service first drafts a passage answering the query, and the
+//! vector search looks for the documents close to the embedding of that passage, optionally
+//! averaged with the embedding of the query. The draft may get the facts wrong, but it is worded
+//! like the documents that get them right.
+
+use crate::{
+    ServiceConfig,
+    metrics::{self, Stage},
+    services,
+    types::RetrievalMode,
+};
+use rmcp::ErrorData as McpError;
+use tracing::{debug, info, warn};
+
+#[derive(Debug, Clone)]
+pub struct HydeConfig {
+    /// The chat service drafting the hypothetical documents
+    pub service: ServiceConfig,
+    /// The drafting instructions. Defaults to the built-in prompt
+    pub prompt: Option<String>,
+    /// Whether the embedding of the hypothetical document is averaged with the embedding of the
+    /// query
+    pub include_query: bool,
+}
+
+/// A hypothetical document drafted for the query of a search call
+#[derive(Debug, Clone)]
+pub struct HypotheticalDocument {
+    pub text: String,
+    /// Whether its embedding is averaged with the embedding of the query
+    pub include_query: bool,
+}
+
+/// The hypothetical document drafted for the query, and a warning if drafting it failed
+///
+/// If the chat service fails or drafts an empty document, the vector backends embed the query as
+/// is.
+pub async fn draft(
+    config: &HydeConfig,
+    query: &str,
+) -> (Option<HypotheticalDocument>, Option<String>) {
+    info!("Drafting a hypothetical document for the query...");
+    let answer = match services::write_hypothetical_document(
+        &config.service,
+        config.prompt.as_deref(),
+        query,
+    )
+    .await
+    {
+        Ok(answer) => answer,
+        Err(e) => {
+            let warning = format!(
+                "Drafting a hypothetical document failed, embedding the query as is: {}",
+                e.message
+            );
+            warn!("{}", warning);
+            return (None, Some(warning));
+        }
+    };
+
+    let text = services::strip_reasoning(&answer).trim();
+    if text.is_empty() {
+        let warning = "The hypothetical document is empty, embedding the query as is".to_string();
+        warn!("{}", warning);
+        return (None, Some(warning));
+    }
+    debug!("Hypothetical document:\n{}", text);
+
+    let document = HypotheticalDocument {
+        text: text.to_string(),
+        include_query: config.include_query,
+    };
+    (Some(document), None)
+}
+
+/// The embedding a vector backend searches the query with
+///
+/// Without a hypothetical document, this is the embedding of the query. With one, this is the
+/// embedding of the document, averaged with the embedding of the query if it includes the query.
+/// The stages are recorded in the metrics of the `backend`.
+pub async fn embed_query(
+    embedding_service: &ServiceConfig,
+    document: Option<&HypotheticalDocument>,
+    query: &str,
+    mode: RetrievalMode,
+    backend: &str,
+) -> Result<Vec<f64>, McpError> {
+    let embed = |text: &str| {
+        metrics::time_stage(
+            mode,
+            backend,
+            Stage::Embedding,
+            services::compute_embedding(embedding_service, text.to_string()),
+        )
+    };
+
+    match document {
+        None => {
+            info!("Computing embedding of the query...");
+            embed(query).await
+        }
+        Some(document) if document.include_query => {
+            info!("Computing embeddings of the hypothetical document and the query...");
+            let (document_embedding, query_embedding) =
+                tokio::try_join!(embed(&document.text), embed(query))?;
+            Ok(average(&document_embedding, &query_embedding))
+        }
+        Some(document) => {
+            info!("Computing embedding of the hypothetical document...");
+            embed(&document.text).await
+        }
+    }
+}
+
+/// The element-wise mean of two embeddings of the same model, each L2-normalized first so that
+/// neither outweighs the other, as some models do not return unit vectors
+fn average(a: &[f64], b: &[f64]) -> Vec<f64> {
+    let norm = |v: &[f64]| {
+        let norm = v.iter().map(|x| x * x).sum::<f64>().sqrt();
+        if norm > 0.0 { norm } else { 1.0 }
+    };
+    let (norm_a, norm_b) = (norm(a), norm(b));
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| (x / norm_a + y / norm_b) / 2.0)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::StubServices;
+
+    fn config(service: ServiceConfig) -> HydeConfig {
+        HydeConfig {
+            service,
+            prompt: None,
+            include_query: true,
+        }
+    }
+
+    #[test]
+    fn the_embeddings_are_normalized_before_averaging() {
+        assert_eq!(average(&[0.0, 2.0], &[4.0, 0.0]), [0.5, 0.5]);
+        // a zero embedding is left as is
+        assert_eq!(average(&[0.0, 0.0], &[0.0, 2.0]), [0.0, 0.5]);
+    }
+
+    #[tokio::test]
+    async fn the_reasoning_is_left_out_of_the_document() {
+        let stub = StubServices::spawn(
+            |_| vec![1.0],
+            |_| "<think>a passage</think>\n Tokio schedules tasks. ".into(),
+        )
+        .await;
+        let (document, warning) = draft(&config(stub.config()), "how does tokio work").await;
+        let document = document.unwrap();
+        assert_eq!(document.text, "Tokio schedules tasks.");
+        assert!(document.include_query);
+        assert_eq!(warning, None);
+    }
+
+    #[tokio::test]
+    async fn an_empty_or_failed_draft_is_a_warning() {
+        let stub = StubServices::spawn(|_| vec![1.0], |_| "<think>hmm</think>  ".into()).await;
+        let (document, warning) = draft(&config(stub.config()), "query").await;
+        assert!(document.is_none());
+        assert!(warning.unwrap().contains("empty"));
+
+        let unreachable = ServiceConfig {
+            url: "http://127.0.0.1:1/v1".to_string(),
+            api_key: None,
+            model: None,
+            keyword_prompt: None,
+        };
+        let (document, warning) = draft(&config(unreachable), "query").await;
+        assert!(document.is_none());
+        assert!(warning.unwrap().contains("failed"));
+    }
+
+    #[tokio::test]
+    async fn the_document_is_embedded_with_the_query_if_configured() {
+        let stub = StubServices::spawn(
+            |text| match text {
+                "document" => vec![0.0, 2.0],
+                _ => vec![1.0, 0.0],
+            },
+            |_| String::new(),
+        )
+        .await;
+        let service = stub.config();
+        let embed = |include_query| {
+            let document = HypotheticalDocument {
+                text: "document".to_string(),
+                include_query,
+            };
+            let service = service.clone();
+            async move {
+                embed_query(
+                    &service,
+                    Some(&document),
+                    "query",
+                    RetrievalMode::Vector,
+                    "qdrant",
+                )
+                .await
+                .unwrap()
+            }
+        };
+
+        assert_eq!(embed(false).await, [0.0, 2.0]);
+        assert_eq!(embed(true).await, [0.5, 0.5]);
+        let query_embedding =
+            embed_query(&service, None, "query", RetrievalMode::Vector, "qdrant").await;
+        assert_eq!(query_embedding.unwrap(), [1.0, 0.0]);
+    }
+}
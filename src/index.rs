@@ -0,0 +1,334 @@
+//! The `index` command, which builds the Tantivy index of the `tantivy` search mode from JSONL and
+//! Markdown files.
+
+use crate::backend::{
+    field_to_string,
+    tantivy::{IndexFields, IndexPayload},
+};
+use anyhow::{Context, anyhow, bail};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+use tantivy::{Index, IndexWriter, TantivyDocument, directory::MmapDirectory};
+use tracing::{info, warn};
+
+/// Memory budget of the index writer
+const WRITER_MEMORY_BUDGET: usize = 100_000_000;
+/// Document field holding the title of a Markdown file
+const MARKDOWN_TITLE_FIELD: &str = "title";
+/// Document field holding the text of a Markdown file
+const MARKDOWN_CONTENT_FIELD: &str = "content";
+
+/// Options of the `index` command
+#[derive(Debug)]
+pub struct IndexOptions {
+    /// Directory of the index, created if it does not exist
+    pub index_dir: PathBuf,
+    /// Files to index, or directories to scan for them recursively
+    pub paths: Vec<PathBuf>,
+    /// Field of a JSONL document holding its id
+    pub id_field: String,
+    /// Fields of a JSONL document whose text is indexed
+    pub search_fields: Vec<String>,
+}
+
+/// The kinds of files the `index` command reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileFormat {
+    /// One JSON object per line
+    Jsonl,
+    /// One document per file
+    Markdown,
+}
+impl FileFormat {
+    fn of(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "jsonl" | "ndjson" => Some(Self::Jsonl),
+            "md" | "markdown" => Some(Self::Markdown),
+            _ => None,
+        }
+    }
+}
+
+/// A Markdown file as stored in the index, with its fields in this order
+#[derive(Serialize)]
+struct MarkdownDocument<'a> {
+    title: &'a str,
+    content: &'a str,
+    path: &'a str,
+}
+
+/// Build the index from the files, replacing the documents it already contains
+///
+/// Returns the number of indexed documents.
+pub fn build_index(options: &IndexOptions) -> anyhow::Result<u64> {
+    let files = collect_files(&options.paths)?;
+    if files.is_empty() {
+        bail!("No JSONL or Markdown files found in {:?}", options.paths);
+    }
+
+    std::fs::create_dir_all(&options.index_dir).with_context(|| {
+        format!(
+            "Failed to create the index directory {}",
+            options.index_dir.display()
+        )
+    })?;
+    let (schema, fields) = IndexFields::schema();
+    let directory = MmapDirectory::open(&options.index_dir)?;
+    let index = Index::open_or_create(directory, schema).map_err(|e| {
+        anyhow!(
+            "Failed to open the index in {}: {e}",
+            options.index_dir.display()
+        )
+    })?;
+
+    let mut writer: IndexWriter = index.writer(WRITER_MEMORY_BUDGET)?;
+    writer.delete_all_documents()?;
+
+    let mut payload = IndexPayload {
+        id_field: options.id_field.clone(),
+        search_fields: options.search_fields.clone(),
+    };
+    let mut count = 0;
+    for (path, format) in files {
+        info!("Indexing {}...", path.display());
+        let documents = match format {
+            FileFormat::Jsonl => read_jsonl(&path, options)?,
+            FileFormat::Markdown => {
+                for field in [MARKDOWN_TITLE_FIELD, MARKDOWN_CONTENT_FIELD] {
+                    if !payload.search_fields.iter().any(|f| f == field) {
+                        payload.search_fields.push(field.to_string());
+                    }
+                }
+                vec![read_markdown(&path)?]
+            }
+        };
+
+        for (id, text, document) in documents {
+            let mut doc = TantivyDocument::new();
+            doc.add_text(fields.id, id);
+            doc.add_text(fields.text, text);
+            doc.add_text(fields.document, document);
+            writer.add_document(doc)?;
+            count += 1;
+        }
+    }
+
+    let mut commit = writer.prepare_commit()?;
+    commit.set_payload(&serde_json::to_string(&payload)?);
+    commit.commit()?;
+    writer.wait_merging_threads()?;
+
+    Ok(count)
+}
+
+/// Collect the JSONL and Markdown files in the paths, in a stable order
+fn collect_files(paths: &[PathBuf]) -> anyhow::Result<Vec<(PathBuf, FileFormat)>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let mut entries = std::fs::read_dir(path)
+                .with_context(|| format!("Failed to read the directory {}", path.display()))?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            entries.sort();
+            for entry in entries {
+                if entry.is_dir() {
+                    files.extend(collect_files(&[entry])?);
+                } else if let Some(format) = FileFormat::of(&entry) {
+                    files.push((entry, format));
+                }
+            }
+        } else {
+            match FileFormat::of(path) {
+                Some(format) => files.push((path.clone(), format)),
+                None => bail!(
+                    "Unsupported file {}, expected a .jsonl or .md file",
+                    path.display()
+                ),
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Read the documents of a JSONL file as (id, indexed text, document) triples
+///
+/// The document is the line itself, so that its fields keep their original order.
+fn read_jsonl(
+    path: &Path,
+    options: &IndexOptions,
+) -> anyhow::Result<Vec<(String, String, String)>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let mut documents = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let object: Map<String, Value> = serde_json::from_str(line)
+            .with_context(|| format!("Invalid JSON object at {}:{}", path.display(), index + 1))?;
+        let text = options
+            .search_fields
+            .iter()
+            .filter_map(|field| object.get(field).and_then(field_to_string))
+            .collect::<Vec<_>>()
+            .join("\n");
+        if text.trim().is_empty() {
+            warn!(
+                "Skipping {}:{}, it has none of the search fields {:?}",
+                path.display(),
+                index + 1,
+                options.search_fields
+            );
+            continue;
+        }
+        let id = object
+            .get(&options.id_field)
+            .and_then(field_to_string)
+            .unwrap_or_else(|| format!("{}:{}", path.display(), index + 1));
+
+        documents.push((id, text, line.to_string()));
+    }
+
+    Ok(documents)
+}
+
+/// Read a Markdown file as an (id, indexed text, document) triple, identified by its path
+///
+/// The title is the first level-1 heading, or the file name if there is none.
+fn read_markdown(path: &Path) -> anyhow::Result<(String, String, String)> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let path = path.display().to_string();
+
+    let title = content
+        .lines()
+        .find_map(|line| line.strip_prefix("# "))
+        .map(str::trim)
+        .map(str::to_string)
+        .unwrap_or_else(|| {
+            Path::new(&path)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_else(|| path.clone())
+        });
+
+    let document = serde_json::to_string(&MarkdownDocument {
+        title: &title,
+        content: content.trim(),
+        path: &path,
+    })?;
+
+    Ok((path.clone(), format!("{title}\n{content}"), document))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A temporary directory removed at the end of the test
+    struct TestDir(PathBuf);
+    impl TestDir {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("cardea-index-{}-{name}", std::process::id()));
+            std::fs::create_dir_all(dir.join("nested")).unwrap();
+            Self(dir)
+        }
+
+        fn write(&self, name: &str, content: &str) -> PathBuf {
+            let path = self.0.join(name);
+            std::fs::write(&path, content).unwrap();
+            path
+        }
+    }
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn options(dir: &TestDir) -> IndexOptions {
+        IndexOptions {
+            index_dir: dir.0.join("index"),
+            paths: vec![dir.0.clone()],
+            id_field: "id".to_string(),
+            search_fields: vec!["title".to_string(), "body".to_string()],
+        }
+    }
+
+    #[test]
+    fn the_files_are_collected_recursively_in_order() {
+        let dir = TestDir::new("collect");
+        let b = dir.write("b.jsonl", "");
+        let a = dir.write("nested/a.MD", "");
+        dir.write("notes.txt", "");
+
+        let files = collect_files(std::slice::from_ref(&dir.0)).unwrap();
+        assert_eq!(files, [(b, FileFormat::Jsonl), (a, FileFormat::Markdown)]);
+
+        let error = collect_files(&[dir.0.join("notes.txt")]).unwrap_err();
+        assert!(error.to_string().contains("Unsupported file"));
+    }
+
+    #[test]
+    fn the_jsonl_documents_without_search_fields_are_skipped() {
+        let dir = TestDir::new("jsonl");
+        let path = dir.write(
+            "docs.jsonl",
+            "{\"id\": 7, \"title\": \"Rust\", \"lang\": \"en\"}\n\n{\"lang\": \"fr\"}\n{\"body\": \"Bread\"}\n",
+        );
+
+        let documents = read_jsonl(&path, &options(&dir)).unwrap();
+        assert_eq!(
+            documents,
+            [
+                (
+                    "7".to_string(),
+                    "Rust".to_string(),
+                    "{\"id\": 7, \"title\": \"Rust\", \"lang\": \"en\"}".to_string()
+                ),
+                (
+                    format!("{}:4", path.display()),
+                    "Bread".to_string(),
+                    "{\"body\": \"Bread\"}".to_string()
+                ),
+            ]
+        );
+
+        let invalid = dir.write("invalid.jsonl", "{\"title\": \"Rust\"}\nnot json\n");
+        let error = read_jsonl(&invalid, &options(&dir)).unwrap_err();
+        assert!(error.to_string().ends_with("invalid.jsonl:2"));
+    }
+
+    #[test]
+    fn the_markdown_title_is_the_first_heading_or_the_file_name() {
+        let dir = TestDir::new("markdown");
+        let path = dir.write("guide.md", "Intro\n\n## Setup\n# Tokio guide \n\nText\n");
+        let (id, text, document) = read_markdown(&path).unwrap();
+        assert_eq!(id, path.display().to_string());
+        assert!(text.starts_with("Tokio guide\nIntro"));
+        let document: Value = serde_json::from_str(&document).unwrap();
+        assert_eq!(document["title"], "Tokio guide");
+
+        let path = dir.write("no-heading.md", "Just text");
+        let (_, text, _) = read_markdown(&path).unwrap();
+        assert_eq!(text, "no-heading\nJust text");
+    }
+
+    #[test]
+    fn an_index_needs_files() {
+        let dir = TestDir::new("empty");
+        let error = build_index(&options(&dir)).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("No JSONL or Markdown files found")
+        );
+    }
+}
@@ -0,0 +1,257 @@
+//! Throttling of the `search` calls, as each one costs embedding and LLM calls and database
+//! queries.
+//!
+//! Each client gets a token bucket refilled at a fixed rate, and the searches in progress across
+//! all clients are capped. Throttled calls fail with a [`RATE_LIMITED`] error whose data tells the
+//! client when to retry.
+
+use rmcp::{ErrorData as McpError, model::ErrorCode};
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::warn;
+
+/// Error code of the throttled calls, in the range JSON-RPC reserves for server errors
+pub const RATE_LIMITED: ErrorCode = ErrorCode(-32029);
+
+/// Retry delay suggested to the clients turned away because too many searches are in progress
+const CONCURRENCY_RETRY_AFTER: Duration = Duration::from_secs(1);
+/// Number of clients above which the buckets that are full again are forgotten
+const MAX_IDLE_BUCKETS: usize = 10_000;
+
+/// Token bucket rate limit of the searches of each client
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitConfig {
+    /// Searches per minute
+    pub per_minute: u32,
+    /// Searches a client may make at once after being idle
+    pub burst: u32,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+#[derive(Debug)]
+struct RateLimiter {
+    capacity: f64,
+    /// Tokens added per second
+    refill_rate: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+impl RateLimiter {
+    fn new(config: RateLimitConfig) -> Self {
+        Self {
+            capacity: config.burst.max(1) as f64,
+            refill_rate: config.per_minute as f64 / 60.0,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take a token from the bucket of the client, or return how long until one is available
+    fn try_take(&self, client: &str) -> Result<(), Duration> {
+        self.try_take_at(client, Instant::now())
+    }
+
+    fn try_take_at(&self, client: &str, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() > MAX_IDLE_BUCKETS {
+            buckets.retain(|_, bucket| {
+                self.refill(bucket.tokens, now.duration_since(bucket.updated_at)) < self.capacity
+            });
+        }
+
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket {
+            tokens: self.capacity,
+            updated_at: now,
+        });
+        bucket.tokens = self.refill(bucket.tokens, now.duration_since(bucket.updated_at));
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.refill_rate,
+            ))
+        }
+    }
+
+    fn refill(&self, tokens: f64, elapsed: Duration) -> f64 {
+        (tokens + elapsed.as_secs_f64() * self.refill_rate).min(self.capacity)
+    }
+}
+
+/// Held for the duration of a search, so that it counts against the concurrency cap
+#[derive(Debug)]
+pub struct SearchPermit {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+/// Admits or throttles the searches, shared by all sessions
+#[derive(Debug, Clone, Default)]
+pub struct SearchLimiter {
+    rate_limiter: Option<Arc<RateLimiter>>,
+    concurrency: Option<Arc<Semaphore>>,
+}
+impl SearchLimiter {
+    /// Build the limiter. Searches are not rate limited if `rate_limit` is `None`, and not capped
+    /// if `max_concurrent` is `None`
+    pub fn new(rate_limit: Option<RateLimitConfig>, max_concurrent: Option<usize>) -> Self {
+        Self {
+            rate_limiter: rate_limit.map(|config| Arc::new(RateLimiter::new(config))),
+            concurrency: max_concurrent.map(|max| Arc::new(Semaphore::new(max))),
+        }
+    }
+
+    /// Admit a search of the client, or fail with a [`RATE_LIMITED`] error
+    ///
+    /// A search turned away by the concurrency cap does not use up a token of the client.
+    pub fn acquire(&self, client: &str) -> Result<SearchPermit, McpError> {
+        let permit = match &self.concurrency {
+            Some(semaphore) => match semaphore.clone().try_acquire_owned() {
+                Ok(permit) => Some(permit),
+                Err(_) => {
+                    return Err(throttled(
+                        client,
+                        "Too many searches in progress",
+                        CONCURRENCY_RETRY_AFTER,
+                    ));
+                }
+            },
+            None => None,
+        };
+
+        if let Some(rate_limiter) = &self.rate_limiter
+            && let Err(retry_after) = rate_limiter.try_take(client)
+        {
+            return Err(throttled(client, "Rate limit exceeded", retry_after));
+        }
+
+        Ok(SearchPermit { _permit: permit })
+    }
+}
+
+fn throttled(client: &str, reason: &str, retry_after: Duration) -> McpError {
+    // round up, so that a client retrying after the advertised delay is admitted
+    let retry_after_secs = retry_after.as_secs_f64().max(0.001);
+    let retry_after_secs = (retry_after_secs * 1000.0).ceil() / 1000.0;
+    let error_message = format!("{reason}, retry after {retry_after_secs:.3} seconds");
+    warn!("Throttled the search of {}: {}", client, error_message);
+
+    McpError::new(
+        RATE_LIMITED,
+        error_message,
+        Some(json!({ "retry_after_secs": retry_after_secs })),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rate_limiter(per_minute: u32, burst: u32) -> RateLimiter {
+        RateLimiter::new(RateLimitConfig { per_minute, burst })
+    }
+
+    #[test]
+    fn the_burst_is_admitted_then_throttled() {
+        let limiter = rate_limiter(60, 3);
+        let now = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(limiter.try_take_at("client", now), Ok(()));
+        }
+        assert_eq!(
+            limiter.try_take_at("client", now),
+            Err(Duration::from_secs(1))
+        );
+    }
+
+    #[test]
+    fn the_tokens_are_refilled_at_the_rate() {
+        let limiter = rate_limiter(60, 1);
+        let now = Instant::now();
+        assert_eq!(limiter.try_take_at("client", now), Ok(()));
+
+        let later = now + Duration::from_millis(250);
+        assert_eq!(
+            limiter.try_take_at("client", later),
+            Err(Duration::from_millis(750))
+        );
+        assert_eq!(
+            limiter.try_take_at("client", now + Duration::from_secs(1)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn the_refill_is_capped_by_the_burst() {
+        let limiter = rate_limiter(60, 2);
+        let now = Instant::now();
+        assert_eq!(limiter.try_take_at("client", now), Ok(()));
+
+        let later = now + Duration::from_secs(3600);
+        assert_eq!(limiter.try_take_at("client", later), Ok(()));
+        assert_eq!(limiter.try_take_at("client", later), Ok(()));
+        assert!(limiter.try_take_at("client", later).is_err());
+    }
+
+    #[test]
+    fn each_client_has_its_own_bucket() {
+        let limiter = rate_limiter(60, 1);
+        let now = Instant::now();
+        assert_eq!(limiter.try_take_at("alice", now), Ok(()));
+        assert!(limiter.try_take_at("alice", now).is_err());
+        assert_eq!(limiter.try_take_at("bob", now), Ok(()));
+    }
+
+    #[test]
+    fn a_zero_burst_admits_one_search() {
+        let limiter = rate_limiter(60, 0);
+        let now = Instant::now();
+        assert_eq!(limiter.try_take_at("client", now), Ok(()));
+        assert!(limiter.try_take_at("client", now).is_err());
+    }
+
+    #[test]
+    fn the_full_buckets_are_forgotten_past_the_limit() {
+        let limiter = rate_limiter(60, 1);
+        let now = Instant::now();
+        for client in 0..=MAX_IDLE_BUCKETS {
+            limiter.try_take_at(&client.to_string(), now).unwrap();
+        }
+        assert_eq!(limiter.buckets.lock().unwrap().len(), MAX_IDLE_BUCKETS + 1);
+
+        // the buckets refilled by then are dropped, the one of the client is kept
+        let later = now + Duration::from_secs(1);
+        limiter.try_take_at("0", later).unwrap();
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 1);
+        assert!(limiter.try_take_at("0", later).is_err());
+    }
+
+    #[test]
+    fn searches_beyond_the_cap_do_not_use_up_tokens() {
+        let limiter = SearchLimiter::new(
+            Some(RateLimitConfig {
+                per_minute: 60,
+                burst: 1,
+            }),
+            Some(1),
+        );
+        let permit = limiter.acquire("alice").unwrap();
+        let error = limiter.acquire("bob").unwrap_err();
+        assert_eq!(error.code, RATE_LIMITED);
+        assert_eq!(error.data, Some(json!({ "retry_after_secs": 1.0 })));
+
+        drop(permit);
+        assert!(limiter.acquire("bob").is_ok());
+    }
+}
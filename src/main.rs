@@ -1,116 +1,445 @@
+mod auth;
+mod backend;
+mod config;
+mod drain;
+mod filter;
+mod fusion;
+mod health;
+mod hyde;
+mod index;
+mod limit;
+mod metrics;
+mod mmr;
+mod reload;
+mod rerank;
+mod rewrite;
 mod search;
+mod services;
+mod sql;
+mod sse;
+mod telemetry;
+#[cfg(test)]
+mod test_support;
 mod types;
 
 use anyhow::{anyhow, bail};
-use clap::{Parser, Subcommand};
+use backend::{
+    SearchBackend,
+    postgres::PostgresBackend,
+    qdrant::QdrantBackend,
+    tantivy::{IndexPayload, TantivyBackend},
+    tidb::TidbBackend,
+};
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, parser::ValueSource};
+use config::{
+    AuthSettings, Config, IndexSettings, LimitSettings, PostgresSettings, QdrantSettings,
+    SearchSettings, ServerSettings, ServiceSettings, TantivySettings, TidbSettings,
+};
+use drain::TrackedSessionManager;
+use fusion::{DEFAULT_RRF_K, FusionConfig, FusionMethod};
+use health::{MonitoredBackend, StatusRegistry};
+use hyde::HydeConfig;
+use mmr::{DEFAULT_MMR_OVERFETCH, MmrConfig};
 use mysql::*;
-use regex::Regex;
-use rmcp::transport::streamable_http_server::{
-    StreamableHttpServerConfig, StreamableHttpService, session::local::LocalSessionManager,
+use rerank::{
+    DEFAULT_LLM_RERANK_MAX_CANDIDATES, DEFAULT_LLM_RERANK_MAX_TOKENS, DEFAULT_RERANK_OVERFETCH,
+    RerankConfig, RerankMethod, Reranker,
+};
+use rewrite::RewriteConfig;
+use rmcp::{
+    ServiceExt,
+    transport::streamable_http_server::{
+        StreamableHttpServerConfig, StreamableHttpService, session::local::LocalSessionManager,
+    },
+};
+use rustls::{
+    crypto::{CryptoProvider, ring::default_provider},
+    pki_types::{CertificateDer, pem::PemObject},
 };
-use rustls::crypto::{CryptoProvider, ring::default_provider};
 use search::AgenticSearchServer;
-use std::{env, path::PathBuf};
-use tracing::{error, info};
-use tracing_subscriber::{self, layer::SubscriberExt, util::SubscriberInitExt};
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::watch;
+use tokio_postgres::NoTls;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tracing::{error, info, warn};
+use tracing_subscriber::{self, Layer, layer::SubscriberExt, util::SubscriberInitExt};
 
+/// The settings that [`Config::validate`] checks are set
+const VALIDATED: &str = "the configuration is validated";
 const DEFAULT_SOCKET_ADDR: &str = "127.0.0.1:8009";
 const DEFAULT_QDRANT_BASE_URL: &str = "http://127.0.0.1:6333";
+const DEFAULT_BACKEND_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 20;
+const DEFAULT_TIDB_SEARCH_FIELD: &str = "content";
+const DEFAULT_TIDB_ID_FIELD: &str = "id";
+const DEFAULT_LIMIT: u64 = 10;
+const DEFAULT_VECTOR_SCORE_THRESHOLD: f32 = 0.5;
+const DEFAULT_KEYWORD_SCORE_THRESHOLD: f32 = 0.0;
+const DEFAULT_MAX_LIMIT: u64 = 100;
+const DEFAULT_MAX_OFFSET: u64 = 1000;
+const DEFAULT_RATE_LIMIT_BURST: u32 = 10;
+const DEFAULT_POSTGRES_ID_FIELD: &str = "id";
+const DEFAULT_POSTGRES_TEXT_SEARCH_CONFIG: &str = "english";
+const DEFAULT_TANTIVY_ID_FIELD: &str = "id";
+const DEFAULT_TANTIVY_TITLE_FIELD: &str = "title";
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "Cardea Agentic Search MCP server")]
 struct Args {
+    /// Configuration file declaring the backends, services, limits and prompts, in TOML or YAML. Replaces the search mode subcommands, and is overridden by the other arguments and the env vars (can be overridden by CONFIG_FILE env var)
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+    /// Profile of the configuration file to apply over its other settings, e.g. `staging` or `prod` (can be overridden by CONFIG_PROFILE env var)
+    #[arg(long)]
+    profile: Option<String>,
     /// Socket address to bind to
     #[arg(short, long, default_value = DEFAULT_SOCKET_ADDR)]
     socket_addr: String,
-    /// Search mode to enable
+    /// Transport type to serve the MCP server over
+    #[arg(short, long, value_enum, default_value_t = TransportType::StreamHttp)]
+    transport: TransportType,
+    /// Seconds to wait for the tool calls in progress on SIGINT or SIGTERM before closing the sessions
+    #[arg(long, default_value_t = DEFAULT_SHUTDOWN_TIMEOUT_SECS)]
+    shutdown_timeout: u64,
+    /// Maximum number of results a single `search` call may request
+    #[arg(long, default_value_t = DEFAULT_MAX_LIMIT)]
+    max_limit: u64,
+    /// Maximum pagination offset a single `search` call may request
+    #[arg(long, default_value_t = DEFAULT_MAX_OFFSET)]
+    max_offset: u64,
+    /// Searches per minute allowed to each client, identified by its API key or JWT subject if it authenticates, by its IP address otherwise. Not limited if 0
+    #[arg(long, default_value_t = 0)]
+    rate_limit: u32,
+    /// Searches a client may make at once after being idle, on top of its rate limit
+    #[arg(long, default_value_t = DEFAULT_RATE_LIMIT_BURST)]
+    rate_limit_burst: u32,
+    /// Maximum number of searches in progress across all clients. Not limited if 0
+    #[arg(long, default_value_t = 0)]
+    max_concurrent_searches: usize,
+    /// Base URL of the OTLP/HTTP collector to export traces to, e.g. http://localhost:4318. Traces are not exported if not set
+    #[arg(long)]
+    otlp_endpoint: Option<String>,
+    #[command(flatten)]
+    auth: AuthArgs,
+    #[command(flatten)]
+    rerank: RerankArgs,
+    #[command(flatten)]
+    mmr: MmrArgs,
+    #[command(flatten)]
+    rewrite: RewriteArgs,
+    #[command(flatten)]
+    hyde: HydeArgs,
+    /// Search mode to enable, unless a configuration file is given
     #[command(subcommand)]
-    search_mode: SearchMode,
+    search_mode: Option<SearchMode>,
+}
+
+/// The transports the MCP server can be served over
+#[derive(
+    clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+enum TransportType {
+    /// Standard input and output, for clients that launch the server as a subprocess
+    Stdio,
+    /// HTTP with Server-Sent Events, at `/sse` and `/message`
+    Sse,
+    /// Streamable HTTP, at `/mcp`
+    StreamHttp,
 }
 
 #[derive(Subcommand, Debug)]
 enum SearchMode {
     /// Enable vector search only
     Qdrant {
-        /// Name of the collection to search in Qdrant (can be overridden by QDRANT_COLLECTION env var)
-        #[arg(long, required = false)]
-        qdrant_collection: Option<String>,
-        /// The name of the field in the payload that contains the source of the document (can be overridden by QDRANT_PAYLOAD_FIELD env var)
-        #[arg(long, required = false)]
-        qdrant_payload_field: Option<String>,
+        #[command(flatten)]
+        qdrant: QdrantArgs,
         /// Maximum number of results to return
-        #[arg(long, default_value = "10")]
+        #[arg(long, default_value_t = DEFAULT_LIMIT)]
         limit: u64,
         /// Score threshold for the results
-        #[arg(long, default_value = "0.5")]
+        #[arg(long, default_value_t = DEFAULT_VECTOR_SCORE_THRESHOLD)]
         score_threshold: f32,
-        /// The base URL of the embedding server, e.g., "https://api.openai.com/v1" (can be overridden by EMBEDDING_SERVICE_BASE_URL env var)
-        #[arg(long, required = false)]
-        embedding_service_base_url: Option<String>,
     },
     /// Enable keyword search only
     Tidb {
-        /// Path to the SSL CA certificate. On macOS, this is typically
-        /// `/etc/ssl/cert.pem`. On Debian/Ubuntu/Arch Linux, it's typically
-        /// `/etc/ssl/certs/ca-certificates.crt`. (can be overridden by TIDB_SSL_CA env var)
-        #[arg(long, required = false)]
-        tidb_ssl_ca: Option<PathBuf>,
-        /// Table name to search in TiDB (can be overridden by TIDB_TABLE_NAME env var)
-        #[arg(long, required = false)]
-        tidb_table_name: Option<String>,
-        /// Field name for full-text search content (can be overridden by TIDB_SEARCH_FIELD env var)
-        #[arg(long, required = false)]
-        tidb_search_field: Option<String>,
-        /// Field names to return from TiDB query results, comma-separated (can be overridden by TIDB_RETURN_FIELD env var)
-        #[arg(long, value_delimiter = ',', required = false)]
-        tidb_return_field: Option<Vec<String>>,
+        #[command(flatten)]
+        tidb: TidbArgs,
         /// Maximum number of results to return
-        #[arg(long, default_value = "10")]
+        #[arg(long, default_value_t = DEFAULT_LIMIT)]
         limit: u64,
-        /// Score threshold for the results
-        #[arg(long, default_value = "0.5")]
+        /// Minimum `fts_match_word` relevance score for the results
+        #[arg(long, default_value_t = DEFAULT_KEYWORD_SCORE_THRESHOLD)]
+        score_threshold: f32,
+    },
+    /// Enable vector, keyword or hybrid search in a Postgres table
+    Postgres {
+        #[command(flatten)]
+        postgres: PostgresArgs,
+        /// Maximum number of results to return
+        #[arg(long, default_value_t = DEFAULT_LIMIT)]
+        limit: u64,
+        /// Minimum cosine similarity for the vector search results
+        #[arg(long, default_value_t = DEFAULT_VECTOR_SCORE_THRESHOLD)]
         score_threshold: f32,
-        /// The base URL of the chat server, e.g., "https://api.openai.com/v1" (can be overridden by CHAT_SERVICE_BASE_URL env var)
+        /// Minimum `ts_rank` relevance score for the full-text search results
+        #[arg(long, default_value_t = DEFAULT_KEYWORD_SCORE_THRESHOLD)]
+        keyword_score_threshold: f32,
+        /// The `k` constant of the Reciprocal Rank Fusion of hybrid searches
+        #[arg(long, default_value_t = DEFAULT_RRF_K)]
+        rrf_k: f64,
+    },
+    /// Enable keyword search in a local Tantivy index
+    Tantivy {
+        #[command(flatten)]
+        tantivy: TantivyArgs,
+        /// Maximum number of results to return
+        #[arg(long, default_value_t = DEFAULT_LIMIT)]
+        limit: u64,
+        /// Minimum BM25 relevance score for the results
+        #[arg(long, default_value_t = DEFAULT_KEYWORD_SCORE_THRESHOLD)]
+        score_threshold: f32,
+    },
+    /// Build the Tantivy index of the `tantivy` search mode from JSONL or Markdown files, then exit
+    Index {
+        /// Directory of the Tantivy index, created if it does not exist. Documents already in the index are replaced (can be overridden by TANTIVY_INDEX_DIR env var)
         #[arg(long, required = false)]
-        chat_service_base_url: Option<String>,
+        tantivy_index_dir: Option<PathBuf>,
+        /// JSONL or Markdown files to index, or directories to scan for them recursively
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Field of a JSONL document that identifies it, reported as the document id. Documents without it are identified by their file and line number
+        #[arg(long, default_value = DEFAULT_TANTIVY_ID_FIELD)]
+        id_field: String,
+        /// Fields of a JSONL document to index for full-text search, comma-separated. Markdown files are indexed as a whole
+        #[arg(long, value_delimiter = ',', default_value = "title,content")]
+        search_field: Vec<String>,
+    },
+    /// Generate an API key for the clients of the HTTP transports and print its hashed entry
+    GenerateApiKey {
+        /// Name of the API key, recorded in the logs as the identity of the clients using it
+        name: String,
     },
     /// Enable both vector and keyword search
     Search {
-        /// Name of the collection to search in Qdrant (can be overridden by QDRANT_COLLECTION env var)
-        #[arg(long, required = false)]
-        qdrant_collection: Option<String>,
-        /// The name of the field in the payload that contains the source of the document (can be overridden by QDRANT_PAYLOAD_FIELD env var)
-        #[arg(long, required = false)]
-        qdrant_payload_field: Option<String>,
-        /// Path to the SSL CA certificate. On macOS, this is typically
-        /// `/etc/ssl/cert.pem`. On Debian/Ubuntu/Arch Linux, it's typically
-        /// `/etc/ssl/certs/ca-certificates.crt`. (can be overridden by TIDB_SSL_CA env var)
-        #[arg(long, required = false)]
-        tidb_ssl_ca: Option<PathBuf>,
-        /// Table name to search in TiDB (can be overridden by TIDB_TABLE_NAME env var)
-        #[arg(long, required = false)]
-        tidb_table_name: Option<String>,
-        /// Field name for full-text search content (can be overridden by TIDB_SEARCH_FIELD env var)
-        #[arg(long, required = false)]
-        tidb_search_field: Option<String>,
-        /// Field names to return from TiDB query results, comma-separated (can be overridden by TIDB_RETURN_FIELD env var)
-        #[arg(long, value_delimiter = ',', required = false)]
-        tidb_return_field: Option<Vec<String>>,
+        #[command(flatten)]
+        qdrant: QdrantArgs,
+        #[command(flatten)]
+        tidb: TidbArgs,
         /// Maximum number of results to return
-        #[arg(long, default_value = "10")]
+        #[arg(long, default_value_t = DEFAULT_LIMIT)]
         limit: u64,
-        /// Score threshold for the results
-        #[arg(long, default_value = "0.5")]
+        /// Score threshold for the vector search results
+        #[arg(long, default_value_t = DEFAULT_VECTOR_SCORE_THRESHOLD)]
         score_threshold: f32,
-        /// The base URL of the chat server, e.g., "https://api.openai.com/v1" (can be overridden by CHAT_SERVICE_BASE_URL env var)
-        #[arg(long, required = false)]
-        chat_service_base_url: Option<String>,
-        /// The base URL of the embedding server, e.g., "https://api.openai.com/v1" (can be overridden by EMBEDDING_SERVICE_BASE_URL env var)
-        #[arg(long, required = false)]
-        embedding_service_base_url: Option<String>,
+        /// Minimum `fts_match_word` relevance score for the keyword search results
+        #[arg(long, default_value_t = DEFAULT_KEYWORD_SCORE_THRESHOLD)]
+        keyword_score_threshold: f32,
+        /// Method used to fuse the vector and keyword search results
+        #[arg(long, value_enum, default_value_t = FusionMethod::Rrf)]
+        fusion_method: FusionMethod,
+        /// The `k` constant of Reciprocal Rank Fusion. Larger values flatten the rank differences
+        #[arg(long, default_value_t = DEFAULT_RRF_K)]
+        rrf_k: f64,
+        /// Weight of a backend during fusion, in the form `<BACKEND>=<WEIGHT>`, e.g. `qdrant=1.0` or `tidb=0.5`, where the backend must be enabled. Can be repeated; unlisted backends weigh 1.0
+        #[arg(long = "fusion-weight", value_parser = parse_fusion_weight)]
+        fusion_weights: Vec<(String, f64)>,
+        /// Timeout in seconds for the search in each backend. A backend that fails or times out is skipped with a warning
+        #[arg(long, default_value_t = DEFAULT_BACKEND_TIMEOUT_SECS)]
+        backend_timeout: u64,
     },
 }
 
+/// Options of the Qdrant vector search backend
+#[derive(clap::Args, Debug)]
+struct QdrantArgs {
+    /// Name of the collection to search in Qdrant (can be overridden by QDRANT_COLLECTION env var)
+    #[arg(long, required = false)]
+    qdrant_collection: Option<String>,
+    /// The name of the field in the payload that contains the source of the document (can be overridden by QDRANT_PAYLOAD_FIELD env var)
+    #[arg(long, required = false)]
+    qdrant_payload_field: Option<String>,
+    /// The name of the field in the payload that contains the title of the document (can be overridden by QDRANT_TITLE_FIELD env var)
+    #[arg(long, required = false)]
+    qdrant_title_field: Option<String>,
+    /// Payload fields to return as metadata, comma-separated. Defaults to all other payload fields (can be overridden by QDRANT_METADATA_FIELD env var)
+    #[arg(long, value_delimiter = ',', required = false)]
+    qdrant_metadata_field: Option<Vec<String>>,
+    /// Payload fields that `search` calls may filter on, comma-separated. Filtering is disabled if empty (can be overridden by QDRANT_FILTERABLE_FIELD env var)
+    #[arg(long, value_delimiter = ',', required = false)]
+    qdrant_filterable_field: Option<Vec<String>>,
+    /// The base URL of the embedding server, e.g., "https://api.openai.com/v1" (can be overridden by EMBEDDING_SERVICE_BASE_URL env var)
+    #[arg(long, required = false)]
+    embedding_service_base_url: Option<String>,
+}
+
+/// Options of the TiDB keyword search backend
+#[derive(clap::Args, Debug)]
+struct TidbArgs {
+    /// Path to the SSL CA certificate. On macOS, this is typically
+    /// `/etc/ssl/cert.pem`. On Debian/Ubuntu/Arch Linux, it's typically
+    /// `/etc/ssl/certs/ca-certificates.crt`. (can be overridden by TIDB_SSL_CA env var)
+    #[arg(long, required = false)]
+    tidb_ssl_ca: Option<PathBuf>,
+    /// Table name to search in TiDB (can be overridden by TIDB_TABLE_NAME env var)
+    #[arg(long, required = false)]
+    tidb_table_name: Option<String>,
+    /// Field name for full-text search content (can be overridden by TIDB_SEARCH_FIELD env var)
+    #[arg(long, required = false)]
+    tidb_search_field: Option<String>,
+    /// Field names to return from TiDB query results, comma-separated (can be overridden by TIDB_RETURN_FIELD env var)
+    #[arg(long, value_delimiter = ',', required = false)]
+    tidb_return_field: Option<Vec<String>>,
+    /// Field that identifies a row, reported as the document id (can be overridden by TIDB_ID_FIELD env var)
+    #[arg(long, required = false)]
+    tidb_id_field: Option<String>,
+    /// Field that contains the title of a row (can be overridden by TIDB_TITLE_FIELD env var)
+    #[arg(long, required = false)]
+    tidb_title_field: Option<String>,
+    /// Returned fields to report as metadata, comma-separated. Defaults to all returned fields except the search, id and title fields (can be overridden by TIDB_METADATA_FIELD env var)
+    #[arg(long, value_delimiter = ',', required = false)]
+    tidb_metadata_field: Option<Vec<String>>,
+    /// The base URL of the chat server, e.g., "https://api.openai.com/v1" (can be overridden by CHAT_SERVICE_BASE_URL env var)
+    #[arg(long, required = false)]
+    chat_service_base_url: Option<String>,
+}
+
+/// Options of the Postgres backend
+#[derive(clap::Args, Debug)]
+struct PostgresArgs {
+    /// Path to the SSL CA certificate. Connections are not encrypted if unset (can be overridden by POSTGRES_SSL_CA env var)
+    #[arg(long, required = false)]
+    postgres_ssl_ca: Option<PathBuf>,
+    /// Table name to search in Postgres (can be overridden by POSTGRES_TABLE_NAME env var)
+    #[arg(long, required = false)]
+    postgres_table_name: Option<String>,
+    /// pgvector column holding the document embeddings. Enables vector search (can be overridden by POSTGRES_EMBEDDING_FIELD env var)
+    #[arg(long, required = false)]
+    postgres_embedding_field: Option<String>,
+    /// `tsvector` column holding the full-text index of the documents. Enables full-text search (can be overridden by POSTGRES_TSVECTOR_FIELD env var)
+    #[arg(long, required = false)]
+    postgres_tsvector_field: Option<String>,
+    /// Text search configuration used to parse the keywords, e.g. `english`, `simple` or `pg_catalog.english` (can be overridden by POSTGRES_TEXT_SEARCH_CONFIG env var)
+    #[arg(long, required = false)]
+    postgres_text_search_config: Option<String>,
+    /// Field names to return from Postgres query results, comma-separated (can be overridden by POSTGRES_RETURN_FIELD env var)
+    #[arg(long, value_delimiter = ',', required = false)]
+    postgres_return_field: Option<Vec<String>>,
+    /// Field that identifies a row, reported as the document id (can be overridden by POSTGRES_ID_FIELD env var)
+    #[arg(long, required = false)]
+    postgres_id_field: Option<String>,
+    /// Field that contains the title of a row (can be overridden by POSTGRES_TITLE_FIELD env var)
+    #[arg(long, required = false)]
+    postgres_title_field: Option<String>,
+    /// Returned fields to report as metadata, comma-separated. Defaults to all returned fields except the id and title fields (can be overridden by POSTGRES_METADATA_FIELD env var)
+    #[arg(long, value_delimiter = ',', required = false)]
+    postgres_metadata_field: Option<Vec<String>>,
+    /// The base URL of the embedding server, required for vector search (can be overridden by EMBEDDING_SERVICE_BASE_URL env var)
+    #[arg(long, required = false)]
+    embedding_service_base_url: Option<String>,
+    /// The base URL of the chat server, required for full-text search (can be overridden by CHAT_SERVICE_BASE_URL env var)
+    #[arg(long, required = false)]
+    chat_service_base_url: Option<String>,
+}
+
+/// Options of the Tantivy keyword search backend
+#[derive(clap::Args, Debug)]
+struct TantivyArgs {
+    /// Directory of the Tantivy index built by the `index` command (can be overridden by TANTIVY_INDEX_DIR env var)
+    #[arg(long, required = false)]
+    tantivy_index_dir: Option<PathBuf>,
+    /// Document fields to return, comma-separated (can be overridden by TANTIVY_RETURN_FIELD env var)
+    #[arg(long, value_delimiter = ',', required = false)]
+    tantivy_return_field: Option<Vec<String>>,
+    /// Document field that contains the title of a document (can be overridden by TANTIVY_TITLE_FIELD env var)
+    #[arg(long, required = false)]
+    tantivy_title_field: Option<String>,
+    /// Returned fields to report as metadata, comma-separated. Defaults to all returned fields except the indexed, id and title fields (can be overridden by TANTIVY_METADATA_FIELD env var)
+    #[arg(long, value_delimiter = ',', required = false)]
+    tantivy_metadata_field: Option<Vec<String>>,
+    /// The base URL of the chat server used to extract the keywords of the query. The query is searched as is if unset (can be overridden by CHAT_SERVICE_BASE_URL env var)
+    #[arg(long, required = false)]
+    chat_service_base_url: Option<String>,
+}
+
+/// Authentication options of the HTTP transports. Clients are not authenticated if neither API keys nor a JWKS file are set
+#[derive(clap::Args, Debug)]
+struct AuthArgs {
+    /// File of API key entries, one `<name>:<sha256>` per line, as printed by the `generate-api-key` command (can be overridden by AUTH_API_KEYS_FILE env var). Entries can also be set, comma-separated, in the AUTH_API_KEYS env var
+    #[arg(long, required = false)]
+    auth_api_keys_file: Option<PathBuf>,
+    /// JWKS file with the public keys that sign the accepted JWTs (can be overridden by AUTH_JWKS_FILE env var)
+    #[arg(long, required = false)]
+    auth_jwks_file: Option<PathBuf>,
+    /// Accepted `iss` claims of the JWTs, comma-separated. Not checked if unset (can be overridden by AUTH_JWT_ISSUER env var)
+    #[arg(long, value_delimiter = ',', required = false)]
+    auth_jwt_issuer: Option<Vec<String>>,
+    /// Accepted `aud` claims of the JWTs, comma-separated. Not checked if unset (can be overridden by AUTH_JWT_AUDIENCE env var)
+    #[arg(long, value_delimiter = ',', required = false)]
+    auth_jwt_audience: Option<Vec<String>>,
+}
+
+/// Options of the reranking of the results, which is disabled unless a rerank method or the
+/// rerank service is set
+#[derive(clap::Args, Debug)]
+struct RerankArgs {
+    /// Reranker of the results: a cross-encoder of the rerank service, or the chat model of the chat service. Defaults to `cross-encoder` if the rerank service is set
+    #[arg(long, value_enum, required = false)]
+    rerank_method: Option<RerankMethod>,
+    /// The base URL of the rerank server serving `/rerank`, e.g., "http://localhost:8080/v1" (can be overridden by RERANK_SERVICE_BASE_URL env var)
+    #[arg(long, required = false)]
+    rerank_service_base_url: Option<String>,
+    /// Number of candidates retrieved per requested result, to be reranked
+    #[arg(long, default_value_t = DEFAULT_RERANK_OVERFETCH)]
+    rerank_overfetch: u64,
+    /// Number of candidates the chat model orders with the `llm` reranker, the others follow in retrieval order
+    #[arg(long, default_value_t = DEFAULT_LLM_RERANK_MAX_CANDIDATES)]
+    llm_rerank_max_candidates: u64,
+    /// Number of tokens of the passages given to the chat model with the `llm` reranker, shared evenly by the candidates
+    #[arg(long, default_value_t = DEFAULT_LLM_RERANK_MAX_TOKENS)]
+    llm_rerank_max_tokens: u64,
+}
+
+/// Options of the diversification of the results with Maximal Marginal Relevance
+#[derive(clap::Args, Debug)]
+struct MmrArgs {
+    /// Trade-off between relevance (1.0) and diversity (0.0) of the results of the `search` calls that do not set one. The results are not diversified if unset
+    #[arg(long, required = false)]
+    mmr_lambda: Option<f64>,
+    /// Number of candidates retrieved per requested result, to be diversified
+    #[arg(long, default_value_t = DEFAULT_MMR_OVERFETCH)]
+    mmr_overfetch: u64,
+}
+
+/// Options of the rewriting of the queries into several variants with the chat service
+#[derive(clap::Args, Debug)]
+struct RewriteArgs {
+    /// Number of variants of the query the chat service rewrites it into, each searched in the backends and fused with the others. The query is not rewritten if 0
+    #[arg(long, default_value_t = 0)]
+    query_variants: u64,
+    /// Search the variants of the query only, without the original query
+    #[arg(long)]
+    exclude_original_query: bool,
+}
+
+/// Options of the hypothetical document embeddings (HyDE) of the vector search
+#[derive(clap::Args, Debug)]
+struct HydeArgs {
+    /// Embed a hypothetical document the chat service drafts for the query, instead of the query itself, in the vector backends
+    #[arg(long)]
+    hyde: bool,
+    /// Average the embedding of the hypothetical document with the embedding of the query
+    #[arg(long)]
+    hyde_include_query: bool,
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // Load .env file in development mode only
@@ -125,580 +454,1013 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    // The configuration chooses where the logs go, so resolve it before logging starts
+    let config_file = env::var("CONFIG_FILE")
+        .ok()
+        .map(PathBuf::from)
+        .or(args.config.clone());
+    let profile = env::var("CONFIG_PROFILE").ok().or(args.profile.clone());
+    let config = match &config_file {
+        Some(config_file) => {
+            if let Some(
+                SearchMode::Qdrant { .. }
+                | SearchMode::Tidb { .. }
+                | SearchMode::Postgres { .. }
+                | SearchMode::Tantivy { .. }
+                | SearchMode::Search { .. },
+            ) = &args.search_mode
+            {
+                bail!(
+                    "The configuration file {} declares the search backends, remove the search mode subcommand",
+                    config_file.display()
+                );
+            }
+            Config::load(config_file, profile.as_deref())?
+        }
+        None => {
+            if args.search_mode.is_none() {
+                bail!("A search mode subcommand or a configuration file (--config) is required");
+            }
+            if profile.is_some() {
+                bail!("--profile requires a configuration file (--config)");
+            }
+            Config::default()
+        }
+    };
+    let command_line = command_line_config(&args, &matches);
+    let mut config = config.merge(command_line.clone())?;
+
+    // In stdio mode, stdout carries the protocol messages, so log to stderr
+    let transport = config.server.transport.unwrap_or(TransportType::StreamHttp);
+    let log_layer = tracing_subscriber::fmt::layer().with_line_number(true);
+    let log_layer = match transport {
+        TransportType::Stdio => log_layer.with_writer(std::io::stderr).boxed(),
+        _ => log_layer.boxed(),
+    };
+    // The exporter is a layer of the subscriber, so resolve its endpoint before logging starts
+    let otlp_endpoint = env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+        .ok()
+        .or(config.server.otlp_endpoint.clone());
+    let tracer_provider = otlp_endpoint
+        .as_deref()
+        .map(telemetry::init_tracer_provider)
+        .transpose()?;
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()),
         )
-        .with(tracing_subscriber::fmt::layer().with_line_number(true))
+        .with(log_layer)
+        .with(tracer_provider.as_ref().map(telemetry::layer))
         .init();
+    if let Some(otlp_endpoint) = &otlp_endpoint {
+        info!(
+            "Exporting traces to the OTLP collector at {}",
+            otlp_endpoint
+        );
+    }
 
-    let args = Args::parse();
+    // Build the Tantivy index and exit, without starting the server
+    if let Some(SearchMode::Index {
+        tantivy_index_dir,
+        paths,
+        id_field,
+        search_field,
+    }) = args.search_mode
+    {
+        // Determine index directory with priority: Environment Variable > Command Line > Configuration File
+        let index_dir = optional_env_or_arg(
+            "TANTIVY_INDEX_DIR",
+            tantivy_index_dir.map(|dir| dir.display().to_string()),
+        )
+        .map(PathBuf::from)
+        .or_else(|| config.tantivy.and_then(|tantivy| tantivy.index_dir))
+        .ok_or_else(|| {
+            anyhow!(
+                "TANTIVY_INDEX_DIR environment variable or --tantivy-index-dir argument is required"
+            )
+        })?;
 
-    // Determine search mode and configure connection
-    let search_config = match args.search_mode {
-        SearchMode::Qdrant {
-            qdrant_collection,
-            qdrant_payload_field,
-            limit,
-            score_threshold,
-            embedding_service_base_url,
-        } => {
-            info!("Enabling vector search mode");
-
-            // Determine collection with priority: Environment Variable > Command Line > Error
-            let qdrant_collection = match env::var("QDRANT_COLLECTION") {
-                Ok(env_value) => {
-                    info!("Using QDRANT_COLLECTION from environment: {}", env_value);
-                    env_value
-                }
-                Err(_) => match qdrant_collection {
-                    Some(arg_value) => {
-                        info!(
-                            "Using qdrant_collection from command line argument: {}",
-                            arg_value
-                        );
-                        arg_value
-                    }
-                    None => {
-                        bail!(
-                            "QDRANT_COLLECTION environment variable or --qdrant-collection argument is required"
-                        );
-                    }
-                },
-            };
+        let count = index::build_index(&index::IndexOptions {
+            index_dir: index_dir.clone(),
+            paths,
+            id_field,
+            search_fields: search_field,
+        })?;
+        info!("Indexed {} documents into {}", count, index_dir.display());
 
-            // Determine payload field with priority: Environment Variable > Command Line > Error
-            let qdrant_payload_field = match env::var("QDRANT_PAYLOAD_FIELD") {
-                Ok(env_value) => {
-                    info!("Using QDRANT_PAYLOAD_FIELD from environment: {}", env_value);
-                    env_value
-                }
-                Err(_) => match qdrant_payload_field {
-                    Some(arg_value) => {
-                        info!(
-                            "Using qdrant_payload_field from command line argument: {}",
-                            arg_value
-                        );
-                        arg_value
-                    }
-                    None => {
-                        bail!(
-                            "QDRANT_PAYLOAD_FIELD environment variable or --qdrant-payload-field argument is required"
-                        );
-                    }
+        return Ok(());
+    }
+
+    if let Some(SearchMode::GenerateApiKey { name }) = &args.search_mode {
+        let (api_key, entry) = auth::generate_api_key(name)?;
+        println!("API key, give it to the client (it is not stored anywhere):\n{api_key}");
+        println!("Entry to add to the API key file or AUTH_API_KEYS:\n{entry}");
+
+        return Ok(());
+    }
+
+    config.apply_env();
+    config.validate()?;
+    match (&config_file, &profile) {
+        (Some(config_file), Some(profile)) => info!(
+            "Using the configuration file {} with the profile `{}`",
+            config_file.display(),
+            profile
+        ),
+        (Some(config_file), None) => {
+            info!("Using the configuration file {}", config_file.display())
+        }
+        _ => {}
+    }
+    info!(
+        "Effective configuration, the settings that are not set take their defaults:\n{}",
+        config.redacted_summary()
+    );
+
+    let rate_limit = config
+        .limits
+        .rate_limit
+        .filter(|per_minute| *per_minute > 0)
+        .map(|per_minute| limit::RateLimitConfig {
+            per_minute,
+            burst: config
+                .limits
+                .rate_limit_burst
+                .unwrap_or(DEFAULT_RATE_LIMIT_BURST),
+        });
+    let max_concurrent_searches = config
+        .limits
+        .max_concurrent_searches
+        .filter(|max_concurrent_searches| *max_concurrent_searches > 0);
+    info!(
+        "Using rate limit {:?} and concurrency cap {:?}",
+        rate_limit, max_concurrent_searches
+    );
+    let limiter = limit::SearchLimiter::new(rate_limit, max_concurrent_searches);
+
+    let authenticator = build_authenticator(&config.auth)?;
+    match (&authenticator, transport) {
+        (Some(_), TransportType::Stdio) => {
+            warn!("Authentication only applies to the HTTP transports, ignoring it on stdio")
+        }
+        (None, TransportType::Sse | TransportType::StreamHttp) => {
+            warn!("Authentication is disabled, anyone who can reach the server can search")
+        }
+        _ => {}
+    }
+
+    let mut pools = ConnectionPools::default();
+    let mut search_config = build_search_config(&config, &mut pools)?;
+    let socket_addr = config
+        .server
+        .socket_addr
+        .clone()
+        .unwrap_or_else(|| DEFAULT_SOCKET_ADDR.to_string());
+    let shutdown_timeout = config
+        .server
+        .shutdown_timeout
+        .unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT_SECS);
+
+    // An unreachable backend does not prevent the server from starting, as it may come up later
+    check_backends(&search_config).await;
+
+    // record the outcome of the searches for the status endpoint
+    let status = StatusRegistry::default();
+    monitor_backends(&mut search_config, &status);
+    let (search_config_sender, search_config) = watch::channel(Arc::new(search_config));
+
+    // `shutdown` stops accepting new connections and tool calls, `ct` closes the sessions once
+    // the requests in progress are responded to or the shutdown timeout is over
+    let shutdown = CancellationToken::new();
+    let ct = CancellationToken::new();
+    let in_flight = TaskTracker::new();
+    tokio::spawn(cancel_on_signal(shutdown.clone()));
+    tokio::spawn(drain_on_shutdown(
+        shutdown.clone(),
+        ct.clone(),
+        in_flight.clone(),
+        Duration::from_secs(shutdown_timeout),
+    ));
+
+    // the search config is only swapped if it comes from a file, without one SIGHUP still stops
+    // the server
+    let reloader = config_file.map(|config_file| {
+        let reloader = reload::ConfigReloader {
+            config_file,
+            profile,
+            command_line,
+            config,
+            pools,
+            status: status.clone(),
+            search_config: search_config_sender,
+        };
+        tokio::spawn(reloader.run(shutdown.clone()))
+    });
+
+    let health_router = health::router(search_config.clone(), status, shutdown.clone());
+    let sessions_config = search_config.clone();
+    match transport {
+        TransportType::Stdio => {
+            info!("Starting Cardea Agentic Search MCP server on stdio");
+
+            let transport = drain::track(rmcp::transport::stdio(), in_flight.clone());
+            let server = AgenticSearchServer::new(sessions_config, in_flight, limiter)
+                .serve_with_ct(transport, ct)
+                .await?;
+            server.waiting().await?;
+        }
+        TransportType::Sse => {
+            info!(
+                "Starting Cardea Agentic Search MCP server on {} with SSE transport",
+                socket_addr
+            );
+
+            let sessions_in_flight = in_flight.clone();
+            let router = sse::router(
+                move || {
+                    AgenticSearchServer::new(
+                        sessions_config.clone(),
+                        sessions_in_flight.clone(),
+                        limiter.clone(),
+                    )
                 },
-            };
+                in_flight.clone(),
+                ct.clone(),
+            );
+            let router = auth::protect(router, authenticator)
+                .merge(health_router)
+                .merge(metrics::router());
+            serve_http(router, &socket_addr, shutdown).await?;
+        }
+        TransportType::StreamHttp => {
+            info!(
+                "Starting Cardea Agentic Search MCP server on {} with Streamable HTTP transport",
+                socket_addr
+            );
 
-            // parse base url
-            let qdrant_base_url =
-                std::env::var("QDRANT_BASE_URL").unwrap_or(DEFAULT_QDRANT_BASE_URL.to_string());
-
-            // parse api key
-            let qdrant_api_key = env::var("QDRANT_API_KEY").ok();
-
-            // parse embedding service base url with priority: Environment Variable > Command Line > Error
-            let embedding_service_base_url = match env::var("EMBEDDING_SERVICE_BASE_URL") {
-                Ok(env_value) => {
-                    info!(
-                        "Using EMBEDDING_SERVICE_BASE_URL from environment: {}",
-                        env_value
-                    );
-                    env_value
-                }
-                Err(_) => match embedding_service_base_url {
-                    Some(arg_value) => {
-                        info!(
-                            "Using embedding_service_base_url from command line argument: {}",
-                            arg_value
-                        );
-                        arg_value
-                    }
-                    None => {
-                        bail!(
-                            "EMBEDDING_SERVICE_BASE_URL environment variable or --embedding-service-base-url argument is required"
-                        );
-                    }
+            let sessions_in_flight = in_flight.clone();
+            let service = StreamableHttpService::new(
+                move || {
+                    Ok(AgenticSearchServer::new(
+                        sessions_config.clone(),
+                        sessions_in_flight.clone(),
+                        limiter.clone(),
+                    ))
                 },
-            };
+                TrackedSessionManager::new(LocalSessionManager::default(), in_flight.clone())
+                    .into(),
+                StreamableHttpServerConfig {
+                    cancellation_token: ct.clone(),
+                    ..Default::default()
+                },
+            );
+
+            let router = axum::Router::new().nest_service("/mcp", service);
+            let router = auth::protect(router, authenticator)
+                .merge(health_router)
+                .merge(metrics::router());
+            serve_http(router, &socket_addr, shutdown).await?;
+        }
+    }
 
-            // parse embedding service api key
-            let embedding_service_api_key = env::var("EMBEDDING_SERVICE_API_KEY").ok();
-
-            // parse embedding service model
-            let embedding_service_model = env::var("EMBEDDING_SERVICE_MODEL").ok();
-
-            AgenticSearchConfig {
-                qdrant_config: Some(QdrantConfig {
-                    api_key: qdrant_api_key,
-                    base_url: qdrant_base_url,
-                    collection: qdrant_collection,
-                    payload_source: qdrant_payload_field,
-                }),
-                tidb_config: None,
-                limit,
-                score_threshold,
-                chat_service: None,
-                embedding_service: Some(ServiceConfig {
-                    url: embedding_service_base_url,
-                    api_key: embedding_service_api_key,
-                    model: embedding_service_model,
-                }),
+    // Dropping the last handle of a connection pool closes its connections. The MySQL client
+    // says goodbye to the server with blocking writes, so do it off the async worker threads.
+    info!("Closing the connections of the search backends...");
+    if let Some(reloader) = reloader {
+        reloader.await?;
+    }
+    tokio::task::spawn_blocking(move || drop(search_config)).await?;
+    if let Some(tracer_provider) = tracer_provider {
+        info!("Flushing the traces...");
+        // the batch exporter runs on its own thread, which shutdown waits for
+        if let Err(e) = tokio::task::spawn_blocking(move || tracer_provider.shutdown()).await? {
+            warn!("Failed to flush the traces: {e}");
+        }
+    }
+    info!("Cardea Agentic Search MCP server stopped");
+
+    Ok(())
+}
+
+/// Serve the router on the socket address until shutdown, then wait for the open connections
+///
+/// The long-lived event streams of the sessions end when the sessions are closed.
+async fn serve_http(
+    router: axum::Router,
+    socket_addr: &str,
+    shutdown: CancellationToken,
+) -> anyhow::Result<()> {
+    let tcp_listener = tokio::net::TcpListener::bind(socket_addr).await?;
+    // the address of the client identifies it for rate limiting if it does not authenticate
+    axum::serve(
+        tcp_listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(async move {
+        shutdown.cancelled().await;
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Cancel the token on SIGINT or SIGTERM
+async fn cancel_on_signal(shutdown: CancellationToken) {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for SIGINT: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
             }
         }
-        SearchMode::Tidb {
-            tidb_ssl_ca,
-            tidb_table_name,
-            tidb_search_field,
-            tidb_return_field,
-            limit,
-            score_threshold,
-            chat_service_base_url,
-        } => {
-            info!("Enabling keyword search mode");
-
-            // Determine SSL CA path with priority: Environment Variable > Command Line > Error
-            let tidb_ssl_ca = match env::var("TIDB_SSL_CA") {
-                Ok(env_value) => {
-                    info!("Using TIDB_SSL_CA from environment: {}", env_value);
-                    PathBuf::from(env_value)
-                }
-                Err(_) => match tidb_ssl_ca {
-                    Some(arg_value) => {
-                        info!(
-                            "Using tidb_ssl_ca from command line argument: {}",
-                            arg_value.display()
-                        );
-                        arg_value
-                    }
-                    None => {
-                        bail!(
-                            "TIDB_SSL_CA environment variable or --tidb-ssl-ca argument is required"
-                        );
-                    }
-                },
-            };
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
 
-            // Determine table name with priority: Environment Variable > Command Line > Error
-            let tidb_table_name = match env::var("TIDB_TABLE_NAME") {
-                Ok(env_value) => {
-                    info!("Using TIDB_TABLE_NAME from environment: {}", env_value);
-                    env_value
-                }
-                Err(_) => match tidb_table_name {
-                    Some(arg_value) => {
-                        info!(
-                            "Using tidb_table_name from command line argument: {}",
-                            arg_value
-                        );
-                        arg_value
-                    }
-                    None => {
-                        bail!(
-                            "TIDB_TABLE_NAME environment variable or --tidb-table-name argument is required"
-                        );
-                    }
-                },
-            };
+    tokio::select! {
+        _ = ctrl_c => info!("Received SIGINT, shutting down..."),
+        _ = terminate => info!("Received SIGTERM, shutting down..."),
+    }
+    shutdown.cancel();
+}
 
-            // Determine content field with priority: Environment Variable > Command Line > Default
-            let tidb_search_field = match env::var("TIDB_SEARCH_FIELD") {
-                Ok(env_value) => {
-                    info!("Using TIDB_SEARCH_FIELD from environment: {}", env_value);
-                    env_value
-                }
-                Err(_) => match tidb_search_field {
-                    Some(arg_value) => {
-                        info!(
-                            "Using TIDB_SEARCH_FIELD from command line argument: {}",
-                            arg_value
-                        );
-                        arg_value
-                    }
-                    None => {
-                        info!("Using TIDB_SEARCH_FIELD default value: content");
-                        "content".to_string()
-                    }
-                },
-            };
+/// Once shutdown starts, wait until the requests in progress are responded to, up to the timeout,
+/// then close the sessions
+///
+/// The sessions deliver the responses handed to their transports before closing.
+async fn drain_on_shutdown(
+    shutdown: CancellationToken,
+    ct: CancellationToken,
+    in_flight: TaskTracker,
+    timeout: Duration,
+) {
+    shutdown.cancelled().await;
 
-            // Determine return field with priority: Environment Variable > Command Line > Default
-            let tidb_return_field = match env::var("TIDB_RETURN_FIELD") {
-                Ok(env_value) => {
-                    info!("Using TIDB_RETURN_FIELD from environment: {}", env_value);
-                    if env_value == "*" {
-                        vec!["*".to_string()]
-                    } else {
-                        env_value.split(',').map(|s| s.trim().to_string()).collect()
-                    }
-                }
-                Err(_) => match tidb_return_field {
-                    Some(arg_value) => {
-                        info!(
-                            "Using TIDB_RETURN_FIELD from command line argument: {:?}",
-                            arg_value
-                        );
-                        arg_value
-                    }
-                    None => {
-                        info!("Using TIDB_RETURN_FIELD default value: *");
-                        vec!["*".to_string()]
-                    }
-                },
-            };
+    in_flight.close();
+    if !in_flight.is_empty() {
+        info!(
+            "Waiting up to {}s for {} request(s) in progress...",
+            timeout.as_secs(),
+            in_flight.len()
+        );
+    }
+    if tokio::time::timeout(timeout, in_flight.wait())
+        .await
+        .is_err()
+    {
+        warn!(
+            "Shutdown timeout reached, abandoning {} request(s) in progress",
+            in_flight.len()
+        );
+    }
 
-            // parse connection string
-            let (username, password, host, port, database) = match env::var("TIDB_CONNECTION") {
-                Ok(ref conn) => {
-                    parse_tidb_conn_str(conn.as_str()).ok_or_else(|| anyhow!(
-                        "Invalid connection string! The pattern should be `mysql://<USERNAME>:<PASSWORD>@<HOST>:<PORT>/<DATABASE>`"
-                    ))?
-                }
-                Err(e) => {
-                    let error_message = format!("Failed to get TIDB_CONNECTION: {e}");
-                    error!(error_message);
-                    bail!(error_message);
-                }
-            };
+    ct.cancel();
+}
 
-            // convert port to u16
-            let port = port.parse::<u16>().map_err(|e| {
-                let error_message = format!("Failed to parse TIDB_PORT: {e}");
-                error!(error_message);
-                anyhow!(error_message)
-            })?;
+#[derive(Debug, Clone)]
+pub struct AgenticSearchConfig {
+    /// The indexes to search, each with its own backends
+    pub indexes: Vec<SearchIndex>,
+    /// The index searched by the calls without a target, if any
+    pub default_target: Option<String>,
+    pub limit: u64,
+    pub fusion: FusionConfig,
+    pub backend_timeout: Duration,
+    pub max_limit: u64,
+    pub max_offset: u64,
+    /// The reranking of the results, if enabled
+    pub rerank: Option<RerankConfig>,
+    /// The diversification of the results
+    pub mmr: MmrConfig,
+    /// The rewriting of the queries, if enabled
+    pub rewrite: Option<RewriteConfig>,
+    /// The hypothetical document embeddings of the vector search, if enabled
+    pub hyde: Option<HydeConfig>,
+}
 
-            // parse chat service base url with priority: Environment Variable > Command Line > Error
-            let chat_service_base_url = match env::var("CHAT_SERVICE_BASE_URL") {
-                Ok(env_value) => {
-                    info!(
-                        "Using CHAT_SERVICE_BASE_URL from environment: {}",
-                        env_value
-                    );
-                    env_value
-                }
-                Err(_) => match chat_service_base_url {
-                    Some(arg_value) => {
-                        info!(
-                            "Using chat_service_base_url from command line argument: {}",
-                            arg_value
-                        );
-                        arg_value
-                    }
-                    None => {
-                        bail!(
-                            "CHAT_SERVICE_BASE_URL environment variable or --chat-service-base-url argument is required"
-                        );
-                    }
-                },
-            };
+impl AgenticSearchConfig {
+    /// The backends of all the indexes
+    pub fn backends(&self) -> impl Iterator<Item = &Arc<dyn SearchBackend>> {
+        self.indexes.iter().flat_map(|index| &index.backends)
+    }
+}
 
-            // parse chat service api key
-            let chat_service_api_key = env::var("CHAT_SERVICE_API_KEY").ok();
+/// A named search index, whose backends are searched together
+#[derive(Debug, Clone)]
+pub struct SearchIndex {
+    pub name: String,
+    /// What the index holds, shown to the clients
+    pub description: Option<String>,
+    /// The backends to search, each with a unique name
+    pub backends: Vec<Arc<dyn SearchBackend>>,
+}
 
-            // parse chat service model
-            let chat_service_model = env::var("CHAT_SERVICE_MODEL").ok();
+/// The settings a connection pool is created from: the connection string and the SSL CA
+/// certificate
+type PoolSettings = (String, Option<PathBuf>);
 
-            CryptoProvider::install_default(default_provider()).map_err(|e| {
-                let err_msg = format!("Failed to install default crypto provider: {e:?}");
-                error!("{}", err_msg);
-                anyhow!(err_msg)
-            })?;
+/// The connection pools of the SQL backends, shared by the indexes on the same database and
+/// kept across the reloads of the configuration while their settings do not change
+#[derive(Default)]
+pub struct ConnectionPools {
+    tidb: HashMap<PoolSettings, Pool>,
+    postgres: HashMap<PoolSettings, deadpool_postgres::Pool>,
+}
+impl ConnectionPools {
+    /// Close the pools that none of the indexes use
+    fn retain_used(&mut self, indexes: &[(String, IndexSettings)]) {
+        let tidb: HashSet<PoolSettings> = indexes
+            .iter()
+            .filter_map(|(_, index)| index.tidb.as_ref())
+            .map(|tidb| {
+                (
+                    tidb.connection.clone().expect(VALIDATED),
+                    tidb.ssl_ca.clone(),
+                )
+            })
+            .collect();
+        self.tidb.retain(|settings, _| tidb.contains(settings));
 
-            // create connection options
-            info!("Creating connection options for TiDB Cloud...");
-            let opts = OptsBuilder::new()
-                .ip_or_hostname(Some(host))
-                .tcp_port(port)
-                .user(Some(username))
-                .pass(Some(password))
-                .db_name(Some(database.clone()))
-                .ssl_opts(Some(
-                    SslOpts::default().with_root_cert_path(Some(tidb_ssl_ca)),
-                ))
-                .init(vec!["SET NAMES utf8mb4".to_string()]);
+        let postgres: HashSet<PoolSettings> = indexes
+            .iter()
+            .filter_map(|(_, index)| index.postgres.as_ref())
+            .map(|postgres| {
+                (
+                    postgres.connection.clone().expect(VALIDATED),
+                    postgres.ssl_ca.clone(),
+                )
+            })
+            .collect();
+        self.postgres
+            .retain(|settings, _| postgres.contains(settings));
+    }
+}
 
-            // create connection pool
-            info!("Creating connection pool...");
-            let pool = Pool::new(opts).map_err(|e| {
-                let error_message = format!("Failed to create connection pool: {e}");
-                error!(error_message);
-                anyhow!(error_message)
-            })?;
+#[derive(Debug, Clone)]
+pub struct QdrantConfig {
+    pub api_key: Option<String>,
+    pub base_url: String,
+    pub collection: String,
+    pub payload_source: String,
+    pub title_field: Option<String>,
+    pub metadata_fields: Option<Vec<String>>,
+    pub filterable_fields: Vec<String>,
+    /// Minimum similarity score of the returned points
+    pub score_threshold: f32,
+}
 
-            AgenticSearchConfig {
-                qdrant_config: None,
-                tidb_config: Some(TiDBConfig {
-                    database,
-                    table_name: tidb_table_name,
-                    pool,
-                    search_field: tidb_search_field,
-                    return_field: tidb_return_field,
-                }),
-                limit,
-                score_threshold,
-                chat_service: Some(ServiceConfig {
-                    url: chat_service_base_url,
-                    api_key: chat_service_api_key,
-                    model: chat_service_model,
-                }),
-                embedding_service: None,
-            }
+#[derive(Debug, Clone)]
+pub struct TiDBConfig {
+    pub database: String,
+    pub table_name: String,
+    pub pool: Pool,
+    pub search_field: String,
+    pub return_field: Vec<String>,
+    pub id_field: String,
+    pub title_field: Option<String>,
+    pub metadata_fields: Option<Vec<String>>,
+    /// Minimum `fts_match_word` relevance score of the returned rows
+    pub score_threshold: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct PostgresConfig {
+    pub pool: deadpool_postgres::Pool,
+    pub table_name: String,
+    pub return_field: Vec<String>,
+    pub id_field: String,
+    pub title_field: Option<String>,
+    pub metadata_fields: Option<Vec<String>>,
+    /// The pgvector column, if vector search is enabled
+    pub embedding_field: Option<String>,
+    /// The `tsvector` column, if full-text search is enabled
+    pub tsvector_field: Option<String>,
+    pub text_search_config: String,
+    /// Minimum cosine similarity of the returned rows
+    pub score_threshold: f32,
+    /// Minimum `ts_rank` relevance score of the returned rows
+    pub keyword_score_threshold: f32,
+    /// The `k` constant of the Reciprocal Rank Fusion of hybrid searches
+    pub rrf_k: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct TantivyConfig {
+    pub index_dir: PathBuf,
+    pub index: tantivy::Index,
+    /// What the `index` command recorded about the documents
+    pub payload: IndexPayload,
+    pub return_field: Vec<String>,
+    pub title_field: String,
+    pub metadata_fields: Option<Vec<String>>,
+    /// Minimum BM25 relevance score of the returned documents
+    pub score_threshold: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct ServiceConfig {
+    pub url: String,
+    pub api_key: Option<String>,
+    pub model: Option<String>,
+    /// The prompt of the keyword extraction, for a chat service. Defaults to the built-in prompt
+    pub keyword_prompt: Option<String>,
+}
+
+/// The settings of the command line arguments: the options of the search mode subcommand, and
+/// the global options that are explicitly given, so that their defaults do not override the
+/// configuration file
+fn command_line_config(args: &Args, matches: &ArgMatches) -> Config {
+    let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    let mut config = Config {
+        server: ServerSettings {
+            socket_addr: given("socket_addr").then(|| args.socket_addr.clone()),
+            transport: given("transport").then_some(args.transport),
+            shutdown_timeout: given("shutdown_timeout").then_some(args.shutdown_timeout),
+            otlp_endpoint: args.otlp_endpoint.clone(),
+        },
+        limits: LimitSettings {
+            max_limit: given("max_limit").then_some(args.max_limit),
+            max_offset: given("max_offset").then_some(args.max_offset),
+            rate_limit: given("rate_limit").then_some(args.rate_limit),
+            rate_limit_burst: given("rate_limit_burst").then_some(args.rate_limit_burst),
+            max_concurrent_searches: given("max_concurrent_searches")
+                .then_some(args.max_concurrent_searches),
+        },
+        auth: AuthSettings {
+            api_keys: None,
+            api_keys_file: args.auth.auth_api_keys_file.clone(),
+            jwks_file: args.auth.auth_jwks_file.clone(),
+            jwt_issuers: args.auth.auth_jwt_issuer.clone(),
+            jwt_audiences: args.auth.auth_jwt_audience.clone(),
+        },
+        search: SearchSettings {
+            rerank_method: args.rerank.rerank_method,
+            rerank_overfetch: given("rerank_overfetch").then_some(args.rerank.rerank_overfetch),
+            llm_rerank_max_candidates: given("llm_rerank_max_candidates")
+                .then_some(args.rerank.llm_rerank_max_candidates),
+            llm_rerank_max_tokens: given("llm_rerank_max_tokens")
+                .then_some(args.rerank.llm_rerank_max_tokens),
+            mmr_lambda: args.mmr.mmr_lambda,
+            mmr_overfetch: given("mmr_overfetch").then_some(args.mmr.mmr_overfetch),
+            query_variants: given("query_variants").then_some(args.rewrite.query_variants),
+            include_original_query: args.rewrite.exclude_original_query.then_some(false),
+            hyde: args.hyde.hyde.then_some(true),
+            hyde_include_query: args.hyde.hyde_include_query.then_some(true),
+            ..Default::default()
+        },
+        rerank_service: ServiceSettings {
+            base_url: args.rerank.rerank_service_base_url.clone(),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    match &args.search_mode {
+        Some(SearchMode::Qdrant {
+            qdrant,
+            limit,
+            score_threshold,
+        }) => {
+            config.qdrant = Some(qdrant.to_settings(*score_threshold));
+            config.embedding_service.base_url = qdrant.embedding_service_base_url.clone();
+            config.search.limit = Some(*limit);
         }
-        SearchMode::Search {
-            qdrant_collection,
-            qdrant_payload_field,
-            tidb_ssl_ca,
-            tidb_table_name,
-            tidb_search_field,
-            tidb_return_field,
+        Some(SearchMode::Tidb {
+            tidb,
             limit,
             score_threshold,
-            chat_service_base_url,
-            embedding_service_base_url,
-        } => {
-            info!("Enabling both vector and keyword search modes");
-
-            // Determine collection with priority: Environment Variable > Command Line > Error
-            let qdrant_collection = match env::var("QDRANT_COLLECTION") {
-                Ok(env_value) => {
-                    info!("Using QDRANT_COLLECTION from environment: {}", env_value);
-                    env_value
-                }
-                Err(_) => match qdrant_collection {
-                    Some(arg_value) => {
-                        info!(
-                            "Using qdrant_collection from command line argument: {}",
-                            arg_value
-                        );
-                        arg_value
-                    }
-                    None => {
-                        bail!(
-                            "QDRANT_COLLECTION environment variable or --qdrant-collection argument is required"
-                        );
-                    }
-                },
-            };
-
-            // Determine payload field with priority: Environment Variable > Command Line > Error
-            let qdrant_payload_field = match env::var("QDRANT_PAYLOAD_FIELD") {
-                Ok(env_value) => {
-                    info!("Using QDRANT_PAYLOAD_FIELD from environment: {}", env_value);
-                    env_value
-                }
-                Err(_) => match qdrant_payload_field {
-                    Some(arg_value) => {
-                        info!(
-                            "Using qdrant_payload_field from command line argument: {}",
-                            arg_value
-                        );
-                        arg_value
-                    }
-                    None => {
-                        bail!(
-                            "QDRANT_PAYLOAD_FIELD environment variable or --qdrant-payload-field argument is required"
-                        );
-                    }
-                },
-            };
+        }) => {
+            config.tidb = Some(tidb.to_settings(*score_threshold));
+            config.chat_service.base_url = tidb.chat_service_base_url.clone();
+            config.search.limit = Some(*limit);
+        }
+        Some(SearchMode::Postgres {
+            postgres,
+            limit,
+            score_threshold,
+            keyword_score_threshold,
+            rrf_k,
+        }) => {
+            config.postgres =
+                Some(postgres.to_settings(*score_threshold, *keyword_score_threshold, *rrf_k));
+            config.embedding_service.base_url = postgres.embedding_service_base_url.clone();
+            config.chat_service.base_url = postgres.chat_service_base_url.clone();
+            config.search.limit = Some(*limit);
+        }
+        Some(SearchMode::Tantivy {
+            tantivy,
+            limit,
+            score_threshold,
+        }) => {
+            config.tantivy = Some(tantivy.to_settings(*score_threshold));
+            config.chat_service.base_url = tantivy.chat_service_base_url.clone();
+            config.search.limit = Some(*limit);
+        }
+        Some(SearchMode::Search {
+            qdrant,
+            tidb,
+            limit,
+            score_threshold,
+            keyword_score_threshold,
+            fusion_method,
+            rrf_k,
+            fusion_weights,
+            backend_timeout,
+        }) => {
+            config.qdrant = Some(qdrant.to_settings(*score_threshold));
+            config.tidb = Some(tidb.to_settings(*keyword_score_threshold));
+            config.embedding_service.base_url = qdrant.embedding_service_base_url.clone();
+            config.chat_service.base_url = tidb.chat_service_base_url.clone();
+            config.search.limit = Some(*limit);
+            config.search.fusion_method = Some(*fusion_method);
+            config.search.rrf_k = Some(*rrf_k);
+            config.search.fusion_weights = Some(fusion_weights.iter().cloned().collect());
+            config.search.backend_timeout = Some(*backend_timeout);
+        }
+        Some(SearchMode::Index { .. } | SearchMode::GenerateApiKey { .. }) | None => {}
+    }
 
-            // Determine SSL CA path with priority: Environment Variable > Command Line > Error
-            let tidb_ssl_ca = match env::var("TIDB_SSL_CA") {
-                Ok(env_value) => {
-                    info!("Using TIDB_SSL_CA from environment: {}", env_value);
-                    PathBuf::from(env_value)
-                }
-                Err(_) => match tidb_ssl_ca {
-                    Some(arg_value) => {
-                        info!(
-                            "Using tidb_ssl_ca from command line argument: {}",
-                            arg_value.display()
-                        );
-                        arg_value
-                    }
-                    None => {
-                        bail!(
-                            "TIDB_SSL_CA environment variable or --tidb-ssl-ca argument is required"
-                        );
-                    }
-                },
-            };
+    config
+}
 
-            // Determine table name with priority: Environment Variable > Command Line > Error
-            let tidb_table_name = match env::var("TIDB_TABLE_NAME") {
-                Ok(env_value) => {
-                    info!("Using TIDB_TABLE_NAME from environment: {}", env_value);
-                    env_value
-                }
-                Err(_) => match tidb_table_name {
-                    Some(arg_value) => {
-                        info!(
-                            "Using tidb_table_name from command line argument: {}",
-                            arg_value
-                        );
-                        arg_value
-                    }
-                    None => {
-                        bail!(
-                            "TIDB_TABLE_NAME environment variable or --tidb-table-name argument is required"
-                        );
-                    }
-                },
-            };
+impl QdrantArgs {
+    fn to_settings(&self, score_threshold: f32) -> QdrantSettings {
+        QdrantSettings {
+            base_url: None,
+            api_key: None,
+            collection: self.qdrant_collection.clone(),
+            payload_field: self.qdrant_payload_field.clone(),
+            title_field: self.qdrant_title_field.clone(),
+            metadata_fields: self.qdrant_metadata_field.clone(),
+            filterable_fields: self.qdrant_filterable_field.clone(),
+            score_threshold: Some(score_threshold),
+        }
+    }
+}
 
-            // Determine content field with priority: Environment Variable > Command Line > Default
-            let tidb_search_field = match env::var("TIDB_SEARCH_FIELD") {
-                Ok(env_value) => {
-                    info!("Using TIDB_SEARCH_FIELD from environment: {}", env_value);
-                    env_value
-                }
-                Err(_) => match tidb_search_field {
-                    Some(arg_value) => {
-                        info!(
-                            "Using TIDB_SEARCH_FIELD from command line argument: {}",
-                            arg_value
-                        );
-                        arg_value
-                    }
-                    None => {
-                        info!("Using TIDB_SEARCH_FIELD default value: content");
-                        "content".to_string()
-                    }
-                },
-            };
+impl TidbArgs {
+    fn to_settings(&self, score_threshold: f32) -> TidbSettings {
+        TidbSettings {
+            connection: None,
+            ssl_ca: self.tidb_ssl_ca.clone(),
+            table_name: self.tidb_table_name.clone(),
+            search_field: self.tidb_search_field.clone(),
+            return_fields: self.tidb_return_field.clone(),
+            id_field: self.tidb_id_field.clone(),
+            title_field: self.tidb_title_field.clone(),
+            metadata_fields: self.tidb_metadata_field.clone(),
+            score_threshold: Some(score_threshold),
+        }
+    }
+}
 
-            // Determine return field with priority: Environment Variable > Command Line > Default
-            let tidb_return_field = match env::var("TIDB_RETURN_FIELD") {
-                Ok(env_value) => {
-                    info!("Using TIDB_RETURN_FIELD from environment: {}", env_value);
-                    if env_value == "*" {
-                        vec!["*".to_string()]
-                    } else {
-                        env_value.split(',').map(|s| s.trim().to_string()).collect()
-                    }
-                }
-                Err(_) => match tidb_return_field {
-                    Some(arg_value) => {
-                        info!(
-                            "Using TIDB_RETURN_FIELD from command line argument: {:?}",
-                            arg_value
-                        );
-                        arg_value
-                    }
-                    None => {
-                        info!("Using TIDB_RETURN_FIELD default value: *");
-                        vec!["*".to_string()]
-                    }
-                },
-            };
+impl PostgresArgs {
+    fn to_settings(
+        &self,
+        score_threshold: f32,
+        keyword_score_threshold: f32,
+        rrf_k: f64,
+    ) -> PostgresSettings {
+        PostgresSettings {
+            connection: None,
+            ssl_ca: self.postgres_ssl_ca.clone(),
+            table_name: self.postgres_table_name.clone(),
+            embedding_field: self.postgres_embedding_field.clone(),
+            tsvector_field: self.postgres_tsvector_field.clone(),
+            text_search_config: self.postgres_text_search_config.clone(),
+            return_fields: self.postgres_return_field.clone(),
+            id_field: self.postgres_id_field.clone(),
+            title_field: self.postgres_title_field.clone(),
+            metadata_fields: self.postgres_metadata_field.clone(),
+            score_threshold: Some(score_threshold),
+            keyword_score_threshold: Some(keyword_score_threshold),
+            rrf_k: Some(rrf_k),
+        }
+    }
+}
 
-            // parse base url
-            let qdrant_base_url =
-                std::env::var("QDRANT_BASE_URL").unwrap_or(DEFAULT_QDRANT_BASE_URL.to_string());
+impl TantivyArgs {
+    fn to_settings(&self, score_threshold: f32) -> TantivySettings {
+        TantivySettings {
+            index_dir: self.tantivy_index_dir.clone(),
+            return_fields: self.tantivy_return_field.clone(),
+            title_field: self.tantivy_title_field.clone(),
+            metadata_fields: self.tantivy_metadata_field.clone(),
+            score_threshold: Some(score_threshold),
+        }
+    }
+}
 
-            // parse qdrant api key
-            let qdrant_api_key = env::var("QDRANT_API_KEY").ok();
+/// Build the search backends and settings of a validated configuration, reusing the connection
+/// pools whose settings did not change
+fn build_search_config(
+    config: &Config,
+    pools: &mut ConnectionPools,
+) -> anyhow::Result<AgenticSearchConfig> {
+    let search_indexes = config.search_indexes()?;
+    let mut indexes = Vec::new();
+    for (name, index) in &search_indexes {
+        let backend_name = |backend: &str| config.backend_name(name, backend);
 
-            // parse connection string
-            let (tidb_username, tidb_password, tidb_host, tidb_port, tidb_database) = match env::var("TIDB_CONNECTION") {
-                Ok(ref conn) => {
-                    parse_tidb_conn_str(conn.as_str()).ok_or_else(|| anyhow!(
-                        "Invalid connection string! The pattern should be `mysql://<USERNAME>:<PASSWORD>@<HOST>:<PORT>/<DATABASE>`"
-                    ))?
-                }
-                Err(e) => {
-                    let error_message = format!("Failed to get TIDB_CONNECTION: {e}");
-                    error!(error_message);
-                    bail!(error_message);
+        let mut backends: Vec<Arc<dyn SearchBackend>> = Vec::new();
+        if let Some(qdrant) = &index.qdrant {
+            backends.push(Arc::new(build_qdrant_backend(
+                backend_name(backend::qdrant::DEFAULT_BACKEND_NAME),
+                qdrant,
+                embedding_service_config(config),
+            )));
+        }
+        if let Some(tidb) = &index.tidb {
+            backends.push(Arc::new(build_tidb_backend(
+                backend_name(backend::tidb::DEFAULT_BACKEND_NAME),
+                tidb,
+                chat_service_config(config),
+                pools,
+            )?));
+        }
+        if let Some(postgres) = &index.postgres {
+            // the services are only needed by the searches that are enabled
+            let embedding_service = postgres
+                .embedding_field
+                .as_ref()
+                .map(|_| embedding_service_config(config));
+            let chat_service = postgres
+                .tsvector_field
+                .as_ref()
+                .map(|_| chat_service_config(config));
+            backends.push(Arc::new(build_postgres_backend(
+                backend_name(backend::postgres::DEFAULT_BACKEND_NAME),
+                postgres,
+                embedding_service,
+                chat_service,
+                pools,
+            )?));
+        }
+        if let Some(tantivy) = &index.tantivy {
+            // the chat service is optional, as the query can be searched as is
+            let chat_service = match &config.chat_service.base_url {
+                Some(_) => Some(chat_service_config(config)),
+                None => {
+                    info!("No chat service configured, searching the queries as is");
+                    None
                 }
             };
+            backends.push(Arc::new(build_tantivy_backend(
+                backend_name(backend::tantivy::DEFAULT_BACKEND_NAME),
+                tantivy,
+                chat_service,
+            )?));
+        }
 
-            // convert port to u16
-            let tidb_port = tidb_port.parse::<u16>().map_err(|e| {
-                let error_message = format!("Failed to parse TIDB_PORT: {e}");
-                error!(error_message);
-                anyhow!(error_message)
-            })?;
+        indexes.push(SearchIndex {
+            name: name.clone(),
+            description: index.description.clone(),
+            backends,
+        });
+    }
+    pools.retain_used(&search_indexes);
 
-            // parse chat service base url with priority: Environment Variable > Command Line > Error
-            let chat_service_base_url = match env::var("CHAT_SERVICE_BASE_URL") {
-                Ok(env_value) => {
-                    info!(
-                        "Using CHAT_SERVICE_BASE_URL from environment: {}",
-                        env_value
-                    );
-                    env_value
-                }
-                Err(_) => match chat_service_base_url {
-                    Some(arg_value) => {
-                        info!(
-                            "Using chat_service_base_url from command line argument: {}",
-                            arg_value
-                        );
-                        arg_value
-                    }
-                    None => {
-                        bail!(
-                            "CHAT_SERVICE_BASE_URL environment variable or --chat-service-base-url argument is required"
-                        );
-                    }
-                },
-            };
+    let fusion = FusionConfig {
+        method: config.search.fusion_method.unwrap_or(FusionMethod::Rrf),
+        rrf_k: config.search.rrf_k.unwrap_or(DEFAULT_RRF_K),
+        weights: config
+            .search
+            .fusion_weights
+            .clone()
+            .unwrap_or_default()
+            .into_iter()
+            .collect(),
+    };
+    if indexes.iter().any(|index| index.backends.len() > 1) {
+        info!("Using fusion config: {:?}", fusion);
+    }
+    let default_target =
+        config
+            .search
+            .default_target
+            .clone()
+            .or_else(|| match indexes.as_slice() {
+                [index] => Some(index.name.clone()),
+                _ => None,
+            });
+    let rerank_method = config.search.rerank_method.or(config
+        .rerank_service
+        .base_url
+        .as_ref()
+        .map(|_| RerankMethod::CrossEncoder));
+    let reranker = rerank_method.map(|rerank_method| match rerank_method {
+        RerankMethod::CrossEncoder => Reranker::CrossEncoder {
+            service: ServiceConfig {
+                url: config.rerank_service.base_url.clone().expect(VALIDATED),
+                api_key: config.rerank_service.api_key.clone(),
+                model: config.rerank_service.model.clone(),
+                keyword_prompt: None,
+            },
+        },
+        RerankMethod::Llm => Reranker::Llm {
+            service: chat_service_config(config),
+            prompt: config.prompts.reranker.clone(),
+            max_candidates: config
+                .search
+                .llm_rerank_max_candidates
+                .unwrap_or(DEFAULT_LLM_RERANK_MAX_CANDIDATES),
+            max_tokens: config
+                .search
+                .llm_rerank_max_tokens
+                .unwrap_or(DEFAULT_LLM_RERANK_MAX_TOKENS),
+        },
+    });
+    let rerank = reranker.map(|reranker| RerankConfig {
+        reranker,
+        overfetch: config
+            .search
+            .rerank_overfetch
+            .unwrap_or(DEFAULT_RERANK_OVERFETCH),
+    });
+    match rerank
+        .as_ref()
+        .map(|rerank| (&rerank.reranker, rerank.overfetch))
+    {
+        Some((Reranker::CrossEncoder { service }, overfetch)) => info!(
+            "Reranking the results with the cross-encoder at {}, retrieving {} candidate(s) per result",
+            service.url, overfetch
+        ),
+        Some((Reranker::Llm { max_candidates, .. }, overfetch)) => info!(
+            "Reranking up to {} result(s) with the chat model, retrieving {} candidate(s) per result",
+            max_candidates, overfetch
+        ),
+        None => {}
+    }
 
-            // parse chat service api key
-            let chat_service_api_key = env::var("CHAT_SERVICE_API_KEY").ok();
+    let rewrite = match config.search.query_variants.unwrap_or(0) {
+        0 => None,
+        variants => Some(RewriteConfig {
+            service: chat_service_config(config),
+            prompt: config.prompts.query_rewriter.clone(),
+            variants,
+            include_original: config.search.include_original_query.unwrap_or(true),
+        }),
+    };
+    if let Some(rewrite) = &rewrite {
+        info!(
+            "Rewriting the queries into {} variant(s) with the chat model, {} the original query",
+            rewrite.variants,
+            if rewrite.include_original {
+                "along with"
+            } else {
+                "instead of"
+            }
+        );
+    }
 
-            // parse chat service model
-            let chat_service_model = env::var("CHAT_SERVICE_MODEL").ok();
+    let hyde = config.search.hyde.unwrap_or(false).then(|| HydeConfig {
+        service: chat_service_config(config),
+        prompt: config.prompts.hypothetical_document.clone(),
+        include_query: config.search.hyde_include_query.unwrap_or(false),
+    });
+    if let Some(hyde) = &hyde {
+        info!(
+            "Embedding a hypothetical document drafted by the chat model for the queries in the vector backends{}",
+            if hyde.include_query {
+                ", averaged with the embedding of the query"
+            } else {
+                ""
+            }
+        );
+    }
 
-            // parse embedding service base url with priority: Environment Variable > Command Line > Error
-            let embedding_service_base_url = match env::var("EMBEDDING_SERVICE_BASE_URL") {
-                Ok(env_value) => {
-                    info!(
-                        "Using EMBEDDING_SERVICE_BASE_URL from environment: {}",
-                        env_value
-                    );
-                    env_value
-                }
-                Err(_) => match embedding_service_base_url {
-                    Some(arg_value) => {
-                        info!(
-                            "Using embedding_service_base_url from command line argument: {}",
-                            arg_value
-                        );
-                        arg_value
-                    }
-                    None => {
-                        bail!(
-                            "EMBEDDING_SERVICE_BASE_URL environment variable or --embedding-service-base-url argument is required"
-                        );
-                    }
-                },
-            };
+    Ok(AgenticSearchConfig {
+        indexes,
+        default_target,
+        limit: config.search.limit.unwrap_or(DEFAULT_LIMIT),
+        fusion,
+        backend_timeout: Duration::from_secs(
+            config
+                .search
+                .backend_timeout
+                .unwrap_or(DEFAULT_BACKEND_TIMEOUT_SECS),
+        ),
+        max_limit: config.limits.max_limit.unwrap_or(DEFAULT_MAX_LIMIT),
+        max_offset: config.limits.max_offset.unwrap_or(DEFAULT_MAX_OFFSET),
+        rerank,
+        mmr: MmrConfig {
+            lambda: config.search.mmr_lambda,
+            overfetch: config.search.mmr_overfetch.unwrap_or(DEFAULT_MMR_OVERFETCH),
+        },
+        rewrite,
+        hyde,
+    })
+}
 
-            // parse embedding service api key
-            let embedding_service_api_key = env::var("EMBEDDING_SERVICE_API_KEY").ok();
+/// Log the indexes and their backends, and warn about the backends that are not healthy
+async fn check_backends(search_config: &AgenticSearchConfig) {
+    for index in &search_config.indexes {
+        match &index.description {
+            Some(description) => info!("Using index `{}`: {}", index.name, description),
+            None => info!("Using index `{}`", index.name),
+        }
+        for backend in &index.backends {
+            let description = backend.describe();
+            info!(
+                "Using {} search backend `{}`: {}",
+                description.kind, description.name, description.description
+            );
+            if let Err(e) = backend.health_check().await {
+                warn!(
+                    "Search backend `{}` is not healthy: {}",
+                    backend.name(),
+                    e.message
+                );
+            }
+        }
+    }
+}
 
-            // parse embedding service model
-            let embedding_service_model = env::var("EMBEDDING_SERVICE_MODEL").ok();
+/// Record the outcome of the searches in the backends for the status endpoint
+fn monitor_backends(search_config: &mut AgenticSearchConfig, status: &StatusRegistry) {
+    for index in &mut search_config.indexes {
+        index.backends = std::mem::take(&mut index.backends)
+            .into_iter()
+            .map(|backend| {
+                Arc::new(MonitoredBackend::new(backend, status.clone())) as Arc<dyn SearchBackend>
+            })
+            .collect();
+    }
+}
 
-            CryptoProvider::install_default(default_provider()).map_err(|e| {
-                let err_msg = format!("Failed to install default crypto provider: {e:?}");
-                error!("{}", err_msg);
-                anyhow!(err_msg)
-            })?;
+/// Build the Qdrant backend from its validated settings
+fn build_qdrant_backend(
+    name: String,
+    settings: &QdrantSettings,
+    embedding_service: ServiceConfig,
+) -> QdrantBackend {
+    QdrantBackend::new(
+        name,
+        QdrantConfig {
+            api_key: settings.api_key.clone(),
+            base_url: settings
+                .base_url
+                .clone()
+                .unwrap_or_else(|| DEFAULT_QDRANT_BASE_URL.to_string()),
+            collection: settings.collection.clone().expect(VALIDATED),
+            payload_source: settings.payload_field.clone().expect(VALIDATED),
+            title_field: settings.title_field.clone(),
+            metadata_fields: settings.metadata_fields.clone(),
+            filterable_fields: settings.filterable_fields.clone().unwrap_or_default(),
+            score_threshold: settings
+                .score_threshold
+                .unwrap_or(DEFAULT_VECTOR_SCORE_THRESHOLD),
+        },
+        embedding_service,
+    )
+}
+
+/// Build the TiDB backend from its validated settings
+fn build_tidb_backend(
+    name: String,
+    settings: &TidbSettings,
+    chat_service: ServiceConfig,
+    pools: &mut ConnectionPools,
+) -> anyhow::Result<TidbBackend> {
+    let connection = settings.connection.clone().expect(VALIDATED);
+    let (username, password, host, port, database) =
+        config::parse_tidb_connection(&connection).expect(VALIDATED);
+
+    let pool_settings = (connection, settings.ssl_ca.clone());
+    let pool = match pools.tidb.get(&pool_settings) {
+        Some(pool) => {
+            info!("Reusing the TiDB connection pool");
+            pool.clone()
+        }
+        None => {
+            install_crypto_provider()?;
 
             // create connection options
             info!("Creating connection options for TiDB Cloud...");
             let opts = OptsBuilder::new()
-                .ip_or_hostname(Some(tidb_host))
-                .tcp_port(tidb_port)
-                .user(Some(tidb_username))
-                .pass(Some(tidb_password))
-                .db_name(Some(tidb_database.clone()))
+                .ip_or_hostname(Some(host))
+                .tcp_port(port)
+                .user(Some(username))
+                .pass(Some(password))
+                .db_name(Some(database.clone()))
                 .ssl_opts(Some(
-                    SslOpts::default().with_root_cert_path(Some(tidb_ssl_ca)),
-                ));
+                    SslOpts::default().with_root_cert_path(settings.ssl_ca.clone()),
+                ))
+                .init(vec!["SET NAMES utf8mb4".to_string()]);
 
             // create connection pool
             info!("Creating connection pool...");
@@ -707,108 +1469,331 @@ async fn main() -> anyhow::Result<()> {
                 error!(error_message);
                 anyhow!(error_message)
             })?;
+            pools.tidb.insert(pool_settings, pool.clone());
+            pool
+        }
+    };
 
-            AgenticSearchConfig {
-                qdrant_config: Some(QdrantConfig {
-                    api_key: qdrant_api_key,
-                    base_url: qdrant_base_url,
-                    collection: qdrant_collection,
-                    payload_source: qdrant_payload_field,
-                }),
-                tidb_config: Some(TiDBConfig {
-                    database: tidb_database,
-                    table_name: tidb_table_name,
-                    pool,
-                    search_field: tidb_search_field,
-                    return_field: tidb_return_field,
-                }),
-                limit,
-                score_threshold,
-                chat_service: Some(ServiceConfig {
-                    url: chat_service_base_url,
-                    api_key: chat_service_api_key,
-                    model: chat_service_model,
-                }),
-                embedding_service: Some(ServiceConfig {
-                    url: embedding_service_base_url,
-                    api_key: embedding_service_api_key,
-                    model: embedding_service_model,
-                }),
+    Ok(TidbBackend::new(
+        name,
+        TiDBConfig {
+            database,
+            table_name: settings.table_name.clone().expect(VALIDATED),
+            pool,
+            search_field: settings
+                .search_field
+                .clone()
+                .unwrap_or_else(|| DEFAULT_TIDB_SEARCH_FIELD.to_string()),
+            return_field: settings
+                .return_fields
+                .clone()
+                .unwrap_or_else(|| vec!["*".to_string()]),
+            id_field: settings
+                .id_field
+                .clone()
+                .unwrap_or_else(|| DEFAULT_TIDB_ID_FIELD.to_string()),
+            title_field: settings.title_field.clone(),
+            metadata_fields: settings.metadata_fields.clone(),
+            score_threshold: settings
+                .score_threshold
+                .unwrap_or(DEFAULT_KEYWORD_SCORE_THRESHOLD),
+        },
+        chat_service,
+    ))
+}
+
+/// Build the Postgres backend from its validated settings
+fn build_postgres_backend(
+    name: String,
+    settings: &PostgresSettings,
+    embedding_service: Option<ServiceConfig>,
+    chat_service: Option<ServiceConfig>,
+    pools: &mut ConnectionPools,
+) -> anyhow::Result<PostgresBackend> {
+    let pool_settings = (
+        settings.connection.clone().expect(VALIDATED),
+        settings.ssl_ca.clone(),
+    );
+    let pool = match pools.postgres.get(&pool_settings) {
+        Some(pool) => {
+            info!("Reusing the Postgres connection pool");
+            pool.clone()
+        }
+        None => {
+            let pool = create_postgres_pool(settings)?;
+            pools.postgres.insert(pool_settings, pool.clone());
+            pool
+        }
+    };
+
+    Ok(PostgresBackend::new(
+        name,
+        PostgresConfig {
+            pool,
+            table_name: settings.table_name.clone().expect(VALIDATED),
+            return_field: settings
+                .return_fields
+                .clone()
+                .unwrap_or_else(|| vec!["*".to_string()]),
+            id_field: settings
+                .id_field
+                .clone()
+                .unwrap_or_else(|| DEFAULT_POSTGRES_ID_FIELD.to_string()),
+            title_field: settings.title_field.clone(),
+            metadata_fields: settings.metadata_fields.clone(),
+            embedding_field: settings.embedding_field.clone(),
+            tsvector_field: settings.tsvector_field.clone(),
+            text_search_config: settings
+                .text_search_config
+                .clone()
+                .unwrap_or_else(|| DEFAULT_POSTGRES_TEXT_SEARCH_CONFIG.to_string()),
+            score_threshold: settings
+                .score_threshold
+                .unwrap_or(DEFAULT_VECTOR_SCORE_THRESHOLD),
+            keyword_score_threshold: settings
+                .keyword_score_threshold
+                .unwrap_or(DEFAULT_KEYWORD_SCORE_THRESHOLD),
+            rrf_k: settings.rrf_k.unwrap_or(DEFAULT_RRF_K),
+        },
+        embedding_service,
+        chat_service,
+    ))
+}
+
+/// Create the connection pool of the Postgres backend
+fn create_postgres_pool(settings: &PostgresSettings) -> anyhow::Result<deadpool_postgres::Pool> {
+    let pg_config = settings
+        .connection
+        .as_deref()
+        .expect(VALIDATED)
+        .parse::<tokio_postgres::Config>()?;
+
+    let manager_config = deadpool_postgres::ManagerConfig {
+        recycling_method: deadpool_postgres::RecyclingMethod::Fast,
+    };
+    let manager = match &settings.ssl_ca {
+        Some(ssl_ca) => {
+            install_crypto_provider()?;
+
+            let mut root_store = rustls::RootCertStore::empty();
+            let certs = CertificateDer::pem_file_iter(ssl_ca).map_err(|e| {
+                anyhow!(
+                    "Failed to read the SSL CA certificate {}: {e}",
+                    ssl_ca.display()
+                )
+            })?;
+            for cert in certs {
+                root_store.add(cert?)?;
             }
+            let tls_config = rustls::ClientConfig::builder()
+                .with_root_certificates(root_store)
+                .with_no_client_auth();
+
+            deadpool_postgres::Manager::from_config(
+                pg_config,
+                tokio_postgres_rustls::MakeRustlsConnect::new(tls_config),
+                manager_config,
+            )
+        }
+        None => {
+            warn!("POSTGRES_SSL_CA is not set, connecting to Postgres without TLS");
+            deadpool_postgres::Manager::from_config(pg_config, NoTls, manager_config)
         }
     };
 
+    // create connection pool
+    info!("Creating Postgres connection pool...");
+    let pool = deadpool_postgres::Pool::builder(manager)
+        .build()
+        .map_err(|e| {
+            let error_message = format!("Failed to create connection pool: {e}");
+            error!(error_message);
+            anyhow!(error_message)
+        })?;
+
+    Ok(pool)
+}
+
+/// Build the authenticator of the HTTP transports from its settings, or `None` if neither API
+/// keys nor a JWKS file are configured
+fn build_authenticator(settings: &AuthSettings) -> anyhow::Result<Option<auth::Authenticator>> {
+    let mut api_key_entries = settings.api_keys.clone().unwrap_or_default();
+    if let Some(api_keys_file) = &settings.api_keys_file {
+        api_key_entries.extend(auth::read_api_key_file(api_keys_file)?);
+    }
+
+    if api_key_entries.is_empty() && settings.jwks_file.is_none() {
+        return Ok(None);
+    }
+
+    let jwt_config = auth::JwtConfig {
+        issuers: settings.jwt_issuers.clone().unwrap_or_default(),
+        audiences: settings.jwt_audiences.clone().unwrap_or_default(),
+    };
+
+    let authenticator =
+        auth::Authenticator::new(&api_key_entries, settings.jwks_file.as_deref(), jwt_config)?;
     info!(
-        "Starting Cardea Agentic Search MCP server on {}",
-        args.socket_addr
+        "Authenticating the clients with {} API key(s) and {} JWT key(s)",
+        authenticator.api_key_count(),
+        authenticator.jwk_count()
     );
 
-    let ct = tokio_util::sync::CancellationToken::new();
+    Ok(Some(authenticator))
+}
 
-    let service = StreamableHttpService::new(
-        move || Ok(AgenticSearchServer::new(search_config.clone())),
-        LocalSessionManager::default().into(),
-        StreamableHttpServerConfig {
-            cancellation_token: ct.clone(),
-            ..Default::default()
-        },
-    );
+/// Build the Tantivy backend from its validated settings
+fn build_tantivy_backend(
+    name: String,
+    settings: &TantivySettings,
+    chat_service: Option<ServiceConfig>,
+) -> anyhow::Result<TantivyBackend> {
+    let tantivy_index_dir = settings.index_dir.clone().expect(VALIDATED);
 
-    let router = axum::Router::new().nest_service("/mcp", service);
-    let tcp_listener = tokio::net::TcpListener::bind(args.socket_addr).await?;
-    let _ = axum::serve(tcp_listener, router)
-        .with_graceful_shutdown(async move {
-            ct.cancelled().await;
-        })
-        .await;
+    // open the index
+    info!("Opening Tantivy index...");
+    let index = tantivy::Index::open_in_dir(&tantivy_index_dir).map_err(|e| {
+        let error_message = format!(
+            "Failed to open the Tantivy index in {}, build it with the `index` command: {e}",
+            tantivy_index_dir.display()
+        );
+        error!(error_message);
+        anyhow!(error_message)
+    })?;
+    let payload = match index.load_metas()?.payload {
+        Some(payload) => serde_json::from_str::<IndexPayload>(&payload).map_err(|e| {
+            anyhow!(
+                "The Tantivy index in {} was not built by the `index` command: {e}",
+                tantivy_index_dir.display()
+            )
+        })?,
+        None => IndexPayload::default(),
+    };
 
-    Ok(())
+    TantivyBackend::new(
+        name,
+        TantivyConfig {
+            index_dir: tantivy_index_dir.clone(),
+            index,
+            payload,
+            return_field: settings
+                .return_fields
+                .clone()
+                .unwrap_or_else(|| vec!["*".to_string()]),
+            title_field: settings
+                .title_field
+                .clone()
+                .unwrap_or_else(|| DEFAULT_TANTIVY_TITLE_FIELD.to_string()),
+            metadata_fields: settings.metadata_fields.clone(),
+            score_threshold: settings
+                .score_threshold
+                .unwrap_or(DEFAULT_KEYWORD_SCORE_THRESHOLD),
+        },
+        chat_service,
+    )
+    .map_err(|e| {
+        anyhow!(
+            "The Tantivy index in {} was not built by the `index` command: {e}",
+            tantivy_index_dir.display()
+        )
+    })
 }
 
-#[derive(Debug, Clone)]
-pub struct AgenticSearchConfig {
-    pub qdrant_config: Option<QdrantConfig>,
-    pub tidb_config: Option<TiDBConfig>,
-    pub limit: u64,
-    pub score_threshold: f32,
-    pub chat_service: Option<ServiceConfig>,
-    pub embedding_service: Option<ServiceConfig>,
+/// The embedding service used to compute the embeddings of queries
+fn embedding_service_config(config: &Config) -> ServiceConfig {
+    ServiceConfig {
+        url: config.embedding_service.base_url.clone().expect(VALIDATED),
+        api_key: config.embedding_service.api_key.clone(),
+        model: config.embedding_service.model.clone(),
+        keyword_prompt: None,
+    }
 }
 
-#[derive(Debug, Clone)]
-pub struct QdrantConfig {
-    pub api_key: Option<String>,
-    pub base_url: String,
-    pub collection: String,
-    pub payload_source: String,
+/// The chat service used to extract the keywords of queries
+fn chat_service_config(config: &Config) -> ServiceConfig {
+    ServiceConfig {
+        url: config.chat_service.base_url.clone().expect(VALIDATED),
+        api_key: config.chat_service.api_key.clone(),
+        model: config.chat_service.model.clone(),
+        keyword_prompt: config.prompts.keyword_extractor.clone(),
+    }
 }
 
-#[derive(Debug, Clone)]
-pub struct TiDBConfig {
-    pub database: String,
-    pub table_name: String,
-    pub pool: Pool,
-    pub search_field: String,
-    pub return_field: Vec<String>,
+/// Install the process-wide rustls crypto provider, unless another backend already did
+fn install_crypto_provider() -> anyhow::Result<()> {
+    if CryptoProvider::get_default().is_some() {
+        return Ok(());
+    }
+
+    CryptoProvider::install_default(default_provider()).map_err(|e| {
+        let err_msg = format!("Failed to install default crypto provider: {e:?}");
+        error!("{}", err_msg);
+        anyhow!(err_msg)
+    })?;
+
+    Ok(())
 }
 
-#[derive(Debug, Clone)]
-pub struct ServiceConfig {
-    pub url: String,
-    pub api_key: Option<String>,
-    pub model: Option<String>,
+/// Resolve an optional setting with priority: Environment Variable > Command Line > None
+fn optional_env_or_arg(env_name: &str, arg_value: Option<String>) -> Option<String> {
+    match env::var(env_name) {
+        Ok(env_value) => {
+            info!("Using {} from environment: {}", env_name, env_value);
+            Some(env_value)
+        }
+        Err(_) => {
+            if let Some(arg_value) = &arg_value {
+                info!(
+                    "Using {} from command line argument: {}",
+                    env_name, arg_value
+                );
+            }
+            arg_value
+        }
+    }
+}
+
+fn parse_fusion_weight(s: &str) -> Result<(String, f64), String> {
+    let (backend, weight) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid fusion weight `{s}`, expected `<BACKEND>=<WEIGHT>`"))?;
+    let weight = weight
+        .trim()
+        .parse::<f64>()
+        .map_err(|e| format!("invalid weight in `{s}`: {e}"))?;
+    // NaN would poison every fused score
+    if !(weight.is_finite() && weight >= 0.0) {
+        return Err(format!(
+            "fusion weight in `{s}` must be a finite number that is not negative"
+        ));
+    }
+    Ok((backend.trim().to_string(), weight))
 }
 
-fn parse_tidb_conn_str(conn_str: &str) -> Option<(String, String, String, String, String)> {
-    let re = Regex::new(r"^mysql://([^:]+):([^@]+)@([^:/]+):(\d+)/(.+)$").unwrap();
-    if let Some(caps) = re.captures(conn_str) {
-        let username = caps.get(1)?.as_str().to_string();
-        let password = caps.get(2)?.as_str().to_string();
-        let host = caps.get(3)?.as_str().to_string();
-        let port = caps.get(4)?.as_str().to_string();
-        let database = caps.get(5)?.as_str().to_string();
-        Some((username, password, host, port, database))
-    } else {
-        None
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_fusion_weights_are_finite_and_not_negative() {
+        assert_eq!(
+            parse_fusion_weight(" qdrant = 2.5"),
+            Ok(("qdrant".to_string(), 2.5))
+        );
+        assert_eq!(
+            parse_fusion_weight("docs/tidb=0"),
+            Ok(("docs/tidb".to_string(), 0.0))
+        );
+
+        for weight in ["qdrant=-1", "qdrant=NaN", "qdrant=inf", "qdrant=-infinity"] {
+            assert_eq!(
+                parse_fusion_weight(weight),
+                Err(format!(
+                    "fusion weight in `{weight}` must be a finite number that is not negative"
+                ))
+            );
+        }
+        assert!(parse_fusion_weight("qdrant").is_err());
+        assert!(parse_fusion_weight("qdrant=heavy").is_err());
     }
 }
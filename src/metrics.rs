@@ -0,0 +1,297 @@
+//! Prometheus metrics of the search calls, served at `GET /metrics`.
+//!
+//! All metrics of the searches are labelled by the retrieval mode of the call (`vector`,
+//! `keyword` or `hybrid`).
+
+use crate::{limit, types::RetrievalMode};
+use axum::{
+    Router,
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder,
+};
+use rmcp::{ErrorData as McpError, model::ErrorCode};
+use std::{sync::LazyLock, time::Instant};
+use tracing::error;
+
+/// Path of the metrics endpoint
+pub const METRICS_PATH: &str = "/metrics";
+
+/// Buckets of the latency histograms, in seconds, from a cache hit to a slow LLM call
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+/// Buckets of the result count histograms
+const RESULT_COUNT_BUCKETS: &[f64] = &[0.0, 1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0];
+
+/// The stages of a search in a backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Computing the embedding of the query with the embedding service
+    Embedding,
+    /// Extracting the keywords of the query with the chat service
+    KeywordExtraction,
+    Qdrant,
+    Tidb,
+    Postgres,
+    Tantivy,
+}
+impl Stage {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Embedding => "embedding",
+            Stage::KeywordExtraction => "keyword_extraction",
+            Stage::Qdrant => "qdrant",
+            Stage::Tidb => "tidb",
+            Stage::Postgres => "postgres",
+            Stage::Tantivy => "tantivy",
+        }
+    }
+}
+
+struct Metrics {
+    registry: Registry,
+    tool_duration: HistogramVec,
+    tool_results: HistogramVec,
+    tool_empty_results: IntCounterVec,
+    stage_duration: HistogramVec,
+    backend_errors: IntCounterVec,
+    backend_results: HistogramVec,
+    backend_empty_results: IntCounterVec,
+    config_reloads: IntCounterVec,
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(|| {
+    let registry = Registry::new_custom(Some("agentic_search".to_string()), None)
+        .expect("the metric prefix is valid");
+
+    let histogram = |name: &str, help: &str, buckets: &[f64], labels: &[&str]| {
+        let histogram = HistogramVec::new(
+            HistogramOpts::new(name, help).buckets(buckets.to_vec()),
+            labels,
+        )
+        .expect("the histogram options are valid");
+        registry
+            .register(Box::new(histogram.clone()))
+            .expect("the metric is registered once");
+        histogram
+    };
+    let counter = |name: &str, help: &str, labels: &[&str]| {
+        let counter =
+            IntCounterVec::new(Opts::new(name, help), labels).expect("the options are valid");
+        registry
+            .register(Box::new(counter.clone()))
+            .expect("the metric is registered once");
+        counter
+    };
+
+    Metrics {
+        tool_duration: histogram(
+            "tool_duration_seconds",
+            "End-to-end latency of the search tool calls",
+            LATENCY_BUCKETS,
+            &["mode", "status"],
+        ),
+        tool_results: histogram(
+            "tool_results",
+            "Number of results returned by the search tool calls",
+            RESULT_COUNT_BUCKETS,
+            &["mode"],
+        ),
+        tool_empty_results: counter(
+            "tool_empty_results_total",
+            "Number of search tool calls that returned no results",
+            &["mode"],
+        ),
+        stage_duration: histogram(
+            "stage_duration_seconds",
+            "Latency of each stage of the searches in the backends",
+            LATENCY_BUCKETS,
+            &["mode", "backend", "stage"],
+        ),
+        backend_errors: counter(
+            "backend_errors_total",
+            "Number of failed searches in the backends, by the stage that failed or `timeout`",
+            &["mode", "backend", "kind"],
+        ),
+        backend_results: histogram(
+            "backend_results",
+            "Number of hits returned by the searches in the backends",
+            RESULT_COUNT_BUCKETS,
+            &["mode", "backend"],
+        ),
+        backend_empty_results: counter(
+            "backend_empty_results_total",
+            "Number of searches in the backends that returned no hits",
+            &["mode", "backend"],
+        ),
+        config_reloads: counter(
+            "config_reloads_total",
+            "Number of reloads of the configuration file, by whether they succeeded",
+            &["status"],
+        ),
+        registry,
+    }
+});
+
+/// Record a completed `search` tool call, with the number of results it returned or its error
+///
+/// `mode` is `None` if the call was rejected before its retrieval mode was resolved.
+pub fn record_tool_call(
+    mode: Option<RetrievalMode>,
+    start: Instant,
+    result: Result<usize, &McpError>,
+) {
+    let mode = mode.map_or("unknown".to_string(), |mode| mode.to_string());
+    let status = match result {
+        Ok(_) => "ok",
+        Err(e) if e.code == ErrorCode::INVALID_PARAMS => "invalid_params",
+        Err(e) if e.code == limit::RATE_LIMITED => "throttled",
+        Err(_) => "error",
+    };
+    METRICS
+        .tool_duration
+        .with_label_values(&[mode.as_str(), status])
+        .observe(start.elapsed().as_secs_f64());
+    if let Ok(result_count) = result {
+        METRICS
+            .tool_results
+            .with_label_values(&[mode.as_str()])
+            .observe(result_count as f64);
+        if result_count == 0 {
+            METRICS
+                .tool_empty_results
+                .with_label_values(&[mode.as_str()])
+                .inc();
+        }
+    }
+}
+
+/// Run a stage of a search in a backend, recording its latency, and counting it as the kind of
+/// the error if it fails
+pub async fn time_stage<T>(
+    mode: RetrievalMode,
+    backend: &str,
+    stage: Stage,
+    future: impl Future<Output = Result<T, McpError>>,
+) -> Result<T, McpError> {
+    let start = Instant::now();
+    let result = future.await;
+    let mode = mode.to_string();
+    METRICS
+        .stage_duration
+        .with_label_values(&[mode.as_str(), backend, stage.as_str()])
+        .observe(start.elapsed().as_secs_f64());
+    if result.is_err() {
+        record_backend_error(&mode, backend, stage.as_str());
+    }
+
+    result
+}
+
+/// Count a search in a backend that timed out
+pub fn record_backend_timeout(mode: RetrievalMode, backend: &str) {
+    record_backend_error(&mode.to_string(), backend, "timeout");
+}
+
+fn record_backend_error(mode: &str, backend: &str, kind: &str) {
+    METRICS
+        .backend_errors
+        .with_label_values(&[mode, backend, kind])
+        .inc();
+}
+
+/// Record the number of hits returned by a search in a backend
+pub fn record_backend_results(mode: RetrievalMode, backend: &str, hit_count: usize) {
+    let mode = mode.to_string();
+    METRICS
+        .backend_results
+        .with_label_values(&[mode.as_str(), backend])
+        .observe(hit_count as f64);
+    if hit_count == 0 {
+        METRICS
+            .backend_empty_results
+            .with_label_values(&[mode.as_str(), backend])
+            .inc();
+    }
+}
+
+/// Count a reload of the configuration file, which keeps the current configuration if it failed
+pub fn record_config_reload(succeeded: bool) {
+    let status = if succeeded { "ok" } else { "error" };
+    METRICS.config_reloads.with_label_values(&[status]).inc();
+}
+
+/// Build the router of the metrics endpoint
+pub fn router() -> Router {
+    Router::new().route(METRICS_PATH, get(metrics_handler))
+}
+
+async fn metrics_handler() -> Response {
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    if let Err(e) = encoder.encode(&METRICS.registry.gather(), &mut buffer) {
+        let error_message = format!("Failed to encode the metrics: {e}");
+        error!("{}", error_message);
+        return (StatusCode::INTERNAL_SERVER_ERROR, error_message).into_response();
+    }
+
+    (
+        [(header::CONTENT_TYPE, encoder.format_type().to_string())],
+        buffer,
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The metrics as served by the endpoint
+    async fn exposition() -> String {
+        let response = metrics_handler().await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn the_stages_are_timed_and_their_failures_counted() {
+        // the registry is global, so the test uses a backend of its own
+        let backend = "metrics-test";
+        for result in [Ok(()), Ok(()), Err(McpError::internal_error("boom", None))] {
+            let stage = time_stage(
+                RetrievalMode::Hybrid,
+                backend,
+                Stage::KeywordExtraction,
+                async {
+                    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+                    result
+                },
+            );
+            let _ = stage.await;
+        }
+        record_backend_timeout(RetrievalMode::Hybrid, backend);
+
+        let metrics = exposition().await;
+        let labels = r#"backend="metrics-test",mode="hybrid",stage="keyword_extraction""#;
+        assert!(metrics.contains(&format!(
+            "agentic_search_stage_duration_seconds_count{{{labels}}} 3\n"
+        )));
+        // none of the stages took less than the smallest bucket
+        assert!(metrics.contains(&format!(
+            "agentic_search_stage_duration_seconds_bucket{{{labels},le=\"0.005\"}} 0\n"
+        )));
+        assert!(metrics.contains(
+            "agentic_search_backend_errors_total{backend=\"metrics-test\",kind=\"keyword_extraction\",mode=\"hybrid\"} 1\n"
+        ));
+        assert!(metrics.contains(
+            "agentic_search_backend_errors_total{backend=\"metrics-test\",kind=\"timeout\",mode=\"hybrid\"} 1\n"
+        ));
+    }
+}
@@ -0,0 +1,178 @@
+//! Diversification of the search results with Maximal Marginal Relevance.
+//!
+//! Neighbouring chunks of a document tend to match a query equally well, so the best results are
+//! often near copies of each other. MMR picks the results one at a time, each maximizing
+//! `lambda * relevance - (1 - lambda) * redundancy`, where the relevance is the score of the
+//! result normalized to `[0, 1]`, and the redundancy is its highest cosine similarity to the
+//! results already picked, computed from the vectors returned by the vector backends.
+
+use crate::{fusion, types::SearchResult};
+
+/// Default number of candidates retrieved per result of the page when diversifying
+pub const DEFAULT_MMR_OVERFETCH: u64 = 3;
+
+#[derive(Debug, Clone)]
+pub struct MmrConfig {
+    /// The trade-off between relevance (1.0) and diversity (0.0) of the calls that do not set
+    /// one. The results are not diversified if `None`
+    pub lambda: Option<f64>,
+    /// Number of candidates retrieved per result of the page
+    pub overfetch: u64,
+}
+
+/// Reorder the results, ranked best first, to trade their relevance against their redundancy,
+/// and keep the `keep` first ones
+///
+/// The results without a vector, e.g. from the keyword backends, are never redundant, so they
+/// keep their place relative to each other. The ranks follow the new order, and the scores stay
+/// the relevance scores.
+pub fn diversify(results: Vec<SearchResult>, lambda: f64, keep: usize) -> Vec<SearchResult> {
+    let relevance =
+        fusion::normalize_scores(results.iter().map(|result| (result.score, result.rank)));
+    let norms: Vec<Option<f64>> = results
+        .iter()
+        .map(|result| result.vector.as_deref().map(norm))
+        .collect();
+
+    let mut candidates: Vec<Option<SearchResult>> = results.into_iter().map(Some).collect();
+    // the highest similarity of each candidate to the picked results
+    let mut redundancy = vec![0.0; candidates.len()];
+    let mut picked: Vec<SearchResult> = Vec::with_capacity(keep.min(candidates.len()));
+    while picked.len() < keep {
+        // `max_by` keeps the last of equal elements, so the candidates are visited in reverse
+        // for the ties to go to the best ranked one
+        let Some(next) = (0..candidates.len())
+            .rev()
+            .filter(|&index| candidates[index].is_some())
+            .max_by(|&a, &b| {
+                let mmr =
+                    |index: usize| lambda * relevance[index] - (1.0 - lambda) * redundancy[index];
+                mmr(a).total_cmp(&mmr(b))
+            })
+        else {
+            break;
+        };
+
+        let result = candidates[next]
+            .take()
+            .expect("the candidate is not picked yet");
+        if let (Some(vector), Some(vector_norm)) = (&result.vector, norms[next]) {
+            for (index, candidate) in candidates.iter().enumerate() {
+                if let Some(candidate) = candidate
+                    && let (Some(candidate_vector), Some(candidate_norm)) =
+                        (&candidate.vector, norms[index])
+                {
+                    let similarity =
+                        cosine_similarity(vector, vector_norm, candidate_vector, candidate_norm);
+                    redundancy[index] = f64::max(redundancy[index], similarity);
+                }
+            }
+        }
+        picked.push(result);
+    }
+
+    for (index, result) in picked.iter_mut().enumerate() {
+        result.rank = index + 1;
+    }
+
+    picked
+}
+
+fn norm(vector: &[f64]) -> f64 {
+    vector.iter().map(|x| x * x).sum::<f64>().sqrt()
+}
+
+/// The cosine similarity of two vectors, 0 if they differ in dimension or one of them is zero
+fn cosine_similarity(a: &[f64], a_norm: f64, b: &[f64], b_norm: f64) -> f64 {
+    if a.len() != b.len() || a_norm == 0.0 || b_norm == 0.0 {
+        return 0.0;
+    }
+    let dot: f64 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+
+    dot / (a_norm * b_norm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Map;
+
+    fn result(source: &str, rank: usize, score: f64, vector: Option<Vec<f64>>) -> SearchResult {
+        SearchResult {
+            id: None,
+            backend: "qdrant".to_string(),
+            rank,
+            score: Some(score),
+            title: None,
+            source: source.to_string(),
+            metadata: Map::new(),
+            matches: Vec::new(),
+            vector,
+        }
+    }
+
+    /// Two near copies of a document and a less relevant, different one
+    fn results() -> Vec<SearchResult> {
+        vec![
+            result("a", 1, 1.0, Some(vec![1.0, 0.0])),
+            result("a'", 2, 0.95, Some(vec![0.99, 0.1])),
+            result("b", 3, 0.8, Some(vec![0.0, 1.0])),
+        ]
+    }
+
+    fn ranked(results: &[SearchResult]) -> Vec<(&str, usize)> {
+        results
+            .iter()
+            .map(|result| (result.source.as_str(), result.rank))
+            .collect()
+    }
+
+    #[test]
+    fn the_relevance_alone_keeps_the_order() {
+        let diversified = diversify(results(), 1.0, 2);
+        assert_eq!(ranked(&diversified), [("a", 1), ("a'", 2)]);
+    }
+
+    #[test]
+    fn the_near_copies_are_pushed_down() {
+        let diversified = diversify(results(), 0.5, 3);
+        assert_eq!(ranked(&diversified), [("a", 1), ("b", 2), ("a'", 3)]);
+        // the scores stay the relevance scores
+        let scores: Vec<Option<f64>> = diversified.iter().map(|result| result.score).collect();
+        assert_eq!(scores, [Some(1.0), Some(0.8), Some(0.95)]);
+    }
+
+    #[test]
+    fn the_results_without_vectors_are_never_redundant() {
+        let mut results = results();
+        results[1].vector = None;
+        let diversified = diversify(results, 0.5, 3);
+        assert_eq!(ranked(&diversified), [("a", 1), ("a'", 2), ("b", 3)]);
+    }
+
+    #[test]
+    fn the_ties_go_to_the_best_ranked() {
+        let results = vec![
+            result("a", 1, 0.5, None),
+            result("b", 2, 0.5, None),
+            result("c", 3, 0.5, None),
+        ];
+        let diversified = diversify(results, 0.3, 10);
+        assert_eq!(ranked(&diversified), [("a", 1), ("b", 2), ("c", 3)]);
+    }
+
+    #[test]
+    fn nothing_is_kept_from_nothing() {
+        assert!(diversify(Vec::new(), 0.5, 10).is_empty());
+        assert!(diversify(results(), 0.5, 0).is_empty());
+    }
+
+    #[test]
+    fn the_similarity_of_incomparable_vectors_is_zero() {
+        let similarity = |a: &[f64], b: &[f64]| cosine_similarity(a, norm(a), b, norm(b));
+        assert!((similarity(&[1.0, 1.0], &[2.0, 2.0]) - 1.0).abs() < 1e-12);
+        assert_eq!(similarity(&[1.0, 0.0], &[0.0, 1.0]), 0.0);
+        assert_eq!(similarity(&[1.0, 0.0], &[1.0, 0.0, 0.0]), 0.0);
+        assert_eq!(similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+    }
+}
@@ -0,0 +1,238 @@
+//! Reloading of the configuration file while the server runs.
+//!
+//! The file is read again on SIGHUP, or when its modification time changes. The search config
+//! built from it is swapped in for the tool calls that start afterwards, while the calls in
+//! progress finish with the one they started with and the MCP sessions stay open. A file that
+//! fails to load or validate is reported and the current configuration is kept.
+
+use crate::{
+    AgenticSearchConfig, ConnectionPools, build_search_config, check_backends, config::Config,
+    health::StatusRegistry, metrics, monitor_backends,
+};
+use anyhow::anyhow;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::sync::{Notify, watch};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+/// How often the modification time of the configuration file is checked
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Reloads the configuration file and swaps the search config of the server
+pub struct ConfigReloader {
+    pub config_file: PathBuf,
+    pub profile: Option<String>,
+    /// The settings of the command line, which keep overriding the file
+    pub command_line: Config,
+    /// The configuration in use
+    pub config: Config,
+    /// The connection pools of the search config in use
+    pub pools: ConnectionPools,
+    pub status: StatusRegistry,
+    pub search_config: watch::Sender<Arc<AgenticSearchConfig>>,
+}
+
+impl ConfigReloader {
+    /// Reload the configuration on SIGHUP or when the file changes, until shutdown
+    pub async fn run(mut self, shutdown: CancellationToken) {
+        let hangup = Arc::new(Notify::new());
+        #[cfg(unix)]
+        tokio::spawn(notify_on_hangup(hangup.clone()));
+
+        info!(
+            "Reloading the configuration file {} on SIGHUP or when it changes",
+            self.config_file.display()
+        );
+        let mut modified = modified_time(&self.config_file).await;
+        let mut interval = tokio::time::interval(WATCH_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = hangup.notified() => info!(
+                    "Received SIGHUP, reloading the configuration file {}...",
+                    self.config_file.display()
+                ),
+                _ = interval.tick() => {
+                    let last_modified = modified_time(&self.config_file).await;
+                    if last_modified == modified {
+                        continue;
+                    }
+                    modified = last_modified;
+                    info!(
+                        "The configuration file {} changed, reloading it...",
+                        self.config_file.display()
+                    );
+                }
+            }
+
+            let result = self.reload().await;
+            if let Err(e) = &result {
+                error!("Failed to reload the configuration, keeping the current one: {e:#}");
+            }
+            metrics::record_config_reload(result.is_ok());
+        }
+
+        // the connection pools may close their connections with blocking writes
+        if let Err(e) = tokio::task::spawn_blocking(move || drop(self)).await {
+            warn!("Failed to close the connection pools: {e}");
+        }
+    }
+
+    async fn reload(&mut self) -> anyhow::Result<()> {
+        let config_file = self.config_file.clone();
+        let profile = self.profile.clone();
+        let command_line = self.command_line.clone();
+        let config = tokio::task::spawn_blocking(move || {
+            let mut config = Config::load(&config_file, profile.as_deref())?.merge(command_line)?;
+            config.apply_env();
+            config.validate()?;
+            anyhow::Ok(config)
+        })
+        .await??;
+        if config == self.config {
+            info!("The configuration is unchanged");
+            return Ok(());
+        }
+        for section in self.config.startup_changes(&config) {
+            warn!(
+                "The `{}` settings changed, restart the server to apply them",
+                section
+            );
+        }
+
+        // opening the connection pools and the indexes blocks
+        let mut pools = std::mem::take(&mut self.pools);
+        let (search_config, pools) = tokio::task::spawn_blocking(move || {
+            let search_config = build_search_config(&config, &mut pools);
+            (
+                search_config.map(|search_config| (search_config, config)),
+                pools,
+            )
+        })
+        .await
+        .map_err(|e| anyhow!("Failed to build the search config: {e}"))?;
+        self.pools = pools;
+        let (mut search_config, config) = search_config?;
+
+        check_backends(&search_config).await;
+        monitor_backends(&mut search_config, &self.status);
+        let previous = self.search_config.send_replace(Arc::new(search_config));
+        // the tool calls in progress may still hold the previous search config, the last one
+        // to finish closes the connection pools it no longer shares with the new one
+        tokio::task::spawn_blocking(move || drop(previous)).await?;
+
+        info!(
+            "Reloaded the configuration, the settings that are not set take their defaults:\n{}",
+            config.redacted_summary()
+        );
+        self.config = config;
+
+        Ok(())
+    }
+}
+
+/// The modification time of the file, or `None` if it cannot be read
+async fn modified_time(path: &Path) -> Option<SystemTime> {
+    tokio::fs::metadata(path)
+        .await
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Notify the reloader on each SIGHUP
+#[cfg(unix)]
+async fn notify_on_hangup(hangup: Arc<Notify>) {
+    let mut signal = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+        Ok(signal) => signal,
+        Err(e) => {
+            error!("Failed to listen for SIGHUP: {e}");
+            return;
+        }
+    };
+    while signal.recv().await.is_some() {
+        hangup.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        [qdrant]
+        base_url = "http://127.0.0.1:1"
+        collection = "docs"
+        payload_field = "text"
+        [embedding_service]
+        base_url = "http://127.0.0.1:1/v1"
+    "#;
+
+    fn write_config(path: &Path, search: &str) {
+        std::fs::write(path, format!("[search]\n{search}\n{CONFIG}")).unwrap();
+    }
+
+    #[tokio::test]
+    async fn the_search_config_is_swapped_on_reload() {
+        let config_file =
+            std::env::temp_dir().join(format!("cardea-reload-{}.toml", std::process::id()));
+        write_config(&config_file, "limit = 5");
+        // the command line keeps overriding the file
+        let command_line: Config = toml::from_str("[limits]\nmax_limit = 50").unwrap();
+        let config = Config::load(&config_file, None)
+            .unwrap()
+            .merge(command_line.clone())
+            .unwrap();
+        let mut pools = ConnectionPools::default();
+        let search_config = build_search_config(&config, &mut pools).unwrap();
+        let (sender, mut receiver) = watch::channel(Arc::new(search_config));
+        let mut reloader = ConfigReloader {
+            config_file: config_file.clone(),
+            profile: None,
+            command_line,
+            config,
+            pools,
+            status: StatusRegistry::default(),
+            search_config: sender,
+        };
+
+        write_config(&config_file, "limit = 7");
+        reloader.reload().await.unwrap();
+        let search_config = receiver.borrow_and_update().clone();
+        assert_eq!(search_config.limit, 7);
+        assert_eq!(search_config.max_limit, 50);
+        assert_eq!(reloader.config.search.limit, Some(7));
+
+        // an unchanged file keeps the search config
+        reloader.reload().await.unwrap();
+        assert!(!receiver.has_changed().unwrap());
+
+        // an invalid file keeps the search config
+        write_config(&config_file, "limit = 0");
+        assert!(reloader.reload().await.is_err());
+        assert!(!receiver.has_changed().unwrap());
+        assert_eq!(reloader.config.search.limit, Some(7));
+
+        std::fs::remove_file(config_file).unwrap();
+    }
+
+    #[test]
+    fn the_startup_settings_changes_are_reported() {
+        let config: Config = toml::from_str(CONFIG).unwrap();
+        let mut changed = config.clone();
+        changed.search.limit = Some(7);
+        assert!(config.startup_changes(&changed).is_empty());
+
+        changed.server.shutdown_timeout = Some(5);
+        changed.limits.rate_limit = Some(60);
+        changed.auth.api_keys = Some(Vec::new());
+        assert_eq!(
+            config.startup_changes(&changed),
+            ["server", "limits.rate_limit", "auth"]
+        );
+    }
+}
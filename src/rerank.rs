@@ -0,0 +1,422 @@
+//! Reranking of the search results with a cross-encoder or the chat model.
+//!
+//! When reranking is enabled, the backends are asked for more candidates than the page holds, by
+//! the over-fetch factor. The reranker orders the candidates by their relevance to the query
+//! before the page is cut from them:
+//!
+//! - `cross-encoder`: a rerank service scores each candidate against the query at its `/rerank`
+//!   endpoint.
+//! - `llm`: the chat service orders the candidates listwise. As the whole list goes into a single
+//!   prompt, the number of candidates and the length of their passages are bounded.
+
+use crate::{ServiceConfig, services, types::SearchResult};
+use clap::ValueEnum;
+use regex::Regex;
+use rmcp::{ErrorData as McpError, model::ErrorCode};
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+use tracing::{error, info};
+
+/// Default number of candidates retrieved per result of the page when reranking
+pub const DEFAULT_RERANK_OVERFETCH: u64 = 3;
+/// Default number of candidates the chat model orders
+pub const DEFAULT_LLM_RERANK_MAX_CANDIDATES: u64 = 20;
+/// Default number of tokens of the passages given to the chat model
+pub const DEFAULT_LLM_RERANK_MAX_TOKENS: u64 = 4000;
+
+/// Rough number of characters per token, to keep the passages within the token budget without
+/// a tokenizer of the model
+const CHARS_PER_TOKEN: usize = 4;
+
+static PASSAGE_NUMBER_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\d+").unwrap());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RerankMethod {
+    /// A cross-encoder served at the `/rerank` endpoint of the rerank service
+    CrossEncoder,
+    /// The chat model of the chat service, ordering the candidates listwise
+    Llm,
+}
+
+#[derive(Debug, Clone)]
+pub enum Reranker {
+    CrossEncoder {
+        /// The rerank service, serving the `/rerank` endpoint
+        service: ServiceConfig,
+    },
+    Llm {
+        /// The chat service
+        service: ServiceConfig,
+        /// The ranking instructions. Defaults to the built-in prompt
+        prompt: Option<String>,
+        /// Number of candidates ordered by the chat model, the others follow in retrieval order
+        max_candidates: u64,
+        /// Number of tokens of all the passages together, shared evenly by the candidates
+        max_tokens: u64,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct RerankConfig {
+    pub reranker: Reranker,
+    /// Number of candidates retrieved per result of the page
+    pub overfetch: u64,
+}
+impl RerankConfig {
+    /// The number of candidates to retrieve to rerank the first `count` results
+    pub fn candidate_count(&self, count: u64) -> u64 {
+        let candidate_count = count.saturating_mul(self.overfetch);
+        match &self.reranker {
+            Reranker::CrossEncoder { .. } => candidate_count,
+            // the page is still filled if it reaches past the candidates the model orders
+            Reranker::Llm { max_candidates, .. } => candidate_count.min(*max_candidates).max(count),
+        }
+    }
+}
+
+/// Reorder the results by their relevance to the query, and keep the `keep` best ones
+///
+/// The ranks and scores follow the new order. The results are left as they are if the reranker
+/// fails.
+pub async fn rerank(
+    config: &RerankConfig,
+    query: &str,
+    results: &mut Vec<SearchResult>,
+    keep: usize,
+) -> Result<(), McpError> {
+    if results.is_empty() {
+        return Ok(());
+    }
+
+    let ranking = match &config.reranker {
+        Reranker::CrossEncoder { service } => {
+            cross_encoder_ranking(service, query, results, keep).await?
+        }
+        Reranker::Llm {
+            service,
+            prompt,
+            max_candidates,
+            max_tokens,
+        } => {
+            llm_ranking(
+                service,
+                prompt.as_deref(),
+                query,
+                results,
+                *max_candidates as usize,
+                *max_tokens as usize,
+            )
+            .await?
+        }
+    };
+
+    let mut candidates: Vec<Option<SearchResult>> = results.drain(..).map(Some).collect();
+    results.extend(
+        ranking
+            .into_iter()
+            .filter_map(|(index, score)| {
+                candidates[index].take().map(|mut result| {
+                    result.score = score;
+                    result
+                })
+            })
+            .take(keep)
+            .enumerate()
+            .map(|(index, mut result)| {
+                result.rank = index + 1;
+                result
+            }),
+    );
+
+    Ok(())
+}
+
+/// Score the results with the cross-encoder, and return the position of the best ones with
+/// their relevance score as their new score, best first
+async fn cross_encoder_ranking(
+    service: &ServiceConfig,
+    query: &str,
+    results: &[SearchResult],
+    keep: usize,
+) -> Result<Vec<(usize, Option<f64>)>, McpError> {
+    info!("Reranking {} candidate(s) ...", results.len());
+    let documents: Vec<&str> = results
+        .iter()
+        .map(|result| result.source.as_str())
+        .collect();
+    let mut ranking =
+        services::rerank(service, query, &documents, keep.min(documents.len())).await?;
+
+    // the services return the best documents first, but do not all promise it
+    ranking.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    Ok(ranking
+        .into_iter()
+        .map(|(index, relevance_score)| (index, Some(relevance_score)))
+        .collect())
+}
+
+/// Order the first `max_candidates` results with the chat model, and return the position of all
+/// the results in their new order
+///
+/// The model only orders the candidates, so they are scored by their new position, from 1 for
+/// the first down to `1 / n` for the last of `n`. The results past the candidates follow unscored, as
+/// their retrieval scores do not compare with the others.
+async fn llm_ranking(
+    service: &ServiceConfig,
+    prompt: Option<&str>,
+    query: &str,
+    results: &[SearchResult],
+    max_candidates: usize,
+    max_tokens: usize,
+) -> Result<Vec<(usize, Option<f64>)>, McpError> {
+    let candidate_count = results.len().min(max_candidates);
+    info!(
+        "Reranking {} candidate(s) with the chat model ...",
+        candidate_count
+    );
+    let max_chars = max_tokens * CHARS_PER_TOKEN / candidate_count;
+    let passages: Vec<&str> = results[..candidate_count]
+        .iter()
+        .map(|result| truncate(&result.source, max_chars))
+        .collect();
+    let answer = services::rank_passages(service, prompt, query, &passages).await?;
+
+    let order = parse_ranking(&answer, candidate_count).ok_or_else(|| {
+        let err_msg = format!("Failed to parse the ranking of the chat model: {answer:?}");
+        error!("{}", err_msg);
+        McpError::new(ErrorCode::INTERNAL_ERROR, err_msg, None)
+    })?;
+
+    Ok(order
+        .into_iter()
+        .enumerate()
+        .map(|(position, index)| {
+            let score = 1.0 - position as f64 / candidate_count as f64;
+            (index, Some(score))
+        })
+        .chain((candidate_count..results.len()).map(|index| (index, None)))
+        .collect())
+}
+
+/// Parse the passage numbers listed by the chat model, e.g. `[2] > [3] > [1]`, into the
+/// positions of the passages from the most relevant
+///
+/// The numbers that are out of range or repeated are skipped, and the passages the model left
+/// out follow in their original order. Returns `None` if the answer lists no passage.
+fn parse_ranking(answer: &str, passage_count: usize) -> Option<Vec<usize>> {
+    let answer = services::strip_reasoning(answer);
+
+    let mut listed = vec![false; passage_count];
+    let mut order: Vec<usize> = PASSAGE_NUMBER_RE
+        .find_iter(answer)
+        .filter_map(|number| number.as_str().parse::<usize>().ok())
+        .filter(|number| (1..=passage_count).contains(number))
+        .map(|number| number - 1)
+        .filter(|&index| !std::mem::replace(&mut listed[index], true))
+        .collect();
+    if order.is_empty() {
+        return None;
+    }
+    order.extend((0..passage_count).filter(|&index| !listed[index]));
+
+    Some(order)
+}
+
+/// The longest prefix of the text with at most `max_chars` characters
+fn truncate(text: &str, max_chars: usize) -> &str {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, StubServices};
+    use axum::{Json, Router, routing::post};
+    use serde_json::{Map, Value, json};
+
+    fn result(source: &str, rank: usize) -> SearchResult {
+        SearchResult {
+            id: None,
+            backend: "qdrant".to_string(),
+            rank,
+            score: Some(0.5),
+            title: None,
+            source: source.to_string(),
+            metadata: Map::new(),
+            matches: Vec::new(),
+            vector: None,
+        }
+    }
+
+    /// Serve a rerank service answering every request with the results
+    async fn rerank_service(results: Value) -> RerankConfig {
+        let address = test_support::serve(Router::new().route(
+            "/rerank",
+            post(move || {
+                let results = results.clone();
+                async move { Json(json!({ "results": results })) }
+            }),
+        ))
+        .await;
+        RerankConfig {
+            reranker: Reranker::CrossEncoder {
+                service: ServiceConfig {
+                    url: format!("http://{address}"),
+                    api_key: None,
+                    model: None,
+                    keyword_prompt: None,
+                },
+            },
+            overfetch: DEFAULT_RERANK_OVERFETCH,
+        }
+    }
+
+    #[test]
+    fn the_listed_passages_come_first() {
+        assert_eq!(parse_ranking("[2] > [3] > [1]", 3), Some(vec![1, 2, 0]));
+        assert_eq!(parse_ranking("2, 3, 1", 3), Some(vec![1, 2, 0]));
+        // the passages left out follow in their original order
+        assert_eq!(parse_ranking("[4] > [2]", 5), Some(vec![3, 1, 0, 2, 4]));
+    }
+
+    #[test]
+    fn the_invalid_numbers_are_skipped() {
+        assert_eq!(
+            parse_ranking("[0] > [3] > [9] > [3] > [1]", 3),
+            Some(vec![2, 0, 1])
+        );
+        assert_eq!(
+            parse_ranking("[12345678901234567890123] > [2]", 2),
+            Some(vec![1, 0])
+        );
+    }
+
+    #[test]
+    fn the_reasoning_is_ignored() {
+        assert_eq!(
+            parse_ranking("<think>[1] looks better than [2]</think>\n[2] > [1]", 2),
+            Some(vec![1, 0])
+        );
+    }
+
+    #[test]
+    fn an_answer_without_passages_is_not_a_ranking() {
+        assert_eq!(parse_ranking("None of them is relevant", 3), None);
+        assert_eq!(parse_ranking("[4] > [5]", 3), None);
+        assert_eq!(parse_ranking("<think>[1] > [2]</think>", 2), None);
+    }
+
+    #[test]
+    fn the_passages_are_truncated_on_characters() {
+        assert_eq!(truncate("héllo", 2), "hé");
+        assert_eq!(truncate("héllo", 5), "héllo");
+        assert_eq!(truncate("héllo", 10), "héllo");
+    }
+
+    #[test]
+    fn the_chat_model_orders_at_most_its_candidates() {
+        let config = |reranker| RerankConfig {
+            reranker,
+            overfetch: 3,
+        };
+        let service = ServiceConfig {
+            url: "http://localhost:8080/v1".to_string(),
+            api_key: None,
+            model: None,
+            keyword_prompt: None,
+        };
+        let cross_encoder = config(Reranker::CrossEncoder {
+            service: service.clone(),
+        });
+        assert_eq!(cross_encoder.candidate_count(10), 30);
+
+        let llm = config(Reranker::Llm {
+            service,
+            prompt: None,
+            max_candidates: 20,
+            max_tokens: 4000,
+        });
+        assert_eq!(llm.candidate_count(5), 15);
+        assert_eq!(llm.candidate_count(10), 20);
+        assert_eq!(llm.candidate_count(30), 30);
+    }
+
+    #[tokio::test]
+    async fn the_results_are_reordered_by_the_chat_model() {
+        let services = StubServices::spawn(|_| Vec::new(), |_| "[3] > [1]".to_string()).await;
+        let config = RerankConfig {
+            reranker: Reranker::Llm {
+                service: services.config(),
+                prompt: None,
+                max_candidates: 3,
+                max_tokens: 4000,
+            },
+            overfetch: 2,
+        };
+        let mut results = vec![
+            result("first", 1),
+            result("second", 2),
+            result("third", 3),
+            result("fourth", 4),
+        ];
+
+        rerank(&config, "query", &mut results, 3).await.unwrap();
+        let reranked: Vec<(&str, usize, Option<f64>)> = results
+            .iter()
+            .map(|result| (result.source.as_str(), result.rank, result.score))
+            .collect();
+        assert_eq!(
+            reranked,
+            [
+                ("third", 1, Some(1.0)),
+                ("first", 2, Some(1.0 - 1.0 / 3.0)),
+                ("second", 3, Some(1.0 - 2.0 / 3.0)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn the_results_are_reordered_by_relevance() {
+        // not sorted, as some services answer
+        let config = rerank_service(json!([
+            { "index": 0, "relevance_score": 0.2 },
+            { "index": 2, "relevance_score": 0.9 },
+            { "index": 3, "relevance_score": 0.4 },
+        ]))
+        .await;
+        let mut results = vec![
+            result("first", 1),
+            result("second", 2),
+            result("third", 3),
+            result("fourth", 4),
+        ];
+
+        rerank(&config, "query", &mut results, 2).await.unwrap();
+        let reranked: Vec<(&str, usize, Option<f64>)> = results
+            .iter()
+            .map(|result| (result.source.as_str(), result.rank, result.score))
+            .collect();
+        assert_eq!(
+            reranked,
+            [("third", 1, Some(0.9)), ("fourth", 2, Some(0.4))]
+        );
+    }
+
+    #[tokio::test]
+    async fn the_documents_out_of_range_are_rejected() {
+        let config = rerank_service(json!([{ "index": 2, "relevance_score": 0.9 }])).await;
+        let mut results = vec![result("first", 1), result("second", 2)];
+
+        let error = rerank(&config, "query", &mut results, 2).await.unwrap_err();
+        assert!(
+            error.message.contains("returned the document 2 of 2"),
+            "{}",
+            error.message
+        );
+    }
+}
@@ -0,0 +1,183 @@
+//! Rewriting of the query into several variants before retrieval.
+//!
+//! Agents often send terse or conversational queries. When query rewriting is enabled, the chat
+//! service rewrites the query into paraphrases or sub-queries, each variant is searched in the
+//! backends, and the result lists of all the variants and backends are fused together. The
+//! reranking and the diversification still judge the results against the original query.
+
+use crate::{ServiceConfig, services};
+use regex::Regex;
+use std::{collections::HashSet, sync::LazyLock};
+use tracing::{info, warn};
+
+/// Maximum number of variants, as each one costs a search in every backend
+pub const MAX_QUERY_VARIANTS: u64 = 10;
+
+/// The numbering or bullet the chat models tend to put before each query despite the prompt
+static LIST_MARKER_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(?:[-*•]|\d+[.)]|\[\d+\])\s*").unwrap());
+
+#[derive(Debug, Clone)]
+pub struct RewriteConfig {
+    /// The chat service
+    pub service: ServiceConfig,
+    /// The rewriting instructions. Defaults to the built-in prompt
+    pub prompt: Option<String>,
+    /// Number of variants to ask for
+    pub variants: u64,
+    /// Whether the original query is searched along with its variants
+    pub include_original: bool,
+}
+
+/// The queries to search for the query, and a warning if rewriting it failed
+///
+/// If the chat service fails or returns no usable variant, only the original query is searched.
+pub async fn expand(config: &RewriteConfig, query: &str) -> (Vec<String>, Option<String>) {
+    let answer = match services::rewrite_query(
+        &config.service,
+        config.prompt.as_deref(),
+        query,
+        config.variants as usize,
+    )
+    .await
+    {
+        Ok(answer) => answer,
+        Err(e) => {
+            let warning = format!(
+                "Query rewriting failed, searching the original query only: {}",
+                e.message
+            );
+            warn!("{}", warning);
+            return (vec![query.to_string()], Some(warning));
+        }
+    };
+
+    let variants = parse_variants(&answer, query, config.variants as usize);
+    if variants.is_empty() {
+        let warning = format!(
+            "Query rewriting returned no variant, searching the original query only: {answer:?}"
+        );
+        warn!("{}", warning);
+        return (vec![query.to_string()], Some(warning));
+    }
+    info!("Rewrote the query {:?} into {:?}", query, variants);
+
+    let queries = match config.include_original {
+        true => std::iter::once(query.to_string()).chain(variants).collect(),
+        false => variants,
+    };
+
+    (queries, None)
+}
+
+/// Parse the queries listed by the chat model, one per line, into at most `count` variants that
+/// differ from each other and from the original query
+fn parse_variants(answer: &str, query: &str, count: usize) -> Vec<String> {
+    let answer = services::strip_reasoning(answer);
+
+    let mut seen = HashSet::from([query.trim().to_lowercase()]);
+    answer
+        .lines()
+        .map(|line| {
+            LIST_MARKER_RE
+                .replace(line.trim(), "")
+                .trim_matches(|c: char| c == '"' || c.is_whitespace())
+                .to_string()
+        })
+        .filter(|variant| !variant.is_empty() && seen.insert(variant.to_lowercase()))
+        .take(count)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::StubServices;
+
+    #[test]
+    fn the_list_markers_and_quotes_are_stripped() {
+        assert_eq!(
+            parse_variants(
+                "1. rust async runtime\n- \"tokio scheduler\"\n[3] futures executor\n* green threads",
+                "async rust",
+                4
+            ),
+            [
+                "rust async runtime",
+                "tokio scheduler",
+                "futures executor",
+                "green threads"
+            ]
+        );
+    }
+
+    #[test]
+    fn the_duplicates_and_blank_lines_are_skipped() {
+        assert_eq!(
+            parse_variants(
+                "Async Rust\n\ntokio scheduler\n  \n2) Tokio Scheduler\nfutures executor",
+                "async rust ",
+                3
+            ),
+            ["tokio scheduler", "futures executor"]
+        );
+    }
+
+    #[test]
+    fn at_most_the_requested_variants_are_kept() {
+        assert_eq!(parse_variants("a\nb\nc\nd", "query", 2), ["a", "b"]);
+    }
+
+    #[test]
+    fn the_reasoning_is_ignored() {
+        assert_eq!(
+            parse_variants(
+                "<think>\n1. not a query\n</think>\n1. tokio scheduler",
+                "q",
+                3
+            ),
+            ["tokio scheduler"]
+        );
+        assert!(parse_variants("<think>1. not a query</think>", "q", 3).is_empty());
+        assert!(parse_variants("", "q", 3).is_empty());
+    }
+
+    fn config(service: ServiceConfig, include_original: bool) -> RewriteConfig {
+        RewriteConfig {
+            service,
+            prompt: None,
+            variants: 2,
+            include_original,
+        }
+    }
+
+    #[tokio::test]
+    async fn the_variants_are_searched_with_the_original_query_if_configured() {
+        let stub = StubServices::spawn(|_| vec![1.0], |_| "tokio scheduler\nfutures".into()).await;
+
+        let (queries, warning) = expand(&config(stub.config(), true), "async rust").await;
+        assert_eq!(queries, ["async rust", "tokio scheduler", "futures"]);
+        assert_eq!(warning, None);
+
+        let (queries, _) = expand(&config(stub.config(), false), "async rust").await;
+        assert_eq!(queries, ["tokio scheduler", "futures"]);
+    }
+
+    #[tokio::test]
+    async fn a_failed_rewriting_falls_back_to_the_original_query() {
+        let stub = StubServices::spawn(|_| vec![1.0], |_| "Async Rust".into()).await;
+        let (queries, warning) = expand(&config(stub.config(), false), "async rust").await;
+        assert_eq!(queries, ["async rust"]);
+        assert!(warning.unwrap().contains("no variant"));
+
+        let unreachable = ServiceConfig {
+            url: "http://127.0.0.1:1/v1".to_string(),
+            api_key: None,
+            model: None,
+            keyword_prompt: None,
+        };
+        let (queries, warning) = expand(&config(unreachable, false), "async rust").await;
+        assert_eq!(queries, ["async rust"]);
+        assert!(warning.unwrap().contains("failed"));
+    }
+}
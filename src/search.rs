@@ -1,13 +1,13 @@
-use crate::{AgenticSearchConfig, types::*};
-use endpoints::{
-    chat::{
-        ChatCompletionObject, ChatCompletionRequestBuilder, ChatCompletionRequestMessage,
-        ChatCompletionUserMessageContent,
-    },
-    embeddings::{EmbeddingRequest, EmbeddingsResponse, InputText},
+use crate::{
+    AgenticSearchConfig, SearchIndex,
+    auth::Identity,
+    backend::{BackendKind, SearchBackend},
+    fusion, hyde,
+    limit::SearchLimiter,
+    metrics, mmr, rerank, rewrite, telemetry,
+    types::*,
 };
-use mysql::prelude::*;
-use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+use axum::{extract::ConnectInfo, http::request::Parts};
 use rmcp::{
     ErrorData as McpError, RoleServer, ServerHandler,
     handler::server::{router::tool::ToolRouter, wrapper::Parameters},
@@ -15,401 +15,540 @@ use rmcp::{
     service::RequestContext,
     tool, tool_handler, tool_router,
 };
-use serde_json::{Value, json};
-use std::collections::HashSet;
-use tracing::{debug, error, info, warn};
-
-const DEFAULT_PROMPT_KEYWORD_EXTRACTOR: &str = r#"
-You are a multilingual keyword extractor. Your task is to extract the most relevant and concise keywords or key phrases from the given user query.
-
-Follow these requirements strictly:
-- Detect the language of the query automatically.
-- Return 3 to 7 keywords or keyphrases that best represent the query's core intent.
-- Keep the extracted keywords in the **original language** (do not translate).
-- Include **multi-word expressions** if they convey meaningful concepts.
-- **Avoid all types of stop words, question words, filler words, or overly generic terms**, such as:
-  - English: what, how, why, is, the, of, and, etc.
-  - Chinese: 什么、怎么、如何、是、的、了、吗、啊 等。
-- Do **not** include punctuation or meaningless words.
-- Only return the final keywords, separated by a **single space**.
-
-Examples:
-- Input: "What is the impact of artificial intelligence on education?"
-  Output: artificial intelligence education impact
-- Input: "什么是人工智能对教育的影响？"
-  Output: 人工智能 教育 影响
-"#;
+use std::{net::SocketAddr, sync::Arc, time::Instant};
+use tokio::sync::watch;
+use tokio_util::task::TaskTracker;
+use tracing::{Instrument, Span, debug, error, field, info, info_span, warn};
 
 #[derive(Debug, Clone)]
 pub struct AgenticSearchServer {
-    config: AgenticSearchConfig,
+    /// The search config, swapped when the configuration is reloaded. Each tool call uses the
+    /// one in place when it starts
+    config: watch::Receiver<Arc<AgenticSearchConfig>>,
+    /// Tracks the requests in progress, and is closed once shutdown starts
+    in_flight: TaskTracker,
+    /// Throttles the searches, shared by all sessions
+    limiter: SearchLimiter,
     tool_router: ToolRouter<Self>,
 }
 #[tool_router]
 impl AgenticSearchServer {
-    pub fn new(config: AgenticSearchConfig) -> Self {
+    pub fn new(
+        config: watch::Receiver<Arc<AgenticSearchConfig>>,
+        in_flight: TaskTracker,
+        limiter: SearchLimiter,
+    ) -> Self {
         Self {
             config,
+            in_flight,
+            limiter,
             tool_router: Self::tool_router(),
         }
     }
 
-    #[tool(description = "Perform a search for the given query")]
+    #[tool(
+        description = "Perform a search for the given query. Use `target` to choose the index to search, `limit` and `offset` to page through the results, `score_threshold` to drop weak vector matches, `mode` to choose between vector, keyword or hybrid search, and `mmr_lambda` to diversify near-duplicate results",
+        output_schema = rmcp::handler::server::tool::schema_for_output::<SearchResponse>()
+            .expect("SearchResponse is a valid output schema")
+    )]
     async fn search(
         &self,
-        Parameters(SearchRequest { query }): Parameters<SearchRequest>,
+        Parameters(request): Parameters<SearchRequest>,
+        extensions: Extensions,
     ) -> Result<CallToolResult, McpError> {
-        match (
-            self.config.qdrant_config.is_some(),
-            self.config.tidb_config.is_some(),
-        ) {
-            (true, true) => {
-                let sources = self.combined_search(query).await?;
-                Ok(CallToolResult::success(vec![Content::text(
-                    sources.join("\n"),
-                )]))
-            }
-            (true, false) => {
-                let sources = self.vector_search(query).await?;
-                Ok(CallToolResult::success(vec![Content::text(
-                    sources.join("\n"),
-                )]))
-            }
-            (false, true) => {
-                let sources = self.keyword_search(query).await?;
-                Ok(CallToolResult::success(vec![Content::text(
-                    sources.join("\n"),
-                )]))
+        // the tracker is closed once shutdown starts, and only drains the requests already received
+        if self.in_flight.is_closed() {
+            let error_message = "The server is shutting down";
+            warn!("{}", error_message);
+            return Err(McpError::new(
+                ErrorCode::INTERNAL_ERROR,
+                error_message,
+                None,
+            ));
+        }
+
+        // continue the trace of the client and record its identity, if the call came over HTTP
+        let span = info_span!(
+            "search",
+            otel.kind = "server",
+            identity = field::Empty,
+            target = field::Empty,
+            mode = field::Empty
+        );
+        let parts = extensions.get::<Parts>();
+        if let Some(parts) = parts {
+            telemetry::set_parent_from_headers(&span, &parts.headers);
+            if let Some(identity) = parts.extensions.get::<Identity>() {
+                span.record("identity", identity.to_string());
             }
-            (false, false) => {
-                let error_message = "No search mode configured";
-                error!("{}", error_message);
-                Err(McpError::new(
-                    ErrorCode::INTERNAL_ERROR,
-                    error_message,
-                    None,
-                ))
+        }
+        let client = Self::client_key(parts);
+        let config = self.config.borrow().clone();
+
+        async {
+            let start = Instant::now();
+            let index = Self::resolve_target(&config, request.target.as_deref())
+                .inspect_err(|e| metrics::record_tool_call(None, start, Err(e)))?;
+            Span::current().record("target", index.name.as_str());
+            let mode = Self::resolve_mode(index, request.mode, request.filter.is_some())
+                .inspect_err(|e| metrics::record_tool_call(None, start, Err(e)))?;
+            Span::current().record("mode", mode.to_string());
+            let result = async {
+                let _permit = self.limiter.acquire(&client)?;
+                Self::search_in_mode(&config, index, &request, mode).await
             }
+            .await;
+            metrics::record_tool_call(
+                Some(mode),
+                start,
+                result.as_ref().map(|response| response.results.len()),
+            );
+
+            Self::into_call_tool_result(result?)
         }
+        .instrument(span)
+        .await
     }
 
-    async fn vector_search(&self, query: impl AsRef<str>) -> Result<Vec<String>, McpError> {
-        info!("Starting vector search ...");
-
-        // compute the embedding of the query
-        info!("Computing embedding of the query...");
-        let embedding = self.compute_embedding(query.as_ref()).await?;
-
-        // search in qdrant
-        info!("Searching in Qdrant...");
-        let hits = self.search_in_qdrant(embedding).await?;
+    #[tool(
+        description = "List the indexes that can be searched, with what each one holds, the retrieval modes it supports and its backends. Pass the name of an index as the `target` of a search",
+        output_schema = rmcp::handler::server::tool::schema_for_output::<ListIndexesResponse>()
+            .expect("ListIndexesResponse is a valid output schema")
+    )]
+    async fn list_indexes(&self) -> Result<CallToolResult, McpError> {
+        let config = self.config.borrow().clone();
+        let indexes: Vec<IndexDescription> = config
+            .indexes
+            .iter()
+            .map(|index| IndexDescription {
+                name: index.name.clone(),
+                description: index.description.clone(),
+                default: config.default_target.as_deref() == Some(index.name.as_str()),
+                modes: match Self::configured_mode(index) {
+                    Some(RetrievalMode::Hybrid) => vec![
+                        RetrievalMode::Vector,
+                        RetrievalMode::Keyword,
+                        RetrievalMode::Hybrid,
+                    ],
+                    Some(mode) => vec![mode],
+                    None => vec![],
+                },
+                backends: index
+                    .backends
+                    .iter()
+                    .map(|backend| backend.describe())
+                    .collect(),
+            })
+            .collect();
+
+        let lines: Vec<String> = indexes
+            .iter()
+            .map(|index| {
+                let modes: Vec<String> = index.modes.iter().map(ToString::to_string).collect();
+                let mut line = format!("{} (modes: {})", index.name, modes.join(", "));
+                if index.default {
+                    line.push_str(" [default]");
+                }
+                if let Some(description) = &index.description {
+                    line.push_str(&format!(": {description}"));
+                }
+                line
+            })
+            .collect();
 
-        if !hits.is_empty() {
-            let qdrant_config = self.config.qdrant_config.as_ref().unwrap();
-            let payload_source = &qdrant_config.payload_source;
-            info!(
-                "Extracting the payload ({}) of the vector search results...",
-                payload_source
-            );
-            let mut output = Vec::new();
-            for hit in hits {
-                let source = hit.payload.get(payload_source).unwrap().as_str().unwrap();
-                output.push(source.to_string());
-            }
+        let structured_content =
+            serde_json::to_value(ListIndexesResponse { indexes }).map_err(|e| {
+                let error_message = format!("Failed to serialize the indexes: {e}");
+                error!("{}", error_message);
+                McpError::new(ErrorCode::INTERNAL_ERROR, error_message, None)
+            })?;
 
-            info!("Vector search done! 🎉");
+        Ok(CallToolResult {
+            content: vec![Content::text(lines.join("\n"))],
+            structured_content: Some(structured_content),
+            is_error: Some(false),
+            meta: None,
+        })
+    }
 
-            debug!("vector search results:\n{:#?}", &output);
+    /// The key the searches of a client are rate limited by: its identity if it authenticated,
+    /// its IP address otherwise
+    fn client_key(parts: Option<&Parts>) -> String {
+        let Some(parts) = parts else {
+            // the single client of the stdio transport
+            return "stdio".to_string();
+        };
 
-            Ok(output)
+        if let Some(identity) = parts.extensions.get::<Identity>() {
+            identity.to_string()
+        } else if let Some(ConnectInfo(address)) = parts.extensions.get::<ConnectInfo<SocketAddr>>()
+        {
+            format!("ip:{}", address.ip())
         } else {
-            let error_message = "No vector search results found in Qdrant";
-            warn!("{}", error_message);
-            Ok(vec![])
+            "unknown".to_string()
         }
     }
 
-    async fn keyword_search(&self, query: impl AsRef<str>) -> Result<Vec<String>, McpError> {
-        info!("Starting keyword search ...");
-
-        // extract keywords from the query
-        info!("Extracting keywords from the query...");
-        let keywords = self.extract_keywords(query.as_ref()).await?;
-        debug!("Extracted keywords: {:#?}", keywords);
+    /// Search in the backends that serve the mode
+    async fn search_in_mode(
+        config: &AgenticSearchConfig,
+        index: &SearchIndex,
+        request: &SearchRequest,
+        mode: RetrievalMode,
+    ) -> Result<SearchResponse, McpError> {
+        let backends = Self::select_backends(index, mode);
+        let params = Self::resolve_params(config, request, mode, &backends)?;
+        info!(
+            "Searching the index `{}` in {} mode in {} backend(s) with {:?}",
+            index.name,
+            mode,
+            backends.len(),
+            params
+        );
 
-        // search in tidb
-        info!("Searching in TiDB...");
-        let hits = self.search_in_tidb(keywords).await?;
+        // the hypothetical document is drafted once, for all the variants and vector backends
+        let uses_vectors = mode != RetrievalMode::Keyword
+            && backends
+                .iter()
+                .any(|backend| backend.kind() != BackendKind::Keyword);
+        let ((queries, rewrite_warning), (hypothetical_document, hyde_warning)) = tokio::join!(
+            async {
+                match &config.rewrite {
+                    Some(rewrite) => rewrite::expand(rewrite, &request.query).await,
+                    None => (vec![request.query.clone()], None),
+                }
+            },
+            async {
+                match &config.hyde {
+                    Some(hyde) if uses_vectors => hyde::draft(hyde, &request.query).await,
+                    _ => (None, None),
+                }
+            },
+        );
+        let params = SearchParams {
+            hypothetical_document,
+            ..params
+        };
 
-        if !hits.is_empty() {
-            // format the search results
-            info!("Extracting the source of the keyword search results...");
-            let mut output = Vec::new();
-            for hit in hits {
-                output.push(hit.content);
+        let mut response = match (
+            backends.as_slice(),
+            queries.as_slice(),
+            config.rerank.as_ref(),
+            params.mmr_lambda,
+        ) {
+            ([backend], [query], None, None) => {
+                let hits = Self::search_backend(config, backend, query, &params).await?;
+                SearchResponse {
+                    results: Self::into_results(hits, &params),
+                    warnings: vec![],
+                }
             }
+            (_, _, rerank, mmr_lambda) => {
+                // the page is cut from the candidates once they are fused, reranked and
+                // diversified, and the reranking keeps the candidates of the diversification
+                let page_end = params.offset + params.limit;
+                let reranked_count = match mmr_lambda {
+                    Some(_) => page_end.saturating_mul(config.mmr.overfetch),
+                    None => page_end,
+                };
+                let candidate_count = rerank.map_or(reranked_count, |rerank| {
+                    rerank.candidate_count(page_end).max(reranked_count)
+                });
+                let (mut results, mut warnings) = match (backends.as_slice(), queries.as_slice()) {
+                    ([backend], [query]) => {
+                        let candidate_params = SearchParams {
+                            limit: candidate_count,
+                            offset: 0,
+                            ..params.clone()
+                        };
+                        let hits =
+                            Self::search_backend(config, backend, query, &candidate_params).await?;
+                        (Self::into_results(hits, &candidate_params), vec![])
+                    }
+                    _ => {
+                        Self::combined_search(
+                            config,
+                            &queries,
+                            mode,
+                            &backends,
+                            &params,
+                            candidate_count,
+                        )
+                        .await?
+                    }
+                };
 
-            info!("Keyword search done! 🎉");
+                if let Some(rerank) = rerank
+                    && let Err(e) = rerank::rerank(
+                        rerank,
+                        &request.query,
+                        &mut results,
+                        reranked_count as usize,
+                    )
+                    .await
+                {
+                    let warning = format!(
+                        "Reranking failed, the results are in retrieval order: {}",
+                        e.message
+                    );
+                    warn!("{}", warning);
+                    warnings.push(warning);
+                }
+                if let Some(mmr_lambda) = mmr_lambda {
+                    info!(
+                        "Diversifying {} candidate(s) with lambda {} ...",
+                        results.len(),
+                        mmr_lambda
+                    );
+                    results = mmr::diversify(results, mmr_lambda, page_end as usize);
+                }
 
-            debug!("keyword search results:\n{:#?}", &output);
+                SearchResponse {
+                    results: results
+                        .into_iter()
+                        .skip(params.offset as usize)
+                        .take(params.limit as usize)
+                        .collect(),
+                    warnings,
+                }
+            }
+        };
+        response
+            .warnings
+            .splice(0..0, rewrite_warning.into_iter().chain(hyde_warning));
 
-            Ok(output)
-        } else {
-            let error_message = "No keyword search results found in TiDB";
-            warn!("{}", error_message);
-            Ok(vec![])
-        }
+        Ok(response)
     }
 
-    async fn combined_search(&self, query: String) -> Result<Vec<String>, McpError> {
-        let vector_search_result = self.vector_search(query.as_str()).await?;
-        let keyword_search_result = self.keyword_search(query.as_str()).await?;
+    /// Convert the hits of a single-backend search into search results
+    fn into_results(hits: Vec<SearchHit>, params: &SearchParams) -> Vec<SearchResult> {
+        hits.into_iter()
+            .enumerate()
+            .map(|(index, hit)| hit.into_result(params.offset as usize + index + 1))
+            .collect()
+    }
 
-        info!("Combining vector and keyword search results ...");
+    /// Build the tool result: the source texts for humans, and the full response as structured content
+    fn into_call_tool_result(response: SearchResponse) -> Result<CallToolResult, McpError> {
+        let sources: Vec<&str> = response
+            .results
+            .iter()
+            .map(|result| result.source.as_str())
+            .collect();
+        let mut contents = vec![Content::text(sources.join("\n"))];
+        contents.extend(
+            response
+                .warnings
+                .iter()
+                .map(|warning| Content::text(format!("Warning: {warning}"))),
+        );
 
-        let output = if !vector_search_result.is_empty() && !keyword_search_result.is_empty() {
-            let mut output: HashSet<String> = HashSet::from_iter(vector_search_result);
-            output.extend(keyword_search_result);
+        let structured_content = serde_json::to_value(&response).map_err(|e| {
+            let error_message = format!("Failed to serialize the search response: {e}");
+            error!("{}", error_message);
+            McpError::new(ErrorCode::INTERNAL_ERROR, error_message, None)
+        })?;
 
-            Vec::from_iter(output)
-        } else if !vector_search_result.is_empty() {
-            vector_search_result
-        } else {
-            keyword_search_result
-        };
+        Ok(CallToolResult {
+            content: contents,
+            structured_content: Some(structured_content),
+            is_error: Some(false),
+            meta: None,
+        })
+    }
 
-        info!("Combined search done! 🎉");
+    /// Resolve the index a search call targets, the default index if it does not set one
+    fn resolve_target<'a>(
+        config: &'a AgenticSearchConfig,
+        target: Option<&str>,
+    ) -> Result<&'a SearchIndex, McpError> {
+        let names = || {
+            config
+                .indexes
+                .iter()
+                .map(|index| index.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
 
-        debug!("combined search results:\n{:#?}", &output);
+        let Some(target) = target.or(config.default_target.as_deref()) else {
+            let error_message = format!(
+                "`target` is required, as this server has no default index. Expected one of: {}",
+                names()
+            );
+            warn!("{}", error_message);
+            return Err(McpError::invalid_params(error_message, None));
+        };
+        config
+            .indexes
+            .iter()
+            .find(|index| index.name == target)
+            .ok_or_else(|| {
+                let error_message =
+                    format!("Unknown target `{target}`, expected one of: {}", names());
+                warn!("{}", error_message);
+                McpError::invalid_params(error_message, None)
+            })
+    }
 
-        Ok(output)
+    /// The retrieval mode that covers all the backends of the index, `None` if it has none
+    fn configured_mode(index: &SearchIndex) -> Option<RetrievalMode> {
+        let has_kind = |kind| {
+            index
+                .backends
+                .iter()
+                .any(|backend| backend.kind() == kind || backend.kind() == BackendKind::Hybrid)
+        };
+        match (
+            has_kind(BackendKind::Vector),
+            has_kind(BackendKind::Keyword),
+        ) {
+            (true, true) => Some(RetrievalMode::Hybrid),
+            (true, false) => Some(RetrievalMode::Vector),
+            (false, true) => Some(RetrievalMode::Keyword),
+            (false, false) => None,
+        }
     }
 
-    async fn compute_embedding(&self, query: impl AsRef<str>) -> Result<Vec<f64>, McpError> {
-        match &self.config.embedding_service {
-            Some(config) => {
-                let embedding_service_url =
-                    format!("{}/embeddings", config.url.trim_end_matches('/'));
-
-                // create a embedding request
-                let embedding_request = EmbeddingRequest {
-                    model: config.model.clone(),
-                    input: InputText::String(query.as_ref().to_string()),
-                    encoding_format: None,
-                    user: None,
-                };
+    /// Resolve the retrieval mode of a search call against the kinds of the backends of the index
+    ///
+    /// Payload filters only apply to vector search, so a filtered call defaults to vector mode
+    /// and is rejected in any other mode rather than returning unfiltered keyword results.
+    fn resolve_mode(
+        index: &SearchIndex,
+        requested: Option<RetrievalMode>,
+        filtered: bool,
+    ) -> Result<RetrievalMode, McpError> {
+        let Some(configured) = Self::configured_mode(index) else {
+            let error_message =
+                format!("No search backend configured in the index `{}`", index.name);
+            error!("{}", error_message);
+            return Err(McpError::new(
+                ErrorCode::INTERNAL_ERROR,
+                error_message,
+                None,
+            ));
+        };
 
-                let response = match &config.api_key {
-                    Some(api_key) => {
-                        let auth_info = if api_key.starts_with("Bearer ") {
-                            api_key.clone()
-                        } else {
-                            format!("Bearer {api_key}")
-                        };
+        let mode = match requested {
+            None if filtered && configured == RetrievalMode::Hybrid => RetrievalMode::Vector,
+            None => configured,
+            Some(mode) if configured == RetrievalMode::Hybrid || mode == configured => mode,
+            Some(mode) => {
+                let error_message = format!(
+                    "Search mode `{mode}` is not available. The index `{}` is configured for `{configured}` search",
+                    index.name
+                );
+                warn!("{}", error_message);
+                return Err(McpError::invalid_params(error_message, None));
+            }
+        };
 
-                        reqwest::Client::new()
-                            .post(&embedding_service_url)
-                            .header(CONTENT_TYPE, "application/json")
-                            .header(AUTHORIZATION, auth_info)
-                            .json(&embedding_request)
-                            .send()
-                            .await
-                            .map_err(|e| {
-                                let err_msg = format!("Failed to send the embedding request: {e}");
-                                error!("{}", err_msg);
-                                McpError::new(ErrorCode::INTERNAL_ERROR, err_msg, None)
-                            })?
-                    }
-                    None => reqwest::Client::new()
-                        .post(&embedding_service_url)
-                        .header(CONTENT_TYPE, "application/json")
-                        .json(&embedding_request)
-                        .send()
-                        .await
-                        .map_err(|e| {
-                            let err_msg = format!("Failed to send the embedding request: {e}");
-                            error!("{}", err_msg);
-                            McpError::new(ErrorCode::INTERNAL_ERROR, err_msg, None)
-                        })?,
-                };
+        if filtered && mode != RetrievalMode::Vector {
+            let error_message =
+                format!("`filter` only applies to vector search, but the search mode is `{mode}`");
+            warn!("{}", error_message);
+            return Err(McpError::invalid_params(error_message, None));
+        }
 
-                let bytes = response.bytes().await.map_err(|e| {
-                    let err_msg = format!("Failed to parse embeddings response: {e}");
-                    error!("{}", err_msg);
-                    McpError::new(ErrorCode::INTERNAL_ERROR, err_msg, None)
-                })?;
+        Ok(mode)
+    }
 
-                // parse the response
-                let embedding_response = serde_json::from_slice::<EmbeddingsResponse>(&bytes)
-                    .map_err(|e| {
-                        let err_msg = format!("Failed to parse embeddings response: {e}");
-                        error!("{}", err_msg);
-                        McpError::new(ErrorCode::INTERNAL_ERROR, err_msg, None)
-                    })?;
-
-                let embedding = embedding_response.data.first().ok_or_else(|| {
-                    let err_msg = "No embeddings returned";
-                    error!("{}", err_msg);
-                    McpError::new(ErrorCode::INTERNAL_ERROR, err_msg, None)
-                })?;
+    /// Select the backends of the index that serve the given mode
+    fn select_backends(index: &SearchIndex, mode: RetrievalMode) -> Vec<Arc<dyn SearchBackend>> {
+        index
+            .backends
+            .iter()
+            .filter(|backend| match mode {
+                RetrievalMode::Vector => backend.kind() != BackendKind::Keyword,
+                RetrievalMode::Keyword => backend.kind() != BackendKind::Vector,
+                RetrievalMode::Hybrid => true,
+            })
+            .cloned()
+            .collect()
+    }
 
-                Ok(embedding.embedding.to_vec())
-            }
-            None => {
-                let error_message = "Embedding service URL is not configured";
-                error!("{}", error_message);
-                Err(McpError::new(
-                    ErrorCode::INTERNAL_ERROR,
-                    error_message,
-                    None,
-                ))
-            }
+    /// Validate the per-call search parameters against the server-side maximums and the
+    /// selected backends, falling back to the configured defaults for the ones that are not set
+    fn resolve_params(
+        config: &AgenticSearchConfig,
+        request: &SearchRequest,
+        mode: RetrievalMode,
+        backends: &[Arc<dyn SearchBackend>],
+    ) -> Result<SearchParams, McpError> {
+        let limit = request.limit.unwrap_or(config.limit);
+        if limit == 0 || limit > config.max_limit {
+            let error_message = format!(
+                "`limit` must be between 1 and {}, got {limit}",
+                config.max_limit
+            );
+            warn!("{}", error_message);
+            return Err(McpError::invalid_params(error_message, None));
         }
-    }
 
-    async fn search_in_qdrant(
-        &self,
-        vector: impl AsRef<[f64]>,
-    ) -> Result<Vec<QdrantSearchHit>, McpError> {
-        match &self.config.qdrant_config {
-            Some(qdrant_config) => {
-                let base_url = qdrant_config.base_url.trim_end_matches('/');
-                let url = format!(
-                    "{}/collections/{}/points/search",
-                    base_url, qdrant_config.collection
-                );
+        let offset = request.offset.unwrap_or(0);
+        if offset > config.max_offset {
+            let error_message = format!(
+                "`offset` must not be greater than {}, got {offset}",
+                config.max_offset
+            );
+            warn!("{}", error_message);
+            return Err(McpError::invalid_params(error_message, None));
+        }
 
-                // build params
-                let params = json!({
-                    "vector": vector.as_ref().to_vec(),
-                    "limit": self.config.limit,
-                    "with_payload": true,
-                    "with_vector": true,
-                    "score_threshold": self.config.score_threshold,
-                });
+        let mmr_lambda = request.mmr_lambda.or(config.mmr.lambda);
+        if let Some(mmr_lambda) = mmr_lambda
+            && !(0.0..=1.0).contains(&mmr_lambda)
+        {
+            let error_message = format!("`mmr_lambda` must be between 0 and 1, got {mmr_lambda}");
+            warn!("{}", error_message);
+            return Err(McpError::invalid_params(error_message, None));
+        }
 
-                let response = match &qdrant_config.api_key {
-                    Some(api_key) => {
-                        let auth_info = if api_key.starts_with("Bearer ") {
-                            api_key.clone()
-                        } else {
-                            format!("Bearer {api_key}")
-                        };
+        let score_threshold = request.score_threshold;
+        if let Some(score_threshold) = score_threshold
+            && !score_threshold.is_finite()
+        {
+            let error_message =
+                format!("`score_threshold` must be a finite number, got {score_threshold}");
+            warn!("{}", error_message);
+            return Err(McpError::invalid_params(error_message, None));
+        }
 
-                        reqwest::Client::new()
-                            .post(&url)
-                            .header("api-key", auth_info)
-                            .header("Content-Type", "application/json")
-                            .json(&params)
-                            .send()
-                            .await
-                            .map_err(|e| {
-                                let err_msg = format!("Failed to search points: {e}");
-                                error!("{}", err_msg);
-                                McpError::new(ErrorCode::INTERNAL_ERROR, err_msg, None)
-                            })?
-                    }
-                    None => reqwest::Client::new()
-                        .post(&url)
-                        .header("Content-Type", "application/json")
-                        .json(&params)
-                        .send()
-                        .await
-                        .map_err(|e| {
-                            let err_msg = format!("Failed to search points: {e}");
-                            error!("{}", err_msg);
-                            McpError::new(ErrorCode::INTERNAL_ERROR, err_msg, None)
-                        })?,
-                };
+        if let Some(filter) = &request.filter {
+            for backend in backends {
+                backend.validate_filter(filter).map_err(|error_message| {
+                    warn!("Invalid filter: {}", error_message);
+                    McpError::invalid_params(error_message, None)
+                })?;
+            }
+        }
 
-                let status = response.status();
-                if !status.is_success() {
-                    let error_message =
-                        format!("Failed to send search request to Qdrant server. Status: {status}");
-                    error!("{}", error_message);
-                    return Err(McpError::new(
-                        ErrorCode::INTERNAL_ERROR,
-                        error_message,
-                        None,
-                    ));
-                }
+        Ok(SearchParams {
+            mode,
+            limit,
+            offset,
+            score_threshold,
+            filter: request.filter.clone(),
+            mmr_lambda,
+            hypothetical_document: None,
+        })
+    }
 
-                match response.json::<Value>().await {
-                    Ok(json) => match json.get("result") {
-                        Some(result) => {
-                            let hits = result
-                                .as_array()
-                                .unwrap()
-                                .iter()
-                                .map(|v| QdrantSearchHit {
-                                    score: v.get("score").unwrap().as_f64().unwrap(),
-                                    payload: v
-                                        .get("payload")
-                                        .unwrap()
-                                        .as_object()
-                                        .unwrap()
-                                        .to_owned()
-                                        .into_iter()
-                                        .map(|(k, v)| (k.to_string(), v.clone()))
-                                        .collect(),
-                                    vector: v
-                                        .get("vector")
-                                        .unwrap()
-                                        .as_array()
-                                        .unwrap()
-                                        .to_owned()
-                                        .iter()
-                                        .map(|v| v.as_f64().unwrap())
-                                        .collect::<Vec<f64>>(),
-                                })
-                                .collect();
-
-                            Ok(hits)
-                        }
-                        None => {
-                            debug!(
-                                "Qdrant search response:\n{}",
-                                serde_json::to_string_pretty(&json).unwrap()
-                            );
-
-                            match json.get("status") {
-                                Some(status) => {
-                                    let error_message = format!(
-                                        "Failed to search points. {}",
-                                        status.get("error").unwrap().as_str().unwrap()
-                                    );
-                                    error!("{}", error_message);
-                                    Err(McpError::new(
-                                        ErrorCode::INTERNAL_ERROR,
-                                        error_message,
-                                        None,
-                                    ))
-                                }
-                                None => {
-                                    let error_message = "Failed to search points. ";
-                                    error!("{}", error_message);
-                                    Err(McpError::new(
-                                        ErrorCode::INTERNAL_ERROR,
-                                        error_message,
-                                        None,
-                                    ))
-                                }
-                            }
-                        }
-                    },
-                    Err(e) => {
-                        let error_message = format!("Failed to search points: {e}");
-                        error!("{}", error_message);
-                        Err(McpError::new(
-                            ErrorCode::INTERNAL_ERROR,
-                            error_message,
-                            None,
-                        ))
-                    }
-                }
-            }
-            None => {
-                let error_message = "Qdrant config is not set";
+    /// Search a single backend, failing if it exceeds the backend timeout
+    async fn search_backend(
+        config: &AgenticSearchConfig,
+        backend: &Arc<dyn SearchBackend>,
+        query: &str,
+        params: &SearchParams,
+    ) -> Result<Vec<SearchHit>, McpError> {
+        let timeout = config.backend_timeout;
+        match tokio::time::timeout(timeout, backend.search(query, params)).await {
+            Ok(result) => result,
+            Err(_) => {
+                metrics::record_backend_timeout(params.mode, backend.name());
+                let error_message =
+                    format!("Search in `{}` timed out after {timeout:?}", backend.name());
                 error!("{}", error_message);
                 Err(McpError::new(
                     ErrorCode::INTERNAL_ERROR,
@@ -420,317 +559,104 @@ impl AgenticSearchServer {
         }
     }
 
-    /// Extract keywords from the query using the embedding service
-    ///
-    /// # Arguments
+    /// Run the search of each query in all the given backends concurrently and fuse their
+    /// results
     ///
-    /// * `query` - The query to extract keywords from
+    /// If some of the searches fail or time out, the results of the others are returned
+    /// together with a warning describing each failure. An error is returned only if all fail.
     ///
-    /// # Returns
-    ///
-    /// A string containing the extracted keywords separated by spaces
-    async fn extract_keywords(&self, query: impl AsRef<str>) -> Result<String, McpError> {
-        let config = self.config.chat_service.as_ref().unwrap();
-
-        let text = query.as_ref();
-        let prompt = std::env::var("PROMPT_KEYWORD_EXTRACTOR")
-            .unwrap_or(DEFAULT_PROMPT_KEYWORD_EXTRACTOR.to_string());
-        let user_prompt = format!("{prompt}\n\n### Input Query\n{text:#?}");
-
-        let user_message = ChatCompletionRequestMessage::new_user_message(
-            ChatCompletionUserMessageContent::Text(user_prompt),
-            None,
-        );
-
-        // create a request
-        let request = if let Some(model) = &config.model {
-            ChatCompletionRequestBuilder::new(&[user_message])
-                .with_model(model)
-                .with_max_completion_tokens(3200)
-                .build()
-        } else {
-            ChatCompletionRequestBuilder::new(&[user_message]).build()
+    /// Each backend is asked for `candidate_count` hits, and the best `candidate_count` fused
+    /// results are returned, for the caller to paginate. The vector and keyword scores have
+    /// different scales, so in hybrid mode a per-call score threshold only applies to the vector
+    /// backends.
+    async fn combined_search(
+        config: &AgenticSearchConfig,
+        queries: &[String],
+        mode: RetrievalMode,
+        backends: &[Arc<dyn SearchBackend>],
+        params: &SearchParams,
+        candidate_count: u64,
+    ) -> Result<(Vec<SearchResult>, Vec<String>), McpError> {
+        let backend_params = SearchParams {
+            limit: candidate_count,
+            offset: 0,
+            ..params.clone()
         };
-
-        let chat_service_url = format!("{}/chat/completions", config.url.trim_end_matches('/'));
-        debug!(
-            "Forward the chat request to {} for extracting keywords",
-            chat_service_url,
-        );
-        let response = match &config.api_key {
-            Some(api_key) => {
-                let auth_info = if api_key.starts_with("Bearer ") {
-                    api_key.clone()
-                } else {
-                    format!("Bearer {api_key}")
-                };
-
-                reqwest::Client::new()
-                    .post(&chat_service_url)
-                    .header(reqwest::header::CONTENT_TYPE, "application/json")
-                    .header(reqwest::header::AUTHORIZATION, auth_info)
-                    .json(&request)
-                    .send()
-                    .await
-                    .map_err(|e| {
-                        let err_msg = format!("Failed to send the chat request: {e}");
-                        error!("{}", err_msg);
-                        McpError::new(ErrorCode::INTERNAL_ERROR, err_msg, None)
-                    })?
-            }
-            None => reqwest::Client::new()
-                .post(&chat_service_url)
-                .header(reqwest::header::CONTENT_TYPE, "application/json")
-                .json(&request)
-                .send()
-                .await
-                .map_err(|e| {
-                    let err_msg = format!("Failed to send the chat request: {e}");
-                    error!("{}", err_msg);
-                    McpError::new(ErrorCode::INTERNAL_ERROR, err_msg, None)
-                })?,
+        let keyword_params = SearchParams {
+            score_threshold: None,
+            ..backend_params.clone()
         };
 
-        let chat_completion_object =
-            response.json::<ChatCompletionObject>().await.map_err(|e| {
-                let err_msg = format!("Failed to parse the chat response: {e}");
-                error!("{}", err_msg);
-                McpError::new(ErrorCode::INTERNAL_ERROR, err_msg, None)
-            })?;
-
-        let content = chat_completion_object.choices[0]
-            .message
-            .content
-            .as_ref()
-            .unwrap();
-
-        Ok(content.to_string())
-    }
-
-    /// Extract rows from TiDB query results using generic natural language format
-    ///
-    /// This method converts MySQL rows to human-readable strings suitable for LLM processing
-    fn extract_rows_generic_natural_language(rows: Vec<mysql::Row>) -> Vec<String> {
-        let mut results = Vec::new();
-
-        for (row_index, row) in rows.iter().enumerate() {
-            let mut field_parts = Vec::new();
-            let columns = row.columns_ref();
-
-            // 文档头部
-            field_parts.push(format!("=== Document {} ===", row_index + 1));
-
-            for (index, column) in columns.iter().enumerate() {
-                let column_name = column.name_str();
-
-                if let Some(value) = row.get::<mysql::Value, _>(index) {
-                    let text_value = match value {
-                        mysql::Value::Bytes(bytes) => String::from_utf8_lossy(&bytes).to_string(),
-                        mysql::Value::NULL => continue,
-                        mysql::Value::Int(i) => i.to_string(),
-                        mysql::Value::UInt(u) => u.to_string(),
-                        mysql::Value::Float(f) => f.to_string(),
-                        mysql::Value::Double(d) => d.to_string(),
-                        mysql::Value::Date(year, month, day, hour, minute, second, microsecond) => {
-                            format!(
-                                "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:06}",
-                                year, month, day, hour, minute, second, microsecond
-                            )
-                        }
-                        _ => format!("{:?}", value),
-                    };
-
-                    if !text_value.trim().is_empty() {
-                        // 将字段名转换为更友好的格式
-                        let friendly_name = column_name
-                            .replace("_", " ")
-                            .split_whitespace()
-                            .map(|word| {
-                                let mut chars = word.chars();
-                                match chars.next() {
-                                    None => String::new(),
-                                    Some(first) => {
-                                        first.to_uppercase().collect::<String>() + chars.as_str()
-                                    }
-                                }
-                            })
-                            .collect::<Vec<_>>()
-                            .join(" ");
-
-                        // 根据内容长度决定格式
-                        let formatted_field = if text_value.len() > 100 {
-                            // 长文本用换行格式
-                            format!("{}:\n{}", friendly_name, text_value)
-                        } else {
-                            // 短文本用同行格式
-                            format!("{}: {}", friendly_name, text_value)
-                        };
-
-                        field_parts.push(formatted_field);
-                    }
+        let timeout = config.backend_timeout;
+        let targets: Vec<(&String, &Arc<dyn SearchBackend>)> = queries
+            .iter()
+            .flat_map(|query| backends.iter().map(move |backend| (query, backend)))
+            .collect();
+        let searches = targets.iter().map(|(query, backend)| {
+            let params = if mode == RetrievalMode::Hybrid && backend.kind() == BackendKind::Keyword
+            {
+                &keyword_params
+            } else {
+                &backend_params
+            };
+            tokio::time::timeout(timeout, backend.search(query, params))
+        });
+        let search_results = futures::future::join_all(searches).await;
+
+        let mut warnings = Vec::new();
+        let mut result_lists = Vec::new();
+        for ((query, backend), search_result) in targets.iter().zip(search_results) {
+            // the searches of the variants of a rewritten query are told apart by their query
+            let search = match queries {
+                [_] => format!("`{}`", backend.name()),
+                _ => format!("`{}` for {:?}", backend.name(), query),
+            };
+            match search_result {
+                Ok(Ok(hits)) => result_lists.push(hits),
+                Ok(Err(e)) => {
+                    let warning = format!("Search in {search} failed: {}", e.message);
+                    warn!("{}", warning);
+                    warnings.push(warning);
+                }
+                Err(_) => {
+                    metrics::record_backend_timeout(mode, backend.name());
+                    let warning = format!("Search in {search} timed out after {timeout:?}");
+                    warn!("{}", warning);
+                    warnings.push(warning);
                 }
             }
-
-            let combined_string = field_parts.join("\n\n");
-            results.push(combined_string);
         }
 
-        results
-    }
-
-    /// Search in TiDB using the keywords
-    ///
-    /// # Arguments
-    ///
-    /// * `keywords` - The keywords to search for. The keywords should be separated by spaces.
-    ///
-    /// # Returns
-    ///
-    /// A string containing the search results
-    async fn search_in_tidb(
-        &self,
-        keywords: impl AsRef<str>,
-    ) -> Result<Vec<TidbSearchHit>, McpError> {
-        match &self.config.tidb_config {
-            Some(tidb_config) => {
-                // get connection
-                debug!("Getting connection to TiDB Cloud...");
-                let mut conn = tidb_config.pool.get_conn().map_err(|e| {
-                    let error_message = format!("Failed to get connection: {e}");
-
-                    error!(error_message);
-
-                    McpError::new(ErrorCode::INTERNAL_ERROR, error_message, None)
-                })?;
-
-                // test connection
-                debug!("Testing connection...");
-                let version: String = match conn.query_first("SELECT VERSION()").map_err(|e| {
-                    let error_message = format!("Failed to query version: {e}");
-
-                    error!(error_message);
-
-                    McpError::new(ErrorCode::INTERNAL_ERROR, error_message, None)
-                })? {
-                    Some(version) => version,
-                    None => {
-                        let error_message = "Failed to query version";
-
-                        error!(error_message);
-
-                        return Err(McpError::new(
-                            ErrorCode::INTERNAL_ERROR,
-                            error_message,
-                            None,
-                        ));
-                    }
-                };
-                debug!("Connected to TiDB Cloud! Version: {}", version);
-
-                // check if table exists
-                debug!("Checking if table exists...");
-                let check_table_sql = format!(
-                    "SELECT COUNT(*) FROM information_schema.tables
-                WHERE table_schema = '{}' AND table_name = '{}'",
-                    tidb_config.database, tidb_config.table_name
-                );
-                let table_exists: i32 = conn
-                    .query_first(&check_table_sql)
-                    .map_err(|e| {
-                        let error_message = format!("Failed to check table: {e}");
-
-                        error!(error_message);
-
-                        McpError::new(ErrorCode::INTERNAL_ERROR, error_message, None)
-                    })?
-                    .unwrap_or(0);
-
-                if table_exists == 0 {
-                    let error_message = format!(
-                        "Not found table `{}` in database `{}`",
-                        tidb_config.table_name, tidb_config.database
-                    );
-
-                    error!(error_message);
-
-                    return Err(McpError::new(
-                        ErrorCode::INTERNAL_ERROR,
-                        error_message,
-                        None,
-                    ));
-                }
-
-                // execute full-text search
-                let query = keywords.as_ref().replace("'", "''"); // ! This is a workaround for the issue that the query contains single quotes.
-
-                debug!(
-                    "\nExecuting full-text search in table {} for '{}'...",
-                    tidb_config.table_name, query
-                );
-                debug!(
-                    "Search field: {}, return fields: {:?}",
-                    tidb_config.search_field, tidb_config.return_field
-                );
-
-                let select_clause = if tidb_config.return_field.contains(&"*".to_string()) {
-                    "*".to_string()
-                } else {
-                    tidb_config
-                        .return_field
-                        .iter()
-                        .map(|field| format!("`{}`.`{}`", tidb_config.table_name, field))
-                        .collect::<Vec<_>>()
-                        .join(", ")
-                };
+        if result_lists.is_empty() {
+            let error_message = format!("All search backends failed: {}", warnings.join("; "));
+            error!("{}", error_message);
+            return Err(McpError::new(
+                ErrorCode::INTERNAL_ERROR,
+                error_message,
+                None,
+            ));
+        }
 
-                let search_sql = format!(
-                    r"SELECT {select_clause}
-                    FROM `{table}`
-                    WHERE fts_match_word('{query}', `{table}`.`{search_field}`)
-                    ORDER BY fts_match_word('{query}', `{table}`.`{search_field}`) DESC
-                    LIMIT {limit}",
-                    select_clause = select_clause,
-                    table = tidb_config.table_name,
-                    query = query,
-                    search_field = tidb_config.search_field,
-                    limit = self.config.limit
-                );
+        info!(
+            "Fusing {} result list(s) with {:?} ...",
+            result_lists.len(),
+            config.fusion.method
+        );
 
-                // execute the query and get the Row results
-                let rows: Vec<mysql::Row> = conn.query(&search_sql).map_err(|e| {
-                    let error_message = format!("Failed to execute search: {e}");
-                    error!(error_message);
-                    McpError::new(ErrorCode::INTERNAL_ERROR, error_message, None)
-                })?;
+        let fused = fusion::fuse(&config.fusion, result_lists);
 
-                info!("Query returned {} rows", rows.len());
+        let output: Vec<SearchResult> = fused
+            .into_iter()
+            .take(candidate_count as usize)
+            .enumerate()
+            .map(|(index, hit)| hit.into_result(index + 1))
+            .collect();
 
-                // convert the Row results to formatted strings
-                let formatted_results = Self::extract_rows_generic_natural_language(rows);
+        info!("Combined search done! 🎉");
 
-                // convert formatted strings to TidbSearchHit instances
-                let mut tidb_hits = Vec::new();
-                for (index, formatted_text) in formatted_results.into_iter().enumerate() {
-                    let hit = TidbSearchHit {
-                        id: index as i32,
-                        title: format!("Search Result {}", index + 1),
-                        content: formatted_text,
-                    };
-                    tidb_hits.push(hit);
-                }
+        debug!("combined search results:\n{:#?}", &output);
 
-                Ok(tidb_hits)
-            }
-            None => {
-                let error_message = "TiDB config is not set";
-                error!("{}", error_message);
-                Err(McpError::new(
-                    ErrorCode::INTERNAL_ERROR,
-                    error_message,
-                    None,
-                ))
-            }
-        }
+        Ok((output, warnings))
     }
 }
 
@@ -799,3 +725,236 @@ impl ServerHandler for AgenticSearchServer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        backend::{BackendDescription, format_document},
+        hyde::HydeConfig,
+        rewrite::RewriteConfig,
+        test_support::{self, StubServices},
+    };
+    use async_trait::async_trait;
+    use serde_json::{Map, json};
+    use std::sync::{Mutex, atomic::Ordering};
+
+    /// A vector backend recording the queries it searches and the hypothetical documents they
+    /// come with
+    #[derive(Debug, Default)]
+    struct RecordingBackend {
+        name: &'static str,
+        searches: Mutex<Vec<(String, Option<String>)>>,
+    }
+
+    #[async_trait]
+    impl SearchBackend for RecordingBackend {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn kind(&self) -> BackendKind {
+            BackendKind::Vector
+        }
+
+        fn describe(&self) -> BackendDescription {
+            BackendDescription {
+                name: self.name.to_string(),
+                kind: BackendKind::Vector,
+                description: "recording".to_string(),
+            }
+        }
+
+        async fn search(
+            &self,
+            query: &str,
+            params: &SearchParams,
+        ) -> Result<Vec<SearchHit>, McpError> {
+            let document = params
+                .hypothetical_document
+                .as_ref()
+                .map(|document| document.text.clone());
+            self.searches
+                .lock()
+                .unwrap()
+                .push((query.to_string(), document));
+            Ok(vec![SearchHit {
+                backend: self.name.to_string(),
+                rank: 1,
+                score: Some(0.5),
+                id: None,
+                title: None,
+                source: query.to_string(),
+                metadata: Map::new(),
+                vector: None,
+            }])
+        }
+
+        async fn health_check(&self) -> Result<(), McpError> {
+            Ok(())
+        }
+    }
+
+    /// A keyword backend returning the rows of a table without ids, in the order listed for
+    /// each query
+    #[derive(Debug)]
+    struct TableBackend {
+        rows: fn(&str) -> Vec<&'static str>,
+    }
+
+    #[async_trait]
+    impl SearchBackend for TableBackend {
+        fn name(&self) -> &str {
+            "tidb"
+        }
+
+        fn kind(&self) -> BackendKind {
+            BackendKind::Keyword
+        }
+
+        fn describe(&self) -> BackendDescription {
+            BackendDescription {
+                name: "tidb".to_string(),
+                kind: BackendKind::Keyword,
+                description: "table".to_string(),
+            }
+        }
+
+        async fn search(
+            &self,
+            query: &str,
+            _params: &SearchParams,
+        ) -> Result<Vec<SearchHit>, McpError> {
+            Ok((self.rows)(query)
+                .into_iter()
+                .enumerate()
+                .map(|(index, row)| SearchHit {
+                    backend: "tidb".to_string(),
+                    rank: index + 1,
+                    score: Some(1.0),
+                    id: None,
+                    title: None,
+                    source: format_document(index + 1, [("content".to_string(), row.to_string())]),
+                    metadata: Map::new(),
+                    vector: None,
+                })
+                .collect())
+        }
+
+        async fn health_check(&self) -> Result<(), McpError> {
+            Ok(())
+        }
+    }
+
+    fn config(
+        backends: Vec<Arc<dyn SearchBackend>>,
+        services: &StubServices,
+        variants: u64,
+    ) -> AgenticSearchConfig {
+        AgenticSearchConfig {
+            rewrite: (variants > 0).then(|| RewriteConfig {
+                service: services.config(),
+                prompt: None,
+                variants,
+                include_original: true,
+            }),
+            hyde: Some(HydeConfig {
+                service: services.config(),
+                prompt: None,
+                include_query: false,
+            }),
+            ..test_support::search_config(backends)
+        }
+    }
+
+    async fn search(config: &AgenticSearchConfig, mode: RetrievalMode) -> SearchResponse {
+        let request = serde_json::from_value(json!({ "query": "how does tokio work" })).unwrap();
+        AgenticSearchServer::search_in_mode(config, &config.indexes[0], &request, mode)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn the_hypothetical_document_is_drafted_once_per_call() {
+        let services =
+            StubServices::spawn(|_| vec![1.0], |_| "tokio scheduler\nasync runtime".into()).await;
+        let backends = [
+            Arc::new(RecordingBackend {
+                name: "a",
+                ..Default::default()
+            }),
+            Arc::new(RecordingBackend {
+                name: "b",
+                ..Default::default()
+            }),
+        ];
+        let config = config(
+            backends
+                .iter()
+                .map(|backend| backend.clone() as Arc<dyn SearchBackend>)
+                .collect(),
+            &services,
+            2,
+        );
+
+        let response = search(&config, RetrievalMode::Vector).await;
+        assert!(response.warnings.is_empty());
+        // one completion rewrites the query, and one drafts the document
+        assert_eq!(services.chat_calls.load(Ordering::SeqCst), 2);
+        for backend in &backends {
+            let searches = backend.searches.lock().unwrap();
+            assert_eq!(searches.len(), 3);
+            assert!(searches.iter().all(|(_, document)| {
+                document.as_deref() == Some("tokio scheduler\nasync runtime")
+            }));
+        }
+    }
+
+    #[tokio::test]
+    async fn an_empty_hypothetical_document_is_reported() {
+        let services =
+            StubServices::spawn(|_| vec![1.0], |_| "<think>no idea</think>".into()).await;
+        let backend = Arc::new(RecordingBackend {
+            name: "a",
+            ..Default::default()
+        });
+        let config = config(vec![backend.clone()], &services, 0);
+
+        let response = search(&config, RetrievalMode::Vector).await;
+        assert_eq!(response.results.len(), 1);
+        assert_eq!(response.warnings.len(), 1);
+        assert!(response.warnings[0].contains("empty"));
+        assert_eq!(
+            backend.searches.lock().unwrap()[0],
+            ("how does tokio work".to_string(), None)
+        );
+    }
+
+    #[tokio::test]
+    async fn a_row_returned_for_several_variants_is_listed_once() {
+        let services =
+            StubServices::spawn(|_| vec![1.0], |_| "tokio scheduler\nasync runtime".into()).await;
+        let backend = TableBackend {
+            rows: |query| match query {
+                "tokio scheduler" => vec!["tasks", "tokio"],
+                _ => vec!["tokio"],
+            },
+        };
+        let config = config(vec![Arc::new(backend)], &services, 2);
+
+        let response = search(&config, RetrievalMode::Keyword).await;
+        let sources: Vec<&str> = response
+            .results
+            .iter()
+            .map(|result| result.source.as_str())
+            .collect();
+        assert_eq!(
+            sources,
+            [
+                "=== Document 1 ===\n\nContent: tokio",
+                "=== Document 1 ===\n\nContent: tasks"
+            ]
+        );
+        assert_eq!(response.results[0].matches.len(), 3);
+    }
+}
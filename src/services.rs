@@ -0,0 +1,521 @@
+//! Clients of the OpenAI-compatible embedding and chat services used by the search backends, of
+//! the chat service that rewrites the queries or drafts hypothetical documents for them, and of
+//! the rerank and chat services that reorder the results.
+
+use crate::{ServiceConfig, telemetry};
+use endpoints::{
+    chat::{
+        ChatCompletionObject, ChatCompletionRequestBuilder, ChatCompletionRequestMessage,
+        ChatCompletionUserMessageContent,
+    },
+    embeddings::{EmbeddingRequest, EmbeddingsResponse, InputText},
+};
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+use rmcp::{ErrorData as McpError, model::ErrorCode};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, instrument};
+
+const DEFAULT_PROMPT_KEYWORD_EXTRACTOR: &str = r#"
+You are a multilingual keyword extractor. Your task is to extract the most relevant and concise keywords or key phrases from the given user query.
+
+Follow these requirements strictly:
+- Detect the language of the query automatically.
+- Return 3 to 7 keywords or keyphrases that best represent the query's core intent.
+- Keep the extracted keywords in the **original language** (do not translate).
+- Include **multi-word expressions** if they convey meaningful concepts.
+- **Avoid all types of stop words, question words, filler words, or overly generic terms**, such as:
+  - English: what, how, why, is, the, of, and, etc.
+  - Chinese: 什么、怎么、如何、是、的、了、吗、啊 等。
+- Do **not** include punctuation or meaningless words.
+- Only return the final keywords, separated by a **single space**.
+
+Examples:
+- Input: "What is the impact of artificial intelligence on education?"
+  Output: artificial intelligence education impact
+- Input: "什么是人工智能对教育的影响？"
+  Output: 人工智能 教育 影响
+"#;
+
+const DEFAULT_PROMPT_RERANKER: &str = r#"
+You are a search relevance ranker. Your task is to order the numbered passages below by how relevant they are to the search query.
+
+Follow these requirements strictly:
+- Judge how well each passage answers the query, not its length or style.
+- Rank the passages in any language against the query, whatever its language.
+- Include **every** passage number **exactly once**, the most relevant first.
+- Only return the passage numbers, in brackets and separated by " > ", without any explanation.
+
+Example:
+- Input: a query and the passages [1], [2] and [3]
+  Output: [2] > [3] > [1]
+"#;
+
+const DEFAULT_PROMPT_QUERY_REWRITER: &str = r#"
+You are a multilingual search query rewriter. Your task is to rewrite the given user query into several search queries that together retrieve the documents answering it.
+
+Follow these requirements strictly:
+- Detect the language of the query automatically, and write the queries in the **same language**.
+- Make each query **self-contained and specific**: drop the conversational phrasing, and spell out what pronouns and vague words refer to.
+- If the query asks several things, write a sub-query for each of them. Otherwise, rephrase it with different wording and synonyms.
+- Return exactly the requested number of queries, different from each other and from the original query.
+- Only return the queries, **one per line**, without numbering, quotes or explanation.
+
+Examples:
+- Input: "why does my rust code not compile when i borrow twice", 2 queries
+  Output:
+  Rust borrow checker error multiple mutable borrows
+  Rust ownership rules mutable and immutable references
+"#;
+
+const DEFAULT_PROMPT_HYPOTHETICAL_DOCUMENT: &str = r#"
+You are a multilingual technical writer. Your task is to write a short passage that answers the given user query, as it would appear in the documents being searched.
+
+Follow these requirements strictly:
+- Detect the language of the query automatically, and write the passage in the **same language**.
+- Write a single paragraph of 3 to 6 sentences in a factual, documentation style, using the terms an expert would use.
+- If you do not know the answer, write a plausible one: the passage is only used to find similar documents.
+- Only return the passage, without a title, preamble or explanation.
+"#;
+
+/// Compute the embedding of the query using the embedding service
+#[instrument(skip_all, fields(model = config.model.as_deref()))]
+pub async fn compute_embedding(
+    config: &ServiceConfig,
+    query: impl AsRef<str>,
+) -> Result<Vec<f64>, McpError> {
+    let embedding_service_url = format!("{}/embeddings", config.url.trim_end_matches('/'));
+
+    // create a embedding request
+    let embedding_request = EmbeddingRequest {
+        model: config.model.clone(),
+        input: InputText::String(query.as_ref().to_string()),
+        encoding_format: None,
+        user: None,
+    };
+
+    let response = match &config.api_key {
+        Some(api_key) => {
+            let auth_info = if api_key.starts_with("Bearer ") {
+                api_key.clone()
+            } else {
+                format!("Bearer {api_key}")
+            };
+
+            reqwest::Client::new()
+                .post(&embedding_service_url)
+                .headers(telemetry::trace_headers())
+                .header(CONTENT_TYPE, "application/json")
+                .header(AUTHORIZATION, auth_info)
+                .json(&embedding_request)
+                .send()
+                .await
+                .map_err(|e| {
+                    let err_msg = format!("Failed to send the embedding request: {e}");
+                    error!("{}", err_msg);
+                    McpError::new(ErrorCode::INTERNAL_ERROR, err_msg, None)
+                })?
+        }
+        None => reqwest::Client::new()
+            .post(&embedding_service_url)
+            .headers(telemetry::trace_headers())
+            .header(CONTENT_TYPE, "application/json")
+            .json(&embedding_request)
+            .send()
+            .await
+            .map_err(|e| {
+                let err_msg = format!("Failed to send the embedding request: {e}");
+                error!("{}", err_msg);
+                McpError::new(ErrorCode::INTERNAL_ERROR, err_msg, None)
+            })?,
+    };
+
+    let bytes = response.bytes().await.map_err(|e| {
+        let err_msg = format!("Failed to parse embeddings response: {e}");
+        error!("{}", err_msg);
+        McpError::new(ErrorCode::INTERNAL_ERROR, err_msg, None)
+    })?;
+
+    // parse the response
+    let embedding_response = serde_json::from_slice::<EmbeddingsResponse>(&bytes).map_err(|e| {
+        let err_msg = format!("Failed to parse embeddings response: {e}");
+        error!("{}", err_msg);
+        McpError::new(ErrorCode::INTERNAL_ERROR, err_msg, None)
+    })?;
+
+    let embedding = embedding_response.data.first().ok_or_else(|| {
+        let err_msg = "No embeddings returned";
+        error!("{}", err_msg);
+        McpError::new(ErrorCode::INTERNAL_ERROR, err_msg, None)
+    })?;
+
+    Ok(embedding.embedding.to_vec())
+}
+
+/// Extract keywords from the query using the chat service
+///
+/// # Arguments
+///
+/// * `config` - The chat service to use
+///
+/// * `query` - The query to extract keywords from
+///
+/// # Returns
+///
+/// A string containing the extracted keywords separated by spaces
+#[instrument(skip_all, fields(model = config.model.as_deref()))]
+pub async fn extract_keywords(
+    config: &ServiceConfig,
+    query: impl AsRef<str>,
+) -> Result<String, McpError> {
+    let text = query.as_ref();
+    let prompt = config
+        .keyword_prompt
+        .as_deref()
+        .unwrap_or(DEFAULT_PROMPT_KEYWORD_EXTRACTOR);
+    let user_prompt = format!("{prompt}\n\n### Input Query\n{text:#?}");
+
+    complete(config, user_prompt, "extracting keywords").await
+}
+
+/// Order the passages by their relevance to the query using the chat service
+///
+/// # Arguments
+///
+/// * `config` - The chat service to use
+///
+/// * `prompt` - The ranking instructions, defaults to the built-in prompt
+///
+/// * `query` - The query the passages are ranked against
+///
+/// * `passages` - The passages, numbered from 1 in the order given
+///
+/// # Returns
+///
+/// The answer of the chat model, which should list the passage numbers from the most relevant
+#[instrument(skip_all, fields(model = config.model.as_deref(), passages = passages.len()))]
+pub async fn rank_passages(
+    config: &ServiceConfig,
+    prompt: Option<&str>,
+    query: &str,
+    passages: &[&str],
+) -> Result<String, McpError> {
+    let prompt = prompt.unwrap_or(DEFAULT_PROMPT_RERANKER);
+    let passages: Vec<String> = passages
+        .iter()
+        .enumerate()
+        .map(|(index, passage)| format!("[{}] {passage}", index + 1))
+        .collect();
+    let user_prompt = format!(
+        "{prompt}\n\n### Query\n{query:#?}\n\n### Passages\n{}",
+        passages.join("\n\n")
+    );
+
+    complete(config, user_prompt, "ranking passages").await
+}
+
+/// Rewrite the query into several search queries using the chat service
+///
+/// # Arguments
+///
+/// * `config` - The chat service to use
+///
+/// * `prompt` - The rewriting instructions, defaults to the built-in prompt
+///
+/// * `query` - The query to rewrite
+///
+/// * `count` - The number of queries to ask for
+///
+/// # Returns
+///
+/// The answer of the chat model, which should list the queries one per line
+#[instrument(skip_all, fields(model = config.model.as_deref(), count))]
+pub async fn rewrite_query(
+    config: &ServiceConfig,
+    prompt: Option<&str>,
+    query: &str,
+    count: usize,
+) -> Result<String, McpError> {
+    let prompt = prompt.unwrap_or(DEFAULT_PROMPT_QUERY_REWRITER);
+    let user_prompt =
+        format!("{prompt}\n\n### Number of Queries\n{count}\n\n### Input Query\n{query:#?}");
+
+    complete(config, user_prompt, "rewriting the query").await
+}
+
+/// Draft a hypothetical document answering the query using the chat service
+///
+/// # Arguments
+///
+/// * `config` - The chat service to use
+///
+/// * `prompt` - The drafting instructions, defaults to the built-in prompt
+///
+/// * `query` - The query to answer
+///
+/// # Returns
+///
+/// The answer of the chat model, which should be a passage answering the query
+#[instrument(skip_all, fields(model = config.model.as_deref()))]
+pub async fn write_hypothetical_document(
+    config: &ServiceConfig,
+    prompt: Option<&str>,
+    query: &str,
+) -> Result<String, McpError> {
+    let prompt = prompt.unwrap_or(DEFAULT_PROMPT_HYPOTHETICAL_DOCUMENT);
+    let user_prompt = format!("{prompt}\n\n### Input Query\n{query:#?}");
+
+    complete(config, user_prompt, "drafting a hypothetical document").await
+}
+
+/// Send the prompt to the chat service and return the content of its answer
+async fn complete(
+    config: &ServiceConfig,
+    user_prompt: String,
+    purpose: &str,
+) -> Result<String, McpError> {
+    let user_message = ChatCompletionRequestMessage::new_user_message(
+        ChatCompletionUserMessageContent::Text(user_prompt),
+        None,
+    );
+
+    // create a request
+    let request = if let Some(model) = &config.model {
+        ChatCompletionRequestBuilder::new(&[user_message])
+            .with_model(model)
+            .with_max_completion_tokens(3200)
+            .build()
+    } else {
+        ChatCompletionRequestBuilder::new(&[user_message]).build()
+    };
+
+    let chat_service_url = format!("{}/chat/completions", config.url.trim_end_matches('/'));
+    debug!(
+        "Forward the chat request to {} for {}",
+        chat_service_url, purpose,
+    );
+    let response = match &config.api_key {
+        Some(api_key) => {
+            let auth_info = if api_key.starts_with("Bearer ") {
+                api_key.clone()
+            } else {
+                format!("Bearer {api_key}")
+            };
+
+            reqwest::Client::new()
+                .post(&chat_service_url)
+                .headers(telemetry::trace_headers())
+                .header(CONTENT_TYPE, "application/json")
+                .header(AUTHORIZATION, auth_info)
+                .json(&request)
+                .send()
+                .await
+                .map_err(|e| {
+                    let err_msg = format!("Failed to send the chat request: {e}");
+                    error!("{}", err_msg);
+                    McpError::new(ErrorCode::INTERNAL_ERROR, err_msg, None)
+                })?
+        }
+        None => reqwest::Client::new()
+            .post(&chat_service_url)
+            .headers(telemetry::trace_headers())
+            .header(CONTENT_TYPE, "application/json")
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| {
+                let err_msg = format!("Failed to send the chat request: {e}");
+                error!("{}", err_msg);
+                McpError::new(ErrorCode::INTERNAL_ERROR, err_msg, None)
+            })?,
+    };
+
+    let chat_completion_object = response.json::<ChatCompletionObject>().await.map_err(|e| {
+        let err_msg = format!("Failed to parse the chat response: {e}");
+        error!("{}", err_msg);
+        McpError::new(ErrorCode::INTERNAL_ERROR, err_msg, None)
+    })?;
+
+    let content = chat_completion_object
+        .choices
+        .first()
+        .and_then(|choice| choice.message.content.as_ref())
+        .ok_or_else(|| {
+            let err_msg = "The chat response has no content";
+            error!("{}", err_msg);
+            McpError::new(ErrorCode::INTERNAL_ERROR, err_msg, None)
+        })?;
+
+    Ok(content.to_string())
+}
+
+/// The answer of the chat model without the reasoning that reasoning models think aloud before
+/// it, up to their closing `</think>` tag
+pub fn strip_reasoning(answer: &str) -> &str {
+    answer
+        .rsplit_once("</think>")
+        .map_or(answer, |(_, answer)| answer)
+}
+
+/// The request of the `/rerank` endpoint, as served by Cohere, Jina or llama.cpp
+#[derive(Debug, Serialize)]
+struct RerankRequest<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<&'a str>,
+    query: &'a str,
+    documents: &'a [&'a str],
+    top_n: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct RerankResponse {
+    results: Vec<RerankResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RerankResult {
+    /// The position of the document in the request
+    index: usize,
+    relevance_score: f64,
+}
+
+/// Score the relevance of the documents to the query using the rerank service
+///
+/// # Arguments
+///
+/// * `config` - The rerank service to use
+///
+/// * `query` - The query the documents are scored against
+///
+/// * `documents` - The texts of the documents
+///
+/// * `top_n` - The number of best documents to return
+///
+/// # Returns
+///
+/// The position of each returned document in `documents` with its relevance score
+#[instrument(skip_all, fields(model = config.model.as_deref(), documents = documents.len()))]
+pub async fn rerank(
+    config: &ServiceConfig,
+    query: &str,
+    documents: &[&str],
+    top_n: usize,
+) -> Result<Vec<(usize, f64)>, McpError> {
+    let rerank_service_url = format!("{}/rerank", config.url.trim_end_matches('/'));
+    let rerank_request = RerankRequest {
+        model: config.model.as_deref(),
+        query,
+        documents,
+        top_n,
+    };
+
+    let request = reqwest::Client::new()
+        .post(&rerank_service_url)
+        .headers(telemetry::trace_headers())
+        .header(CONTENT_TYPE, "application/json");
+    let request = match &config.api_key {
+        Some(api_key) => {
+            let auth_info = if api_key.starts_with("Bearer ") {
+                api_key.clone()
+            } else {
+                format!("Bearer {api_key}")
+            };
+            request.header(AUTHORIZATION, auth_info)
+        }
+        None => request,
+    };
+
+    let response = request.json(&rerank_request).send().await.map_err(|e| {
+        let err_msg = format!("Failed to send the rerank request: {e}");
+        error!("{}", err_msg);
+        McpError::new(ErrorCode::INTERNAL_ERROR, err_msg, None)
+    })?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        let err_msg = format!("The rerank service failed. Status: {status}. Body: {body}");
+        error!("{}", err_msg);
+        return Err(McpError::new(ErrorCode::INTERNAL_ERROR, err_msg, None));
+    }
+
+    let rerank_response = response.json::<RerankResponse>().await.map_err(|e| {
+        let err_msg = format!("Failed to parse the rerank response: {e}");
+        error!("{}", err_msg);
+        McpError::new(ErrorCode::INTERNAL_ERROR, err_msg, None)
+    })?;
+
+    if let Some(result) = rerank_response
+        .results
+        .iter()
+        .find(|result| result.index >= documents.len())
+    {
+        let err_msg = format!(
+            "The rerank service returned the document {} of {}",
+            result.index,
+            documents.len()
+        );
+        error!("{}", err_msg);
+        return Err(McpError::new(ErrorCode::INTERNAL_ERROR, err_msg, None));
+    }
+
+    Ok(rerank_response
+        .results
+        .into_iter()
+        .map(|result| (result.index, result.relevance_score))
+        .collect())
+}
+
+/// Check that the service responds, by listing its models
+pub async fn health_check(config: &ServiceConfig) -> Result<(), McpError> {
+    let models_url = format!("{}/models", config.url.trim_end_matches('/'));
+
+    let request = reqwest::Client::new()
+        .get(&models_url)
+        .headers(telemetry::trace_headers());
+    let request = match &config.api_key {
+        Some(api_key) => {
+            let auth_info = if api_key.starts_with("Bearer ") {
+                api_key.clone()
+            } else {
+                format!("Bearer {api_key}")
+            };
+            request.header(AUTHORIZATION, auth_info)
+        }
+        None => request,
+    };
+
+    let response = request.send().await.map_err(|e| {
+        let err_msg = format!("Failed to reach the service at {}: {e}", config.url);
+        error!("{}", err_msg);
+        McpError::new(ErrorCode::INTERNAL_ERROR, err_msg, None)
+    })?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let err_msg = format!(
+            "The service at {} failed to list its models. Status: {status}",
+            config.url
+        );
+        error!("{}", err_msg);
+        return Err(McpError::new(ErrorCode::INTERNAL_ERROR, err_msg, None));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_reasoning_is_stripped() {
+        assert_eq!(strip_reasoning("[2] > [1]"), "[2] > [1]");
+        assert_eq!(
+            strip_reasoning("<think>[1] or [2]?</think>\n[2] > [1]"),
+            "\n[2] > [1]"
+        );
+        // only the last closing tag ends the reasoning
+        assert_eq!(strip_reasoning("a</think>b</think>c"), "c");
+        assert_eq!(strip_reasoning("<think>unfinished"), "<think>unfinished");
+    }
+}
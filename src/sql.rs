@@ -0,0 +1,118 @@
+//! Helpers for building SQL statements from configured identifiers.
+//!
+//! Values such as search keywords are always bound as statement parameters. Identifiers
+//! (tables and columns) cannot be bound, so they are validated once at startup and quoted
+//! whenever they are interpolated into a statement.
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+static IDENTIFIER_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Za-z_][A-Za-z0-9_$]{0,63}$").unwrap());
+
+/// Check that `name` is a plain SQL identifier: an ASCII letter or underscore followed by at
+/// most 63 ASCII letters, digits, underscores or dollar signs
+pub fn validate_identifier(kind: &str, name: &str) -> Result<(), String> {
+    if IDENTIFIER_RE.is_match(name) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Invalid {kind} `{name}`: identifiers must start with a letter or underscore and contain only letters, digits, `_` or `$` (at most 64 characters)"
+        ))
+    }
+}
+
+/// Check that `name` is a plain SQL identifier, optionally qualified by a schema, e.g.
+/// `english` or `public.english`
+pub fn validate_qualified_identifier(kind: &str, name: &str) -> Result<(), String> {
+    let valid = match name.split_once('.') {
+        Some((schema, name)) => IDENTIFIER_RE.is_match(schema) && IDENTIFIER_RE.is_match(name),
+        None => IDENTIFIER_RE.is_match(name),
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "Invalid {kind} `{name}`: identifiers, optionally qualified as `schema.name`, must start with a letter or underscore and contain only letters, digits, `_` or `$` (at most 64 characters)"
+        ))
+    }
+}
+
+/// Quote an identifier for MySQL/TiDB with backticks, doubling any embedded backtick
+pub fn quote_mysql_identifier(name: &str) -> String {
+    format!("`{}`", name.replace('`', "``"))
+}
+
+/// Quote an identifier for Postgres with double quotes, doubling any embedded double quote
+pub fn quote_postgres_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Quote each part of a validated, optionally schema-qualified identifier for Postgres
+pub fn quote_postgres_qualified_identifier(name: &str) -> String {
+    name.split('.')
+        .map(quote_postgres_identifier)
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_identifiers_are_valid() {
+        for name in ["docs", "_docs", "Docs_2024", "price$", &"a".repeat(64)] {
+            assert!(validate_identifier("table", name).is_ok(), "{name}");
+        }
+    }
+
+    #[test]
+    fn identifiers_that_need_quoting_are_invalid() {
+        for name in [
+            "",
+            "2docs",
+            "$docs",
+            "my docs",
+            "docs;DROP TABLE docs",
+            "do\"cs",
+            "do`cs",
+            "public.docs",
+            "docés",
+            &"a".repeat(65),
+        ] {
+            assert!(validate_identifier("table", name).is_err(), "{name}");
+        }
+    }
+
+    #[test]
+    fn qualified_identifiers_allow_one_schema() {
+        assert!(validate_qualified_identifier("config", "english").is_ok());
+        assert!(validate_qualified_identifier("config", "public.english").is_ok());
+
+        for name in [
+            "public.",
+            ".english",
+            "a.b.c",
+            "public.eng lish",
+            "pub'lic.english",
+        ] {
+            assert!(
+                validate_qualified_identifier("config", name).is_err(),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn quoting_doubles_the_quotes() {
+        assert_eq!(quote_mysql_identifier("docs"), "`docs`");
+        assert_eq!(quote_mysql_identifier("do`cs"), "`do``cs`");
+        assert_eq!(quote_postgres_identifier("docs"), "\"docs\"");
+        assert_eq!(quote_postgres_identifier("do\"cs"), "\"do\"\"cs\"");
+        assert_eq!(
+            quote_postgres_qualified_identifier("public.english"),
+            "\"public\".\"english\""
+        );
+    }
+}
@@ -0,0 +1,306 @@
+//! The HTTP with Server-Sent Events transport of MCP.
+//!
+//! A client opens an event stream with `GET /sse`, receives an `endpoint` event with the URL of
+//! its session, and posts its messages to that URL. Each session is served by its own service,
+//! created by the service factory.
+
+use crate::drain;
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    http::{StatusCode, request::Parts},
+    response::sse::{Event, KeepAlive, Sse},
+    routing::{get, post},
+};
+use futures::{Stream, StreamExt};
+use rmcp::{
+    RoleServer, Service, ServiceExt,
+    model::ClientJsonRpcMessage,
+    service::TxJsonRpcMessage,
+    transport::common::server_side_http::{DEFAULT_AUTO_PING_INTERVAL, SessionId, session_id},
+};
+use std::{collections::HashMap, io, sync::Arc};
+use tokio::sync::{RwLock, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_util::{
+    sync::{CancellationToken, PollSender},
+    task::TaskTracker,
+};
+use tracing::{debug, error, info};
+
+/// Path of the event streams
+pub const SSE_PATH: &str = "/sse";
+/// Path the clients post their messages to
+pub const MESSAGE_PATH: &str = "/message";
+
+/// Capacity of the message channels of a session
+const CHANNEL_CAPACITY: usize = 64;
+
+type SessionStore = Arc<RwLock<HashMap<SessionId, mpsc::Sender<ClientJsonRpcMessage>>>>;
+
+struct SseState<S> {
+    sessions: SessionStore,
+    service_factory: Arc<dyn Fn() -> S + Send + Sync>,
+    in_flight: TaskTracker,
+    ct: CancellationToken,
+}
+impl<S> Clone for SseState<S> {
+    fn clone(&self) -> Self {
+        Self {
+            sessions: self.sessions.clone(),
+            service_factory: self.service_factory.clone(),
+            in_flight: self.in_flight.clone(),
+            ct: self.ct.clone(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MessageQuery {
+    session_id: String,
+}
+
+/// Build the router of the SSE transport, serving each session with a new service
+///
+/// The requests of the sessions are tracked in `in_flight` until responded to. Cancelling `ct`
+/// closes all sessions.
+pub fn router<S>(
+    service_factory: impl Fn() -> S + Send + Sync + 'static,
+    in_flight: TaskTracker,
+    ct: CancellationToken,
+) -> Router
+where
+    S: Service<RoleServer> + Send + 'static,
+{
+    let state = SseState {
+        sessions: Default::default(),
+        service_factory: Arc::new(service_factory),
+        in_flight,
+        ct,
+    };
+
+    Router::new()
+        .route(SSE_PATH, get(sse_handler::<S>))
+        .route(MESSAGE_PATH, post(message_handler::<S>))
+        .with_state(state)
+}
+
+async fn sse_handler<S>(
+    State(state): State<SseState<S>>,
+    parts: Parts,
+) -> Sse<impl Stream<Item = Result<Event, io::Error>>>
+where
+    S: Service<RoleServer> + Send + 'static,
+{
+    let session = session_id();
+    info!("New SSE session {}", session);
+    debug!("SSE connection request: {:?}", parts);
+
+    let (from_client_tx, from_client_rx) = mpsc::channel(CHANNEL_CAPACITY);
+    let (to_client_tx, to_client_rx) =
+        mpsc::channel::<TxJsonRpcMessage<RoleServer>>(CHANNEL_CAPACITY);
+    state
+        .sessions
+        .write()
+        .await
+        .insert(session.clone(), from_client_tx);
+
+    // serve the session until the client disconnects or the server shuts down
+    let service = (state.service_factory)();
+    let transport = drain::track(
+        (
+            PollSender::new(to_client_tx.clone()),
+            ReceiverStream::new(from_client_rx),
+        ),
+        state.in_flight.clone(),
+    );
+    let ct = state.ct.child_token();
+    let session_ct = ct.clone();
+    tokio::spawn(async move {
+        match service.serve_with_ct(transport, session_ct).await {
+            Ok(server) => {
+                if let Err(e) = server.waiting().await {
+                    error!("SSE session failed: {e}");
+                }
+            }
+            Err(e) => error!("Failed to start SSE session: {e}"),
+        }
+    });
+
+    // forget the session once its event stream is dropped or the session is closed. Once closed,
+    // the event stream ends after the messages left are sent, as no sender remains.
+    let sessions = state.sessions.clone();
+    let closed_session = session.clone();
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = to_client_tx.closed() => {}
+            _ = ct.cancelled() => {}
+        }
+        drop(to_client_tx);
+        sessions.write().await.remove(&closed_session);
+        info!("Closed SSE session {}", closed_session);
+    });
+
+    let endpoint = Event::default()
+        .event("endpoint")
+        .data(format!("{MESSAGE_PATH}?sessionId={session}"));
+    let messages = ReceiverStream::new(to_client_rx).map(|message| {
+        serde_json::to_string(&message)
+            .map(|data| Event::default().event("message").data(data))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    });
+
+    Sse::new(futures::stream::once(async { Ok(endpoint) }).chain(messages))
+        .keep_alive(KeepAlive::new().interval(DEFAULT_AUTO_PING_INTERVAL))
+}
+
+async fn message_handler<S>(
+    State(state): State<SseState<S>>,
+    Query(MessageQuery { session_id }): Query<MessageQuery>,
+    parts: Parts,
+    Json(mut message): Json<ClientJsonRpcMessage>,
+) -> StatusCode {
+    let tx = match state.sessions.read().await.get(session_id.as_str()) {
+        Some(tx) => tx.clone(),
+        None => return StatusCode::NOT_FOUND,
+    };
+
+    // expose the HTTP request to the tools, like the Streamable HTTP transport does
+    message.insert_extension(parts);
+    if tx.send(message).await.is_err() {
+        error!(
+            "Failed to forward the message to SSE session {}",
+            session_id
+        );
+        return StatusCode::GONE;
+    }
+
+    StatusCode::ACCEPTED
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use rmcp::ServerHandler;
+    use serde_json::{Value, json};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+        net::TcpStream,
+    };
+
+    #[derive(Clone)]
+    struct Handler;
+    impl ServerHandler for Handler {}
+
+    /// Read the events of a stream until one of the given kind, returning its data
+    async fn next_event(lines: &mut Lines<BufReader<TcpStream>>, kind: &str) -> String {
+        let mut event = None;
+        while let Some(line) = lines.next_line().await.unwrap() {
+            if let Some(name) = line.strip_prefix("event: ") {
+                event = Some(name.to_string());
+            } else if let Some(data) = line.strip_prefix("data: ")
+                && event.as_deref() == Some(kind)
+            {
+                return data.to_string();
+            }
+        }
+        panic!("the event stream ended before a `{kind}` event");
+    }
+
+    fn initialize() -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": {
+                "protocolVersion": "2025-03-26",
+                "capabilities": {},
+                "clientInfo": { "name": "test", "version": "0.1.0" },
+            },
+        })
+    }
+
+    #[tokio::test]
+    async fn the_responses_are_sent_on_the_event_stream_of_the_session() {
+        let address = test_support::serve(router(
+            || Handler,
+            TaskTracker::new(),
+            CancellationToken::new(),
+        ))
+        .await;
+
+        // HTTP/1.0 keeps the event stream free of chunk headers
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream
+            .write_all(format!("GET {SSE_PATH} HTTP/1.0\r\n\r\n").as_bytes())
+            .await
+            .unwrap();
+        let mut events = BufReader::new(stream).lines();
+        let endpoint = next_event(&mut events, "endpoint").await;
+        assert!(endpoint.starts_with("/message?sessionId="), "{endpoint}");
+
+        let client = reqwest::Client::new();
+        let response = client
+            .post(format!("http://{address}{endpoint}"))
+            .json(&initialize())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        let message: Value =
+            serde_json::from_str(&next_event(&mut events, "message").await).unwrap();
+        assert_eq!(message["id"], 1);
+        assert!(message["result"]["serverInfo"].is_object(), "{message}");
+    }
+
+    #[tokio::test]
+    async fn the_event_streams_end_once_the_sessions_are_closed() {
+        let in_flight = TaskTracker::new();
+        let ct = CancellationToken::new();
+        let address = test_support::serve(router(|| Handler, in_flight.clone(), ct.clone())).await;
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream
+            .write_all(format!("GET {SSE_PATH} HTTP/1.0\r\n\r\n").as_bytes())
+            .await
+            .unwrap();
+        let mut events = BufReader::new(stream).lines();
+        let endpoint = next_event(&mut events, "endpoint").await;
+
+        let response = reqwest::Client::new()
+            .post(format!("http://{address}{endpoint}"))
+            .json(&initialize())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        next_event(&mut events, "message").await;
+        assert!(in_flight.is_empty());
+
+        ct.cancel();
+        while let Some(line) = events.next_line().await.unwrap() {
+            assert!(!line.starts_with("event: message"), "{line}");
+        }
+    }
+
+    #[tokio::test]
+    async fn messages_to_unknown_sessions_are_not_found() {
+        let address = test_support::serve(router(
+            || Handler,
+            TaskTracker::new(),
+            CancellationToken::new(),
+        ))
+        .await;
+
+        let response = reqwest::Client::new()
+            .post(format!("http://{address}{MESSAGE_PATH}?sessionId=unknown"))
+            .json(&initialize())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
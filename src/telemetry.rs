@@ -0,0 +1,246 @@
+//! OpenTelemetry tracing of the search calls, exported to an OTLP/HTTP collector.
+//!
+//! The trace context is propagated with the W3C `traceparent` and `tracestate` headers, from the
+//! HTTP requests of the MCP clients and into the requests to Qdrant and the embedding and chat
+//! services.
+
+use opentelemetry::{Context, global, trace::TracerProvider as _};
+use opentelemetry_http::{HeaderExtractor, HeaderInjector};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{Resource, propagation::TraceContextPropagator, trace::SdkTracerProvider};
+use tracing::{Span, Subscriber};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{Layer, registry::LookupSpan};
+
+/// Path of the traces endpoint of an OTLP/HTTP collector
+const OTLP_TRACES_PATH: &str = "/v1/traces";
+
+/// Build the tracer provider exporting the spans to the collector at `endpoint`, e.g.
+/// `http://localhost:4318`, and install the W3C trace context propagator
+///
+/// The service name defaults to the package name, and can be overridden with the
+/// `OTEL_SERVICE_NAME` environment variable.
+pub fn init_tracer_provider(endpoint: &str) -> anyhow::Result<SdkTracerProvider> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!(
+            "{}{OTLP_TRACES_PATH}",
+            endpoint.trim_end_matches('/')
+        ))
+        .build()?;
+
+    let resource = match std::env::var("OTEL_SERVICE_NAME") {
+        Ok(_) => Resource::builder().build(),
+        Err(_) => Resource::builder()
+            .with_service_name(env!("CARGO_PKG_NAME"))
+            .build(),
+    };
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource)
+        .build();
+
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    Ok(provider)
+}
+
+/// The layer turning the `tracing` spans into OpenTelemetry spans exported by the provider
+pub fn layer<S>(provider: &SdkTracerProvider) -> impl Layer<S> + use<S>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer(env!("CARGO_PKG_NAME")))
+}
+
+/// Make the trace context of the HTTP request, if any, the parent of the span
+///
+/// Does nothing if tracing is disabled. Must be called before the span is entered.
+pub fn set_parent_from_headers(span: &Span, headers: &axum::http::HeaderMap) {
+    let parent =
+        global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)));
+    let _ = span.set_parent(parent);
+}
+
+/// The headers propagating the trace context of the current span to an outgoing request
+///
+/// Empty if tracing is disabled.
+pub fn trace_headers() -> reqwest::header::HeaderMap {
+    let context: Context = Span::current().context();
+    let mut headers = reqwest::header::HeaderMap::new();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(&mut headers))
+    });
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        AgenticSearchConfig, QdrantConfig,
+        backend::qdrant::QdrantBackend,
+        limit::SearchLimiter,
+        search::AgenticSearchServer,
+        test_support::{self, StubServices},
+    };
+    use axum::{Json, Router, body::Bytes, extract::State, http::HeaderMap, routing::post};
+    use rmcp::transport::streamable_http_server::{
+        StreamableHttpServerConfig, StreamableHttpService, session::local::LocalSessionManager,
+    };
+    use serde_json::{Value, json};
+    use std::sync::{Arc, Mutex};
+    use tokio::sync::watch;
+    use tokio_util::task::TaskTracker;
+    use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+    /// The trace of the client calling the `search` tool
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+    const PARENT_SPAN_ID: &str = "00f067aa0ba902b7";
+
+    /// The bodies of the requests received by a stub, and their `traceparent` headers
+    #[derive(Clone, Default)]
+    struct Recorder {
+        bodies: Arc<Mutex<Vec<Bytes>>>,
+        traceparents: Arc<Mutex<Vec<String>>>,
+    }
+    impl Recorder {
+        fn record(&self, headers: &HeaderMap, body: Bytes) {
+            if let Some(traceparent) = headers.get("traceparent") {
+                let traceparent = traceparent.to_str().unwrap().to_string();
+                self.traceparents.lock().unwrap().push(traceparent);
+            }
+            self.bodies.lock().unwrap().push(body);
+        }
+    }
+
+    async fn otlp_traces(State(recorder): State<Recorder>, headers: HeaderMap, body: Bytes) {
+        recorder.record(&headers, body);
+    }
+
+    async fn qdrant_search(
+        State(recorder): State<Recorder>,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> Json<Value> {
+        recorder.record(&headers, body);
+        Json(json!({
+            "result": [{ "id": 1, "score": 0.9, "payload": { "text": "Rust is a language" } }],
+            "status": "ok",
+        }))
+    }
+
+    fn search_config(qdrant_url: String, services: &StubServices) -> AgenticSearchConfig {
+        let qdrant = QdrantConfig {
+            api_key: None,
+            base_url: qdrant_url,
+            collection: "docs".to_string(),
+            payload_source: "text".to_string(),
+            title_field: None,
+            metadata_fields: None,
+            filterable_fields: Vec::new(),
+            score_threshold: 0.5,
+        };
+        test_support::search_config(vec![Arc::new(QdrantBackend::new(
+            "qdrant",
+            qdrant,
+            services.config(),
+        ))])
+    }
+
+    #[tokio::test]
+    async fn search_calls_are_exported_and_propagated() {
+        let collector = Recorder::default();
+        let collector_address = test_support::serve(
+            Router::new()
+                .route(OTLP_TRACES_PATH, post(otlp_traces))
+                .with_state(collector.clone()),
+        )
+        .await;
+        let provider = init_tracer_provider(&format!("http://{collector_address}")).unwrap();
+        // the runtime of the test runs on a single thread, where the layer is installed
+        let _subscriber = tracing_subscriber::registry()
+            .with(layer(&provider))
+            .set_default();
+
+        let qdrant = Recorder::default();
+        let qdrant_address = test_support::serve(
+            Router::new()
+                .route("/collections/docs/points/search", post(qdrant_search))
+                .with_state(qdrant.clone()),
+        )
+        .await;
+        let services = StubServices::spawn(|_| vec![1.0, 0.0], |_| String::new()).await;
+        let (_, config) = watch::channel(Arc::new(search_config(
+            format!("http://{qdrant_address}"),
+            &services,
+        )));
+        let service = StreamableHttpService::new(
+            move || {
+                Ok(AgenticSearchServer::new(
+                    config.clone(),
+                    TaskTracker::new(),
+                    SearchLimiter::new(None, None),
+                ))
+            },
+            LocalSessionManager::default().into(),
+            StreamableHttpServerConfig {
+                stateful_mode: false,
+                ..Default::default()
+            },
+        );
+        let server_address = test_support::serve(Router::new().nest_service("/mcp", service)).await;
+
+        let response = reqwest::Client::new()
+            .post(format!("http://{server_address}/mcp"))
+            .header("Accept", "application/json, text/event-stream")
+            .header("traceparent", format!("00-{TRACE_ID}-{PARENT_SPAN_ID}-01"))
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "tools/call",
+                "params": { "name": "search", "arguments": { "query": "rust" } },
+            }))
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert!(response.contains("Rust is a language"), "{response}");
+
+        // the requests to the embedding service and Qdrant continue the trace of the client,
+        // under the spans of the server
+        let traceparents: Vec<String> = [&services.traceparents, &qdrant.traceparents]
+            .into_iter()
+            .flat_map(|traceparents| traceparents.lock().unwrap().clone())
+            .collect();
+        assert_eq!(traceparents.len(), 2);
+        for traceparent in &traceparents {
+            let parts: Vec<&str> = traceparent.split('-').collect();
+            assert_eq!(parts.len(), 4, "{traceparent}");
+            assert_eq!(parts[1], TRACE_ID);
+            assert_ne!(parts[2], PARENT_SPAN_ID);
+        }
+
+        // the batch exporter sends the spans to the collector on its own thread
+        tokio::task::spawn_blocking(move || provider.force_flush())
+            .await
+            .unwrap()
+            .unwrap();
+        let exported = collector.bodies.lock().unwrap().concat();
+        let contains = |bytes: &[u8]| exported.windows(bytes.len()).any(|window| window == bytes);
+        let trace_id: Vec<u8> = (0..TRACE_ID.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&TRACE_ID[i..i + 2], 16).unwrap())
+            .collect();
+        assert!(contains(&trace_id));
+        for span in [b"search".as_slice(), b"search_in_qdrant"] {
+            assert!(
+                contains(span),
+                "{} was not exported",
+                String::from_utf8_lossy(span)
+            );
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! Stand-ins of the external services for the tests.
+
+use crate::{
+    AgenticSearchConfig, SearchIndex, ServiceConfig, backend::SearchBackend, mmr::MmrConfig,
+};
+use axum::{Json, Router, extract::State, http::HeaderMap, routing::post};
+use serde_json::{Value, json};
+use std::{
+    net::SocketAddr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+/// Serve the router on a free local port until the test runtime stops
+pub async fn serve(router: Router) -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await });
+
+    address
+}
+
+/// A stub of the OpenAI-compatible embedding and chat services, which embeds the texts and
+/// answers the prompts with the given functions
+#[derive(Clone)]
+pub struct StubServices {
+    pub base_url: String,
+    pub embedding_calls: Arc<AtomicUsize>,
+    pub chat_calls: Arc<AtomicUsize>,
+    /// The `traceparent` headers of the requests, if any
+    pub traceparents: Arc<Mutex<Vec<String>>>,
+}
+impl StubServices {
+    pub async fn spawn(embed: fn(&str) -> Vec<f64>, answer: fn(&str) -> String) -> Self {
+        #[derive(Clone)]
+        struct Stub {
+            embed: fn(&str) -> Vec<f64>,
+            answer: fn(&str) -> String,
+            embedding_calls: Arc<AtomicUsize>,
+            chat_calls: Arc<AtomicUsize>,
+            traceparents: Arc<Mutex<Vec<String>>>,
+        }
+        impl Stub {
+            fn record_traceparent(&self, headers: &HeaderMap) {
+                if let Some(traceparent) = headers.get("traceparent") {
+                    let traceparent = traceparent.to_str().unwrap_or_default().to_string();
+                    self.traceparents.lock().unwrap().push(traceparent);
+                }
+            }
+        }
+
+        async fn embeddings(
+            State(stub): State<Stub>,
+            headers: HeaderMap,
+            Json(request): Json<Value>,
+        ) -> Json<Value> {
+            stub.embedding_calls.fetch_add(1, Ordering::SeqCst);
+            stub.record_traceparent(&headers);
+            let embedding = (stub.embed)(request["input"].as_str().unwrap_or_default());
+            Json(json!({
+                "object": "list",
+                "data": [{ "index": 0, "object": "embedding", "embedding": embedding }],
+                "model": "stub",
+                "usage": { "prompt_tokens": 1, "completion_tokens": 0, "total_tokens": 1 },
+            }))
+        }
+
+        async fn chat(
+            State(stub): State<Stub>,
+            headers: HeaderMap,
+            Json(request): Json<Value>,
+        ) -> Json<Value> {
+            stub.chat_calls.fetch_add(1, Ordering::SeqCst);
+            stub.record_traceparent(&headers);
+            let answer = (stub.answer)(
+                request["messages"][0]["content"]
+                    .as_str()
+                    .unwrap_or_default(),
+            );
+            Json(json!({
+                "id": "stub",
+                "object": "chat.completion",
+                "created": 0,
+                "model": "stub",
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": answer },
+                    "finish_reason": "stop",
+                    "logprobs": null,
+                }],
+                "usage": { "prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2 },
+            }))
+        }
+
+        let stub = Stub {
+            embed,
+            answer,
+            embedding_calls: Arc::default(),
+            chat_calls: Arc::default(),
+            traceparents: Arc::default(),
+        };
+        let address = serve(
+            Router::new()
+                .route("/v1/embeddings", post(embeddings))
+                .route("/v1/chat/completions", post(chat))
+                .with_state(stub.clone()),
+        )
+        .await;
+
+        Self {
+            base_url: format!("http://{address}/v1"),
+            embedding_calls: stub.embedding_calls,
+            chat_calls: stub.chat_calls,
+            traceparents: stub.traceparents,
+        }
+    }
+
+    pub fn config(&self) -> ServiceConfig {
+        ServiceConfig {
+            url: self.base_url.clone(),
+            api_key: None,
+            model: None,
+            keyword_prompt: None,
+        }
+    }
+}
+
+/// The query a prompt ends with, under its `### Input Query` heading
+pub fn input_query(prompt: &str) -> String {
+    prompt
+        .rsplit_once("### Input Query")
+        .and_then(|(_, query)| serde_json::from_str(query.trim()).ok())
+        .unwrap_or_default()
+}
+
+/// A search config of a single default index holding the backends, without reranking,
+/// diversification, query rewriting or HyDE
+pub fn search_config(backends: Vec<Arc<dyn SearchBackend>>) -> AgenticSearchConfig {
+    AgenticSearchConfig {
+        indexes: vec![SearchIndex {
+            name: "default".to_string(),
+            description: None,
+            backends,
+        }],
+        default_target: Some("default".to_string()),
+        limit: 10,
+        fusion: Default::default(),
+        backend_timeout: Duration::from_secs(10),
+        max_limit: 100,
+        max_offset: 1000,
+        rerank: None,
+        mmr: MmrConfig {
+            lambda: None,
+            overfetch: 1,
+        },
+        rewrite: None,
+        hyde: None,
+    }
+}
@@ -1,34 +1,182 @@
-use mysql_common::prelude::FromRow;
+use crate::{backend::BackendDescription, hyde::HypotheticalDocument};
 use rmcp::schemars;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{Map, Value};
 use std::collections::HashMap;
 
 #[derive(Debug, Deserialize, schemars::JsonSchema)]
 pub struct SearchRequest {
     #[schemars(description = "The query to search for")]
     pub query: String,
+    #[schemars(
+        description = "Maximum number of results to return. Defaults to the server's configured limit"
+    )]
+    #[serde(default)]
+    pub limit: Option<u64>,
+    #[schemars(
+        description = "Minimum relevance score of the results: the vector similarity in vector and hybrid mode, the full-text relevance in keyword mode. Defaults to the server's configured thresholds"
+    )]
+    #[serde(default)]
+    pub score_threshold: Option<f32>,
+    #[schemars(
+        description = "Retrieval mode: `vector`, `keyword` or `hybrid`. Only the modes backed by the server's configured backends are available. Defaults to all configured backends"
+    )]
+    #[serde(default)]
+    pub mode: Option<RetrievalMode>,
+    #[schemars(description = "Number of results to skip, for pagination. Defaults to 0")]
+    #[serde(default)]
+    pub offset: Option<u64>,
+    #[schemars(
+        description = "Payload filter for vector search, e.g. `{\"must\": [{\"key\": \"lang\", \"match\": {\"value\": \"en\"}}]}`. Only applies to vector search, so it defaults `mode` to `vector`"
+    )]
+    #[serde(default)]
+    pub filter: Option<SearchFilter>,
+    #[schemars(
+        description = "The name of the index to search, as listed by the `list_indexes` tool. Defaults to the server's default index"
+    )]
+    #[serde(default)]
+    pub target: Option<String>,
+    #[schemars(
+        description = "Trade-off between relevance (1.0) and diversity (0.0) of the results, to avoid near-duplicate results such as chunks of the same paragraph. Only the results with vectors, from vector search, are diversified. Defaults to the server's configured trade-off, or no diversification"
+    )]
+    #[serde(default)]
+    pub mmr_lambda: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RetrievalMode {
+    /// Vector search only
+    Vector,
+    /// Keyword search only
+    Keyword,
+    /// Both vector and keyword search, with the results fused
+    Hybrid,
+}
+impl std::fmt::Display for RetrievalMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RetrievalMode::Vector => write!(f, "vector"),
+            RetrievalMode::Keyword => write!(f, "keyword"),
+            RetrievalMode::Hybrid => write!(f, "hybrid"),
+        }
+    }
+}
+
+/// The validated parameters of a single search call
+#[derive(Debug, Clone)]
+pub struct SearchParams {
+    /// The retrieval mode of the call
+    pub mode: RetrievalMode,
+    pub limit: u64,
+    pub offset: u64,
+    /// Overrides the backend's configured score threshold
+    pub score_threshold: Option<f32>,
+    /// The payload filter, already validated against the selected backends
+    pub filter: Option<SearchFilter>,
+    /// The trade-off between relevance and diversity of the results, if they are diversified
+    pub mmr_lambda: Option<f64>,
+    /// The document the vector backends embed instead of the query, if drafted with HyDE
+    pub hypothetical_document: Option<HypotheticalDocument>,
 }
 
 #[derive(Debug, Serialize, Deserialize, schemars::JsonSchema)]
-#[allow(dead_code)]
 pub struct SearchResponse {
-    #[schemars(description = "The result of the search operation")]
-    pub text: String,
+    #[schemars(description = "The search results, best match first")]
+    pub results: Vec<SearchResult>,
+    #[schemars(
+        description = "Problems that did not fail the search, e.g. a backend that timed out"
+    )]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Serialize, schemars::JsonSchema)]
+pub struct ListIndexesResponse {
+    #[schemars(description = "The indexes that can be searched")]
+    pub indexes: Vec<IndexDescription>,
+}
+
+#[derive(Debug, Serialize, schemars::JsonSchema)]
+pub struct IndexDescription {
+    #[schemars(description = "The name of the index, to pass as the `target` of a search")]
+    pub name: String,
+    #[schemars(description = "What the index holds, if the server describes it")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[schemars(description = "Whether the searches without a `target` search this index")]
+    pub default: bool,
+    #[schemars(description = "The retrieval modes the index supports")]
+    pub modes: Vec<RetrievalMode>,
+    #[schemars(description = "The backends searched in the index")]
+    pub backends: Vec<BackendDescription>,
 }
 
-#[derive(Debug, Serialize, Deserialize, schemars::JsonSchema, FromRow)]
+#[derive(Debug, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct SearchResult {
+    #[schemars(
+        description = "The id of the document in its backend: the Qdrant point id or the value of the TiDB id field. Absent if the backend did not return one"
+    )]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[schemars(
+        description = "The backend that returned the document first, e.g. `qdrant` or `tidb`"
+    )]
+    pub backend: String,
+    #[schemars(
+        description = "The 1-based rank of the document in the response, counting from the first page"
+    )]
+    pub rank: usize,
+    #[schemars(
+        description = "The relevance score of the document. The backend's native score for single-backend searches, the fused score for hybrid searches, or the score of the reranker if the server reranks the results"
+    )]
+    pub score: Option<f64>,
+    #[schemars(description = "The title of the document, if the backend has a title field")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[schemars(description = "The source text of the document")]
+    pub source: String,
+    #[schemars(description = "Selected metadata fields of the document")]
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub metadata: Map<String, Value>,
+    #[schemars(
+        description = "The native rank and score of the document in every backend that returned it"
+    )]
+    pub matches: Vec<BackendMatch>,
+    /// The embedding of the document, to diversify the results. Not sent to the clients
+    #[serde(skip)]
+    #[schemars(skip)]
+    pub vector: Option<Vec<f64>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct BackendMatch {
+    #[schemars(description = "The name of the backend")]
+    pub backend: String,
+    #[schemars(description = "The 1-based rank of the document in the backend's results")]
+    pub rank: usize,
+    #[schemars(description = "The backend's native relevance score, if it reports one")]
+    pub score: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, schemars::JsonSchema)]
 pub struct TidbSearchHit {
-    #[schemars(description = "The id of the tidb server")]
-    pub id: i32,
-    #[schemars(description = "The title of the tidb server")]
-    pub title: String,
-    #[schemars(description = "The content of the tidb server")]
+    #[schemars(description = "The value of the id field of the row, if it was returned")]
+    pub id: Option<String>,
+    #[schemars(description = "The value of the title field of the row, if it was returned")]
+    pub title: Option<String>,
+    #[schemars(description = "The `fts_match_word` relevance score of the row")]
+    pub score: f64,
+    #[schemars(description = "The human-readable content of the row")]
     pub content: String,
+    #[schemars(description = "The returned fields of the row")]
+    pub fields: Map<String, Value>,
 }
 
 #[derive(Debug, Serialize, Deserialize, schemars::JsonSchema)]
 pub struct QdrantSearchHit {
+    #[schemars(description = "The id of the point")]
+    pub id: String,
     #[schemars(description = "The score of the point")]
     pub score: f64,
     #[schemars(description = "The payload of the point")]
@@ -36,3 +184,128 @@ pub struct QdrantSearchHit {
     #[schemars(description = "The vector of the point")]
     pub vector: Vec<f64>,
 }
+
+/// A single result returned by a search backend
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    /// The name of the backend that produced the hit, e.g. `qdrant` or `tidb`
+    pub backend: String,
+    /// The 1-based rank of the hit in the backend's result list
+    pub rank: usize,
+    /// The backend's native relevance score, if it reports one
+    pub score: Option<f64>,
+    /// The id of the document in the backend
+    pub id: Option<String>,
+    /// The title of the document
+    pub title: Option<String>,
+    /// The source text of the document
+    pub source: String,
+    /// Selected metadata fields of the document
+    pub metadata: Map<String, Value>,
+    /// The embedding of the document, if the backend returns it
+    pub vector: Option<Vec<f64>>,
+}
+
+impl SearchHit {
+    pub fn to_match(&self) -> BackendMatch {
+        BackendMatch {
+            backend: self.backend.clone(),
+            rank: self.rank,
+            score: self.score,
+        }
+    }
+
+    /// Convert the hit of a single-backend search into a search result
+    pub fn into_result(self, rank: usize) -> SearchResult {
+        SearchResult {
+            matches: vec![self.to_match()],
+            id: self.id,
+            backend: self.backend,
+            rank,
+            score: self.score,
+            title: self.title,
+            source: self.source,
+            metadata: self.metadata,
+            vector: self.vector,
+        }
+    }
+}
+
+/// A payload filter for vector search, a safe subset of Qdrant's filter syntax
+#[derive(Debug, Clone, Default, Deserialize, schemars::JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SearchFilter {
+    #[schemars(description = "Conditions that must all be satisfied")]
+    #[serde(default)]
+    pub must: Vec<FilterCondition>,
+    #[schemars(description = "Conditions of which at least one must be satisfied")]
+    #[serde(default)]
+    pub should: Vec<FilterCondition>,
+    #[schemars(description = "Conditions that must not be satisfied")]
+    #[serde(default)]
+    pub must_not: Vec<FilterCondition>,
+}
+
+/// A condition on a single payload field. Exactly one of `match`, `range` and `is_empty` must be set
+#[derive(Debug, Clone, Deserialize, schemars::JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct FilterCondition {
+    #[schemars(
+        description = "The payload field to filter on. Must be one of the server's filterable fields"
+    )]
+    pub key: String,
+    #[schemars(description = "Match the field against a value, or any of a list of values")]
+    #[serde(default, rename = "match")]
+    pub match_: Option<MatchCondition>,
+    #[schemars(
+        description = "Match the field against a numeric range, or a datetime range with RFC 3339 bounds"
+    )]
+    #[serde(default)]
+    pub range: Option<RangeCondition>,
+    #[schemars(
+        description = "`true` to match points where the field is missing, null or an empty array, `false` for the opposite"
+    )]
+    #[serde(default)]
+    pub is_empty: Option<bool>,
+}
+
+/// Exactly one of `value` and `any` must be set
+#[derive(Debug, Clone, Deserialize, schemars::JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MatchCondition {
+    #[schemars(description = "The exact value the field must have")]
+    #[serde(default)]
+    pub value: Option<MatchValue>,
+    #[schemars(description = "The field must have one of these values")]
+    #[serde(default)]
+    pub any: Option<Vec<MatchValue>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(untagged)]
+pub enum MatchValue {
+    Bool(bool),
+    Integer(i64),
+    String(String),
+}
+
+/// At least one bound must be set. Bounds are either all numbers or all RFC 3339 datetimes
+#[derive(Debug, Clone, Deserialize, schemars::JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RangeCondition {
+    #[serde(default)]
+    pub gt: Option<RangeBound>,
+    #[serde(default)]
+    pub gte: Option<RangeBound>,
+    #[serde(default)]
+    pub lt: Option<RangeBound>,
+    #[serde(default)]
+    pub lte: Option<RangeBound>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(untagged)]
+pub enum RangeBound {
+    Number(f64),
+    Datetime(String),
+}